@@ -0,0 +1,350 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use clap::Parser;
+use greet::models::Greeting;
+use greet::{DbConnection, GreetError, Pool};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+/// Serve the greetings of the database of DATABASE_URL as REST API
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Address to listen on
+    #[clap(long, env = "LISTEN_ADDR", default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+}
+
+/// number of greetings listed without a limit.
+const DEFAULT_LIMIT: u32 = 100;
+
+#[derive(Deserialize)]
+struct ListParams {
+    text: Option<String>,
+    limit: Option<u32>,
+}
+
+/// body of created and updated greetings.
+#[derive(Deserialize)]
+struct GreetingBody {
+    greeting: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// GreetError returned as json with a matching status. database errors are
+/// only logged, as they may contain the database url.
+struct ApiError(GreetError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self.0 {
+            GreetError::NotFound(_) => (StatusCode::NOT_FOUND, self.0.to_string()),
+            GreetError::Pool(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "database unavailable".to_string(),
+            ),
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal server error".to_string(),
+            ),
+        };
+        if status != StatusCode::NOT_FOUND {
+            eprintln!("Error: {}", self.0);
+        }
+        let body = ErrorBody { error };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// run f with a connection of the pool on a thread which may block.
+async fn with_connection<T, F>(pool: Pool, f: F) -> Result<T, ApiError>
+where
+    F: FnOnce(&mut DbConnection) -> greet::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&mut *pool.get()?))
+        .await
+        .expect("database task panicked")
+        .map_err(ApiError)
+}
+
+async fn list(
+    State(pool): State<Pool>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Vec<Greeting>>, ApiError> {
+    let greetings = with_connection(pool, move |conn| match params.text {
+        Some(text) => greet::list_text(conn, &text),
+        None => greet::list_greetings(conn, params.limit.unwrap_or(DEFAULT_LIMIT).into()),
+    })
+    .await?;
+    Ok(Json(greetings))
+}
+
+async fn create(
+    State(pool): State<Pool>,
+    Json(body): Json<GreetingBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let greeting = with_connection(pool, move |conn| {
+        greet::create_greeting(conn, &body.greeting)
+    })
+    .await?;
+    let location = format!("/greetings/{}", greeting.id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(greeting),
+    ))
+}
+
+async fn get_greeting(
+    State(pool): State<Pool>,
+    Path(id): Path<i32>,
+) -> Result<Json<Greeting>, ApiError> {
+    let greeting = with_connection(pool, move |conn| {
+        greet::get_id(conn, id)?.ok_or(GreetError::NotFound(id))
+    })
+    .await?;
+    Ok(Json(greeting))
+}
+
+async fn update(
+    State(pool): State<Pool>,
+    Path(id): Path<i32>,
+    Json(body): Json<GreetingBody>,
+) -> Result<Json<Greeting>, ApiError> {
+    let greeting =
+        with_connection(pool, move |conn| greet::update_id(conn, id, &body.greeting)).await?;
+    Ok(Json(greeting))
+}
+
+async fn delete(State(pool): State<Pool>, Path(id): Path<i32>) -> Result<StatusCode, ApiError> {
+    with_connection(pool, move |conn| greet::delete_id(conn, id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn app(pool: Pool) -> Router {
+    Router::new()
+        .route("/greetings", get(list).post(create))
+        .route(
+            "/greetings/{id}",
+            get(get_greeting).put(update).delete(delete),
+        )
+        .with_state(pool)
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = greet::establish_pool()?;
+
+    let listener = tokio::net::TcpListener::bind(cli.listen).await?;
+    axum::serve(listener, app(pool)).await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    // print errors with their message instead of their debug representation
+    if let Err(err) = run(Cli::parse()).await {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    /// send request with an optional json body to app and return the
+    /// status, location and body of the response.
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Option<String>, Value) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .map(|location| location.to_str().unwrap().to_string());
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        // rejections of axum have plain text bodies
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+        (status, location, body)
+    }
+
+    #[tokio::test]
+    async fn test_greetings() {
+        let app = app(greet::pool(":memory:").unwrap());
+
+        for (i, text) in ["hello", "hi", "hi"].into_iter().enumerate() {
+            let id = i + 1;
+            assert_eq!(
+                send(&app, "POST", "/greetings", Some(json!({"greeting": text}))).await,
+                (
+                    StatusCode::CREATED,
+                    Some(format!("/greetings/{id}")),
+                    json!({"id": id, "greeting": text})
+                )
+            );
+        }
+
+        for (method, uri, body, status, want) in [
+            (
+                "GET",
+                "/greetings",
+                None,
+                StatusCode::OK,
+                json!([
+                    {"id": 1, "greeting": "hello"},
+                    {"id": 2, "greeting": "hi"},
+                    {"id": 3, "greeting": "hi"}
+                ]),
+            ),
+            (
+                "GET",
+                "/greetings?limit=1",
+                None,
+                StatusCode::OK,
+                json!([{"id": 1, "greeting": "hello"}]),
+            ),
+            (
+                "GET",
+                "/greetings?text=hi",
+                None,
+                StatusCode::OK,
+                json!([{"id": 2, "greeting": "hi"}, {"id": 3, "greeting": "hi"}]),
+            ),
+            (
+                "GET",
+                "/greetings/1",
+                None,
+                StatusCode::OK,
+                json!({"id": 1, "greeting": "hello"}),
+            ),
+            (
+                "PUT",
+                "/greetings/3",
+                Some(json!({"greeting": "hey"})),
+                StatusCode::OK,
+                json!({"id": 3, "greeting": "hey"}),
+            ),
+            (
+                "DELETE",
+                "/greetings/2",
+                None,
+                StatusCode::NO_CONTENT,
+                Value::Null,
+            ),
+            (
+                "GET",
+                "/greetings",
+                None,
+                StatusCode::OK,
+                json!([{"id": 1, "greeting": "hello"}, {"id": 3, "greeting": "hey"}]),
+            ),
+            (
+                "GET",
+                "/greetings/2",
+                None,
+                StatusCode::NOT_FOUND,
+                json!({"error": "no greeting with id 2"}),
+            ),
+            (
+                "PUT",
+                "/greetings/2",
+                Some(json!({"greeting": "hey"})),
+                StatusCode::NOT_FOUND,
+                json!({"error": "no greeting with id 2"}),
+            ),
+            (
+                "DELETE",
+                "/greetings/2",
+                None,
+                StatusCode::NOT_FOUND,
+                json!({"error": "no greeting with id 2"}),
+            ),
+        ] {
+            let (got_status, _, got) = send(&app, method, uri, body).await;
+            assert_eq!((got_status, got), (status, want), "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_request() {
+        let app = app(greet::pool(":memory:").unwrap());
+
+        for (method, uri, body, status) in [
+            ("GET", "/greetings/x", None, StatusCode::BAD_REQUEST),
+            ("GET", "/greetings?limit=-1", None, StatusCode::BAD_REQUEST),
+            (
+                "POST",
+                "/greetings",
+                Some(json!({"text": "hello"})),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            ("POST", "/greetings/1", None, StatusCode::METHOD_NOT_ALLOWED),
+        ] {
+            let (got, _, _) = send(&app, method, uri, body).await;
+            assert_eq!(got, status, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_error() {
+        let Err(connection) = greet::connect("file:/nonexistent/greetings.db") else {
+            panic!("connected to a nonexistent directory");
+        };
+        for (err, status, want) in [
+            (
+                GreetError::NotFound(1),
+                StatusCode::NOT_FOUND,
+                "no greeting with id 1",
+            ),
+            (
+                connection,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal server error",
+            ),
+            (
+                GreetError::Migration("no such table".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal server error",
+            ),
+        ] {
+            let response = ApiError(err).into_response();
+            assert_eq!(response.status(), status);
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(
+                serde_json::from_slice::<Value>(&bytes).unwrap(),
+                json!({ "error": want })
+            );
+        }
+    }
+}
@@ -0,0 +1,4 @@
+CREATE TABLE greetings (
+	  id SERIAL PRIMARY KEY,
+	  greeting TEXT NOT NULL
+)
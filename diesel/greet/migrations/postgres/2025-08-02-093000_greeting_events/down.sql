@@ -0,0 +1,2 @@
+DROP TABLE greeting_events;
+DROP TABLE users
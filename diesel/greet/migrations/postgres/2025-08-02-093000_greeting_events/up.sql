@@ -0,0 +1,15 @@
+CREATE TABLE users (
+	  id SERIAL PRIMARY KEY,
+	  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE greeting_events (
+	  id SERIAL PRIMARY KEY,
+	  greeting_id INTEGER NOT NULL REFERENCES greetings (id) ON DELETE CASCADE,
+	  sender_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+	  recipient_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+	  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX greeting_events_sender ON greeting_events (sender_id, greeting_id);
+CREATE INDEX greeting_events_created_at ON greeting_events (created_at);
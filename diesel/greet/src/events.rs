@@ -0,0 +1,169 @@
+use crate::models::{Greeting, GreetingEvent, NewGreetingEvent, User};
+use crate::schema::{greeting_events, greetings, users};
+use crate::{create_greeting, get_text, DbConnection, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date};
+
+// users table joined a second time for the recipients of events
+diesel::alias!(users as recipients: Recipients);
+
+diesel::allow_columns_to_appear_in_same_group_by_clause!(
+    users::id,
+    users::name,
+    greetings::id,
+    greetings::greeting,
+);
+
+/// greeting event with the names of its users and the greeting text.
+#[derive(Debug, PartialEq, Queryable)]
+pub struct HistoryEntry {
+    pub created_at: NaiveDateTime,
+    pub sender: String,
+    pub recipient: String,
+    pub greeting: String,
+}
+
+/// greeting a user sent most often.
+#[derive(Debug, PartialEq)]
+pub struct MostUsedGreeting {
+    pub user: User,
+    pub greeting: Greeting,
+    pub count: i64,
+}
+
+/// number of greetings sent on a day.
+#[derive(Debug, PartialEq, QueryableByName)]
+pub struct DayCount {
+    #[diesel(sql_type = Date)]
+    pub day: NaiveDate,
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+}
+
+/// user with name, created if it does not exist.
+pub fn user(conn: &mut DbConnection, name: &str) -> Result<User> {
+    let find = users::table
+        .filter(users::name.eq(name))
+        .select(User::as_select());
+    if let Some(user) = find.first(conn).optional()? {
+        return Ok(user);
+    }
+
+    // a user created concurrently is not inserted again
+    let insert = diesel::insert_into(users::table).values(users::name.eq(name));
+    match conn {
+        DbConnection::Postgresql(conn) => {
+            insert.on_conflict(users::name).do_nothing().execute(conn)?
+        }
+        DbConnection::Sqlite(conn) => insert.on_conflict(users::name).do_nothing().execute(conn)?,
+    };
+    Ok(find.first(conn)?)
+}
+
+/// record that sender greeted recipient with greeting at created_at, or
+/// now if it is None. users and greeting are created if they do not exist.
+pub fn record_greeting(
+    conn: &mut DbConnection,
+    sender: &str,
+    recipient: &str,
+    greeting: &str,
+    created_at: Option<NaiveDateTime>,
+) -> Result<GreetingEvent> {
+    conn.transaction(|conn| {
+        let event = NewGreetingEvent {
+            greeting_id: match get_text(conn, greeting)? {
+                Some(greeting) => greeting.id,
+                None => create_greeting(conn, greeting)?.id,
+            },
+            sender_id: user(conn, sender)?.id,
+            recipient_id: user(conn, recipient)?.id,
+            created_at,
+        };
+
+        // returning clauses are not supported for multi connections
+        let insert = diesel::insert_into(greeting_events::table).values(&event);
+        Ok(match conn {
+            DbConnection::Postgresql(conn) => insert
+                .returning(GreetingEvent::as_returning())
+                .get_result(conn)?,
+            DbConnection::Sqlite(conn) => insert
+                .returning(GreetingEvent::as_returning())
+                .get_result(conn)?,
+        })
+    })
+}
+
+/// events sent by user with their greetings, oldest first.
+pub fn sent_greetings(
+    conn: &mut DbConnection,
+    user: &User,
+) -> Result<Vec<(GreetingEvent, Greeting)>> {
+    Ok(GreetingEvent::belonging_to(user)
+        .inner_join(greetings::table)
+        .order((greeting_events::created_at, greeting_events::id))
+        .select((GreetingEvent::as_select(), Greeting::as_select()))
+        .load(conn)?)
+}
+
+/// at most limit events, latest first.
+pub fn history(conn: &mut DbConnection, limit: i64) -> Result<Vec<HistoryEntry>> {
+    Ok(greeting_events::table
+        .inner_join(greetings::table)
+        .inner_join(users::table.on(users::id.eq(greeting_events::sender_id)))
+        .inner_join(
+            recipients.on(recipients
+                .field(users::id)
+                .eq(greeting_events::recipient_id)),
+        )
+        .order((
+            greeting_events::created_at.desc(),
+            greeting_events::id.desc(),
+        ))
+        .limit(limit)
+        .select((
+            greeting_events::created_at,
+            users::name,
+            recipients.field(users::name),
+            greetings::greeting,
+        ))
+        .load(conn)?)
+}
+
+/// most used greeting of each user who sent greetings, ordered by user id.
+/// ties are won by the greeting with the lowest id.
+pub fn most_used_greetings(conn: &mut DbConnection) -> Result<Vec<MostUsedGreeting>> {
+    let counts: Vec<(User, Greeting, i64)> = greeting_events::table
+        .inner_join(greetings::table)
+        .inner_join(users::table.on(users::id.eq(greeting_events::sender_id)))
+        .group_by((users::id, greetings::id))
+        .order((users::id, count_star().desc(), greetings::id))
+        .select((User::as_select(), Greeting::as_select(), count_star()))
+        .load(conn)?;
+
+    // the first count of each user is the highest
+    let mut most_used: Vec<MostUsedGreeting> = Vec::new();
+    for (user, greeting, count) in counts {
+        if most_used.last().is_some_and(|last| last.user.id == user.id) {
+            continue;
+        }
+        most_used.push(MostUsedGreeting {
+            user,
+            greeting,
+            count,
+        });
+    }
+    Ok(most_used)
+}
+
+/// number of greetings sent on each day with greetings.
+pub fn greetings_per_day(conn: &mut DbConnection) -> Result<Vec<DayCount>> {
+    // diesel does not group by expressions, date() is a function of SQLite
+    // and a cast of PostgreSQL
+    Ok(diesel::sql_query(
+        "SELECT date(created_at) AS day, count(*) AS count FROM greeting_events \
+         GROUP BY day ORDER BY day",
+    )
+    .load(conn)?)
+}
@@ -1,80 +1,274 @@
+pub mod events;
 pub mod models;
+pub mod query;
 pub mod schema;
 
+pub use query::{GreetingQuery, Order};
+
 use diesel::prelude::*;
+use diesel::r2d2::{self, ManageConnection, R2D2Connection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use dotenvy::dotenv;
 use std::env;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// migrations of each backend, applied on startup by
+/// `establish_connection`.
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+/// connection to a PostgreSQL or SQLite database.
+#[derive(diesel::MultiConnection)]
+pub enum DbConnection {
+    Postgresql(PgConnection),
+    Sqlite(SqliteConnection),
+}
+
+/// error of the greeting api.
+#[derive(Debug)]
+pub enum GreetError {
+    /// DATABASE_URL is neither set in the environment nor in .env
+    MissingDatabaseUrl,
+    /// the database could not be opened
+    Connection {
+        database_url: String,
+        source: ConnectionError,
+    },
+    /// a migration could not be applied
+    Migration(Box<dyn Error + Send + Sync>),
+    /// no connection of the pool became available
+    Pool(r2d2::PoolError),
+    /// no greeting with id
+    NotFound(i32),
+    /// a query failed
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for GreetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GreetError::MissingDatabaseUrl => write!(f, "DATABASE_URL must be set"),
+            GreetError::Connection {
+                database_url,
+                source,
+            } => write!(f, "error connecting to {}: {}", database_url, source),
+            GreetError::Migration(err) => write!(f, "migration failed: {}", err),
+            GreetError::Pool(err) => write!(f, "no database connection: {}", err),
+            GreetError::NotFound(id) => write!(f, "no greeting with id {}", id),
+            GreetError::Query(err) => write!(f, "query failed: {}", err),
+        }
+    }
+}
+
+impl Error for GreetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GreetError::MissingDatabaseUrl | GreetError::NotFound(_) => None,
+            GreetError::Connection { source, .. } => Some(source),
+            GreetError::Migration(err) => Some(err.as_ref()),
+            GreetError::Pool(err) => Some(err),
+            GreetError::Query(err) => Some(err),
+        }
+    }
+}
+
+impl From<r2d2::PoolError> for GreetError {
+    fn from(err: r2d2::PoolError) -> Self {
+        GreetError::Pool(err)
+    }
+}
+
+impl From<diesel::result::Error> for GreetError {
+    fn from(err: diesel::result::Error) -> Self {
+        GreetError::Query(err)
+    }
+}
 
-pub fn establish_connection() -> SqliteConnection {
+pub type Result<T, E = GreetError> = std::result::Result<T, E>;
+
+/// get DATABASE_URL, which may be set in .env.
+fn database_url() -> Result<String> {
     dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    SqliteConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+    env::var("DATABASE_URL").map_err(|_| GreetError::MissingDatabaseUrl)
+}
+
+/// connect to the database of DATABASE_URL and apply pending migrations.
+pub fn establish_connection() -> Result<DbConnection> {
+    let mut conn = connect(&database_url()?)?;
+    run_migrations(&mut conn)?;
+    Ok(conn)
+}
+
+/// creates pooled connections with `connect`.
+pub struct ConnectionManager {
+    database_url: String,
+}
+
+impl ManageConnection for ConnectionManager {
+    type Connection = DbConnection;
+    type Error = GreetError;
+
+    fn connect(&self) -> Result<DbConnection> {
+        connect(&self.database_url)
+    }
+
+    fn is_valid(&self, conn: &mut DbConnection) -> Result<()> {
+        Ok(conn.ping()?)
+    }
+
+    fn has_broken(&self, conn: &mut DbConnection) -> bool {
+        conn.is_broken()
+    }
+}
+
+pub type Pool = r2d2::Pool<ConnectionManager>;
+
+/// create pool of connections to the database of database_url and apply
+/// pending migrations. each connection of an in memory SQLite database has
+/// its own database, so those pools are limited to one connection.
+pub fn pool(database_url: &str) -> Result<Pool> {
+    let manager = ConnectionManager {
+        database_url: database_url.to_string(),
+    };
+    let mut builder = r2d2::Pool::builder().connection_timeout(Duration::from_secs(5));
+    if database_url.contains(":memory:") {
+        builder = builder.max_size(1);
+    }
+    let pool = builder.build(manager)?;
+    run_migrations(&mut *pool.get()?)?;
+    Ok(pool)
+}
+
+/// create pool of connections to the database of DATABASE_URL and apply
+/// pending migrations.
+pub fn establish_pool() -> Result<Pool> {
+    pool(&database_url()?)
+}
+
+/// connect to the database of database_url, "postgres://" and
+/// "postgresql://" urls are PostgreSQL databases, all others SQLite
+/// databases like "file:greetings.db".
+pub fn connect(database_url: &str) -> Result<DbConnection> {
+    let conn =
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            PgConnection::establish(database_url).map(DbConnection::Postgresql)
+        } else {
+            SqliteConnection::establish(database_url).map(DbConnection::Sqlite)
+        };
+    let mut conn = conn.map_err(|source| GreetError::Connection {
+        database_url: database_url.to_string(),
+        source,
+    })?;
+    // wait for other connections, e.g. of a pool, instead of failing and
+    // delete the events of deleted greetings and users
+    if let DbConnection::Sqlite(conn) = &mut conn {
+        diesel::sql_query("PRAGMA busy_timeout = 5000").execute(conn)?;
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(conn)?;
+    }
+    Ok(conn)
+}
+
+/// apply pending migrations of the backend and return their versions.
+pub fn run_migrations(conn: &mut DbConnection) -> Result<Vec<String>> {
+    let versions = match conn {
+        DbConnection::Postgresql(conn) => conn.run_pending_migrations(POSTGRES_MIGRATIONS),
+        DbConnection::Sqlite(conn) => conn.run_pending_migrations(SQLITE_MIGRATIONS),
+    }
+    .map_err(GreetError::Migration)?;
+    Ok(versions.iter().map(|version| version.to_string()).collect())
 }
 
 use self::models::{Greeting, NewGreeting};
 
-pub fn create_greeting(conn: &mut SqliteConnection, greeting: &str) -> Greeting {
+pub fn create_greeting(conn: &mut DbConnection, greeting: &str) -> Result<Greeting> {
     use crate::schema::greetings;
 
     let new_greeting = NewGreeting { greeting };
 
-    diesel::insert_into(greetings::table)
-        .values(&new_greeting)
-        .returning(Greeting::as_returning())
-        .get_result(conn)
-        .expect("Error inserting new greeting")
+    // returning clauses are not supported for multi connections, so the
+    // insert runs on the connection of the backend
+    let insert = diesel::insert_into(greetings::table).values(&new_greeting);
+    Ok(match conn {
+        DbConnection::Postgresql(conn) => insert
+            .returning(Greeting::as_returning())
+            .get_result(conn)?,
+        DbConnection::Sqlite(conn) => insert
+            .returning(Greeting::as_returning())
+            .get_result(conn)?,
+    })
 }
 
-pub fn list_greetings(conn: &mut SqliteConnection) {
-    use crate::schema::greetings::dsl::greetings;
-
-    let results = greetings
-        .limit(5)
-        .select(Greeting::as_select())
-        .load(conn)
-        .expect("Error loading posts");
-    println!("Listing {} greetings:", results.len());
-    for greeting in results {
-        println!("  {} {}", greeting.id, greeting.greeting);
-    }
+/// list at most limit greetings ordered by id.
+pub fn list_greetings(conn: &mut DbConnection, limit: i64) -> Result<Vec<Greeting>> {
+    GreetingQuery::new().limit(limit).load(conn)
 }
 
-pub fn get_id(conn: &mut SqliteConnection, id: i32) {
+pub fn get_id(conn: &mut DbConnection, id: i32) -> Result<Option<Greeting>> {
     use crate::schema::greetings::dsl::greetings;
 
-    if let Ok(Some(greeting)) = greetings
+    Ok(greetings
         .find(id)
         .select(Greeting::as_select())
         .first(conn)
-        .optional()
-    {
-        println!("Got greeting with ID {id}:");
-        println!("  {} {}", greeting.id, greeting.greeting);
-    }
+        .optional()?)
 }
 
-pub fn get_text(conn: &mut SqliteConnection, greeting_text: &str) {
-    use crate::schema::greetings::dsl::greeting;
-    use crate::schema::greetings::dsl::greetings;
+/// first greeting with text.
+pub fn get_text(conn: &mut DbConnection, greeting_text: &str) -> Result<Option<Greeting>> {
+    use crate::schema::greetings::dsl::{greeting, greetings, id};
 
-    if let Ok(Some(g)) = greetings
+    Ok(greetings
         .filter(greeting.eq(greeting_text))
+        .order(id)
         .select(Greeting::as_select())
         .first(conn)
-        .optional()
-    {
-        println!("Got greeting with text \"{greeting_text}\":");
-        println!("  {} {}", g.id, g.greeting);
+        .optional()?)
+}
+
+/// greetings with text ordered by id.
+pub fn list_text(conn: &mut DbConnection, greeting_text: &str) -> Result<Vec<Greeting>> {
+    use crate::schema::greetings::dsl::{greeting, greetings, id};
+
+    Ok(greetings
+        .filter(greeting.eq(greeting_text))
+        .order(id)
+        .select(Greeting::as_select())
+        .load(conn)?)
+}
+
+/// update text of greeting and return the updated greeting.
+pub fn update_id(
+    conn: &mut DbConnection,
+    greeting_id: i32,
+    greeting_text: &str,
+) -> Result<Greeting> {
+    use crate::schema::greetings::dsl::*;
+
+    let num_updated = diesel::update(greetings.filter(id.eq(greeting_id)))
+        .set(greeting.eq(greeting_text))
+        .execute(conn)?;
+    if num_updated == 0 {
+        return Err(GreetError::NotFound(greeting_id));
     }
+    get_id(conn, greeting_id)?.ok_or(GreetError::NotFound(greeting_id))
 }
 
-pub fn delete_id(conn: &mut SqliteConnection, greeting_id: i32) {
+pub fn delete_id(conn: &mut DbConnection, greeting_id: i32) -> Result<()> {
     use crate::schema::greetings::dsl::*;
 
-    let num_deleted = diesel::delete(greetings.filter(id.eq(greeting_id)))
-        .execute(conn)
-        .expect("Error deleting greeting");
-    println!("Deleted {num_deleted} greeting with ID {greeting_id}");
+    let num_deleted = diesel::delete(greetings.filter(id.eq(greeting_id))).execute(conn)?;
+    if num_deleted == 0 {
+        return Err(GreetError::NotFound(greeting_id));
+    }
+    Ok(())
+}
+
+/// delete all greetings and return their number.
+pub fn delete_all(conn: &mut DbConnection) -> Result<usize> {
+    use crate::schema::greetings::dsl::greetings;
+
+    Ok(diesel::delete(greetings).execute(conn)?)
 }
@@ -1,70 +1,235 @@
-use diesel::prelude::*;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use greet::events::*;
+use greet::models::Greeting;
 use greet::*;
 
-fn print_usage() {
-    print! {"Usage:
-  run                   run some test commands
-  list                  list greetings
-  id <id>               get greeting by id
-  text <greeting>       get greeting by text
-"}
+/// Store greetings in the database of DATABASE_URL
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> diesel::QueryResult<()> {
-    use greet::schema::greetings::dsl::greetings;
+#[derive(Subcommand)]
+enum Command {
+    /// Run some test commands on an in memory database
+    Run,
+    /// List greetings
+    List(ListArgs),
+    /// Get greeting by id
+    Id { id: i32 },
+    /// Get greeting by text
+    Text { greeting: String },
+    /// Record that a user greeted another user
+    Log {
+        sender: String,
+        recipient: String,
+        greeting: String,
+    },
+    /// List the latest greetings of users
+    History {
+        /// Maximum number of greetings
+        #[clap(long, default_value_t = 10)]
+        limit: i64,
+    },
+    /// Print the most used greeting of each user and greetings per day
+    Stats,
+}
+
+/// column greetings are listed by.
+#[derive(Clone, Copy, ValueEnum)]
+enum OrderBy {
+    Id,
+    Text,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Maximum number of greetings
+    #[clap(long, default_value_t = 5)]
+    limit: i64,
+    /// Number of greetings to skip
+    #[clap(long)]
+    offset: Option<i64>,
+    /// List greetings after the greeting with this id in the order of the list
+    #[clap(long, value_name = "ID")]
+    after: Option<i32>,
+    /// Only greetings containing text, ignoring case
+    #[clap(long, value_name = "TEXT")]
+    contains: Option<String>,
+    /// Only greetings starting with text, ignoring case
+    #[clap(long, value_name = "TEXT")]
+    starts_with: Option<String>,
+    /// Order greetings by column
+    #[clap(long, value_enum, default_value = "id")]
+    order: OrderBy,
+    /// Order greetings descending
+    #[clap(long)]
+    desc: bool,
+    /// Print the number of greetings matching the filters
+    #[clap(long)]
+    count: bool,
+}
+
+impl ListArgs {
+    fn query(&self, connection: &mut DbConnection) -> greet::Result<GreetingQuery> {
+        let mut query = GreetingQuery::new()
+            .order(match self.order {
+                OrderBy::Id => Order::Id,
+                OrderBy::Text => Order::Text,
+            })
+            .descending(self.desc)
+            .limit(self.limit);
+        if let Some(offset) = self.offset {
+            query = query.offset(offset);
+        }
+        if let Some(id) = self.after {
+            let greeting = get_id(connection, id)?.ok_or(GreetError::NotFound(id))?;
+            query = query.after(&greeting);
+        }
+        if let Some(text) = &self.contains {
+            query = query.contains(text);
+        }
+        if let Some(text) = &self.starts_with {
+            query = query.starts_with(text);
+        }
+        Ok(query)
+    }
+}
 
-    let connection = &mut establish_connection();
+fn list(connection: &mut DbConnection, query: &GreetingQuery) -> greet::Result<()> {
+    let results = query.load(connection)?;
+    println!("Listing {} greetings:", results.len());
+    for greeting in results {
+        println!("  {} {}", greeting.id, greeting.greeting);
+    }
+    Ok(())
+}
+
+fn print_greeting(greeting: Option<Greeting>, description: &str) {
+    match greeting {
+        Some(greeting) => {
+            println!("Got greeting with {description}:");
+            println!("  {} {}", greeting.id, greeting.greeting);
+        }
+        None => println!("No greeting with {description}"),
+    }
+}
 
+/// replace greetings by the test greetings and return the last id.
+fn seed(connection: &mut DbConnection) -> greet::Result<i32> {
     // delete existing greetings
-    diesel::delete(greetings).execute(connection)?;
+    delete_all(connection)?;
 
     // create greetings
     let mut last_id = 0;
-    for g in vec!["hello", "hi", "good day", "greetings"] {
-        let greeting = create_greeting(connection, g);
+    for g in ["hello", "hi", "good day", "greetings"] {
+        let greeting = create_greeting(connection, g)?;
         println!("Created greeting {} {}", greeting.id, greeting.greeting);
         last_id = greeting.id;
     }
+    Ok(last_id)
+}
+
+fn run(cli: Cli) -> greet::Result<()> {
+    // run deletes all greetings and with them the greeting events, so it
+    // never uses the database of DATABASE_URL
+    let connection = &mut match cli.command {
+        Some(Command::Run) => {
+            let mut connection = connect(":memory:")?;
+            run_migrations(&mut connection)?;
+            connection
+        }
+        _ => establish_connection()?,
+    };
+
+    // run starts from the test greetings, the other commands only seed an
+    // empty database, so ids stay valid for paging
+    if !matches!(cli.command, Some(Command::Run)) && GreetingQuery::new().count(connection)? == 0 {
+        seed(connection)?;
+    }
+
+    match cli.command {
+        Some(Command::Run) => {
+            let last_id = seed(connection)?;
 
-    // command line arguments
-    match std::env::args().nth(1).as_deref() {
-        Some("run") => {
             // list greetings
-            list_greetings(connection);
+            list(connection, &GreetingQuery::new().limit(5))?;
 
             // get greeting by id
-            get_id(connection, last_id);
+            print_greeting(get_id(connection, last_id)?, &format!("ID {last_id}"));
 
             // get greeting by text
-            get_text(connection, "good day");
+            print_greeting(get_text(connection, "good day")?, "text \"good day\"");
 
             // delete greeting by id
-            delete_id(connection, last_id);
+            delete_id(connection, last_id)?;
+            println!("Deleted greeting with ID {last_id}");
 
             // list greetings
-            list_greetings(connection);
+            list(connection, &GreetingQuery::new().limit(5))?;
         }
-        Some("list") => {
-            list_greetings(connection);
-        }
-        Some("id") => {
-            if let Some(s) = std::env::args().nth(2)
-                && let Ok(id) = s.parse::<i32>()
-            {
-                get_id(connection, id);
+        Some(Command::List(args)) => {
+            let query = args.query(connection)?;
+            if args.count {
+                println!("Counted {} greetings", query.count(connection)?);
             } else {
-                print_usage()
+                list(connection, &query)?;
             }
         }
-        Some("text") => {
-            if let Some(text) = std::env::args().nth(2) {
-                get_text(connection, &text);
-            } else {
-                print_usage()
+        Some(Command::Id { id }) => {
+            print_greeting(get_id(connection, id)?, &format!("ID {id}"));
+        }
+        Some(Command::Text { greeting }) => {
+            print_greeting(
+                get_text(connection, &greeting)?,
+                &format!("text \"{greeting}\""),
+            );
+        }
+        Some(Command::Log {
+            sender,
+            recipient,
+            greeting,
+        }) => {
+            let event = record_greeting(connection, &sender, &recipient, &greeting, None)?;
+            println!(
+                "Recorded {sender} greeting {recipient} with \"{greeting}\" at {}",
+                event.created_at
+            );
+        }
+        Some(Command::History { limit }) => {
+            let entries = history(connection, limit)?;
+            println!("Listing {} greetings of users:", entries.len());
+            for entry in entries {
+                println!(
+                    "  {} {} -> {}: {}",
+                    entry.created_at, entry.sender, entry.recipient, entry.greeting
+                );
             }
         }
-        _ => print_usage(),
+        Some(Command::Stats) => {
+            println!("Most used greetings:");
+            for most_used in most_used_greetings(connection)? {
+                println!(
+                    "  {} {} ({} times)",
+                    most_used.user.name, most_used.greeting.greeting, most_used.count
+                );
+            }
+            println!("Greetings per day:");
+            for day in greetings_per_day(connection)? {
+                println!("  {} {}", day.day, day.count);
+            }
+        }
+        None => print!("{}", Cli::command().render_help()),
     }
 
     Ok(())
 }
+
+fn main() {
+    // print errors with their message instead of their debug representation
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+}
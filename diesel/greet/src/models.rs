@@ -1,8 +1,10 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use serde::Serialize;
 
-#[derive(Queryable, Selectable)]
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Serialize)]
 #[diesel(table_name = crate::schema::greetings)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
 pub struct Greeting {
     pub id: i32,
     pub greeting: String,
@@ -15,3 +17,37 @@ use crate::schema::greetings;
 pub struct NewGreeting<'a> {
     pub greeting: &'a str,
 }
+
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Serialize)]
+#[diesel(table_name = crate::schema::users)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+}
+
+/// sender greeted recipient with greeting at created_at.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Serialize)]
+#[diesel(belongs_to(Greeting))]
+#[diesel(belongs_to(User, foreign_key = sender_id))]
+#[diesel(table_name = crate::schema::greeting_events)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
+pub struct GreetingEvent {
+    pub id: i32,
+    pub greeting_id: i32,
+    pub sender_id: i32,
+    pub recipient_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+use crate::schema::greeting_events;
+
+/// event to record, created_at defaults to the current time.
+#[derive(Insertable)]
+#[diesel(table_name = greeting_events)]
+pub struct NewGreetingEvent {
+    pub greeting_id: i32,
+    pub sender_id: i32,
+    pub recipient_id: i32,
+    pub created_at: Option<NaiveDateTime>,
+}
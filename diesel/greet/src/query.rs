@@ -0,0 +1,170 @@
+use crate::models::Greeting;
+use crate::schema::greetings;
+use crate::{DbConnection, MultiBackend, Result};
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+#[diesel::declare_sql_function]
+extern "SQL" {
+    /// lowercase text, only ASCII characters are converted by SQLite.
+    fn lower(text: Text) -> Text;
+}
+
+/// column greetings are ordered by.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Order {
+    #[default]
+    Id,
+    Text,
+}
+
+/// query of greetings, built like
+/// `GreetingQuery::new().contains("hi").order(Order::Text).limit(10)`.
+/// filters are case-insensitive and ties of the text order are ordered
+/// by id, so pages are stable.
+#[derive(Clone, Debug, Default)]
+pub struct GreetingQuery {
+    contains: Option<String>,
+    starts_with: Option<String>,
+    order: Order,
+    descending: bool,
+    after: Option<(i32, String)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// escape LIKE wildcards of text with backslashes.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl GreetingQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// only greetings containing text.
+    pub fn contains(mut self, text: &str) -> Self {
+        self.contains = Some(text.to_string());
+        self
+    }
+
+    /// only greetings starting with text.
+    pub fn starts_with(mut self, text: &str) -> Self {
+        self.starts_with = Some(text.to_string());
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    /// only greetings after greeting in the order of the query, e.g. the
+    /// last greeting of the previous page.
+    pub fn after(mut self, greeting: &Greeting) -> Self {
+        self.after = Some((greeting.id, greeting.greeting.clone()));
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// greetings matching the filters. greetings and patterns are lowercased
+    /// by the database, so both are folded the same way, which is only for
+    /// ASCII letters with SQLite.
+    fn filtered(&self) -> greetings::BoxedQuery<'static, MultiBackend> {
+        use crate::schema::greetings::dsl::*;
+
+        let mut query = greetings.into_boxed();
+        if let Some(text) = &self.contains {
+            let pattern = format!("%{}%", escape_like(text));
+            query = query.filter(lower(greeting).like(lower(pattern)).escape('\\'));
+        }
+        if let Some(text) = &self.starts_with {
+            let pattern = format!("{}%", escape_like(text));
+            query = query.filter(lower(greeting).like(lower(pattern)).escape('\\'));
+        }
+        query
+    }
+
+    /// load the greetings of the query.
+    pub fn load(&self, conn: &mut DbConnection) -> Result<Vec<Greeting>> {
+        use crate::schema::greetings::dsl::*;
+
+        let mut query = self.filtered();
+        if let Some((after_id, after_text)) = self.after.clone() {
+            query = match (self.order, self.descending) {
+                (Order::Id, false) => query.filter(id.gt(after_id)),
+                (Order::Id, true) => query.filter(id.lt(after_id)),
+                (Order::Text, false) => query.filter(
+                    greeting
+                        .gt(after_text.clone())
+                        .or(greeting.eq(after_text).and(id.gt(after_id))),
+                ),
+                (Order::Text, true) => query.filter(
+                    greeting
+                        .lt(after_text.clone())
+                        .or(greeting.eq(after_text).and(id.lt(after_id))),
+                ),
+            };
+        }
+        query = match (self.order, self.descending) {
+            (Order::Id, false) => query.order(id.asc()),
+            (Order::Id, true) => query.order(id.desc()),
+            (Order::Text, false) => query.order((greeting.asc(), id.asc())),
+            (Order::Text, true) => query.order((greeting.desc(), id.desc())),
+        };
+        // SQLite does not support an offset without a limit
+        if let Some(limit) = self.limit.or(self.offset.map(|_| i64::MAX)) {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = self.offset {
+            query = query.offset(offset);
+        }
+        Ok(query.select(Greeting::as_select()).load(conn)?)
+    }
+
+    /// number of greetings matching the filters, regardless of the
+    /// pagination.
+    pub fn count(&self, conn: &mut DbConnection) -> Result<i64> {
+        Ok(self.filtered().count().get_result(conn)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        for (text, want) in [
+            ("Hello", "Hello"),
+            ("Über", "Über"),
+            ("100%", "100\\%"),
+            ("a_b", "a\\_b"),
+            ("C:\\", "C:\\\\"),
+        ] {
+            assert_eq!(escape_like(text), want, "{}", text);
+        }
+    }
+}
@@ -1,8 +1,29 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    greeting_events (id) {
+        id -> Integer,
+        greeting_id -> Integer,
+        sender_id -> Integer,
+        recipient_id -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     greetings (id) {
         id -> Integer,
         greeting -> Text,
     }
 }
+
+diesel::table! {
+    users (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+diesel::joinable!(greeting_events -> greetings (greeting_id));
+
+diesel::allow_tables_to_appear_in_same_query!(greeting_events, greetings, users,);
@@ -0,0 +1,351 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::Connection;
+use greet::events::*;
+use greet::models::Greeting;
+use greet::*;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+
+/// connect to a new database in a temporary file and apply the migrations.
+fn setup(name: &str) -> (DbConnection, PathBuf) {
+    let path = env::temp_dir().join(format!("diesel-greet-{}-{}.db", name, process::id()));
+    let _ = fs::remove_file(&path);
+    let mut conn = connect(&format!("file:{}", path.display())).unwrap();
+    assert_eq!(
+        run_migrations(&mut conn).unwrap(),
+        ["20250706212833", "20250802093000"]
+    );
+    assert!(run_migrations(&mut conn).unwrap().is_empty());
+    (conn, path)
+}
+
+fn greeting(id: i32, greeting: &str) -> Greeting {
+    Greeting {
+        id,
+        greeting: greeting.to_string(),
+    }
+}
+
+/// exercise the api on an empty greetings table. ids are relative to the
+/// first id, as sequences of PostgreSQL are not reset.
+fn check_greetings(conn: &mut DbConnection) {
+    let mut ids = Vec::new();
+    for text in ["hello", "hi", "good day", "hi"] {
+        let created = create_greeting(conn, text).unwrap();
+        assert_eq!(created.greeting, text);
+        ids.push(created.id);
+    }
+    let first = ids[0];
+    assert_eq!(ids, [first, first + 1, first + 2, first + 3]);
+
+    assert_eq!(
+        list_greetings(conn, 5).unwrap(),
+        [
+            greeting(first, "hello"),
+            greeting(first + 1, "hi"),
+            greeting(first + 2, "good day"),
+            greeting(first + 3, "hi")
+        ]
+    );
+    assert_eq!(list_greetings(conn, 2).unwrap().len(), 2);
+
+    assert_eq!(
+        get_id(conn, first + 2).unwrap(),
+        Some(greeting(first + 2, "good day"))
+    );
+    assert_eq!(get_id(conn, first + 4).unwrap(), None);
+    assert_eq!(
+        get_text(conn, "hi").unwrap(),
+        Some(greeting(first + 1, "hi"))
+    );
+    assert_eq!(get_text(conn, "hey").unwrap(), None);
+    assert_eq!(
+        list_text(conn, "hi").unwrap(),
+        [greeting(first + 1, "hi"), greeting(first + 3, "hi")]
+    );
+    assert!(list_text(conn, "hey").unwrap().is_empty());
+
+    assert_eq!(
+        update_id(conn, first + 3, "hey").unwrap(),
+        greeting(first + 3, "hey")
+    );
+    assert!(matches!(
+        update_id(conn, first + 4, "hey"),
+        Err(GreetError::NotFound(id)) if id == first + 4
+    ));
+    assert_eq!(update_id(conn, first + 3, "hi").unwrap().greeting, "hi");
+
+    delete_id(conn, first + 1).unwrap();
+    assert!(matches!(
+        delete_id(conn, first + 1),
+        Err(GreetError::NotFound(id)) if id == first + 1
+    ));
+    assert_eq!(
+        get_text(conn, "hi").unwrap(),
+        Some(greeting(first + 3, "hi"))
+    );
+
+    assert_eq!(delete_all(conn).unwrap(), 3);
+    assert!(list_greetings(conn, 5).unwrap().is_empty());
+}
+
+/// run queries on an empty greetings table.
+fn check_query(conn: &mut DbConnection) {
+    let mut all = Vec::new();
+    for text in ["hi", "hello", "Good day", "hi", "hi there", "50% off_"] {
+        all.push(create_greeting(conn, text).unwrap());
+    }
+    let ids = |greetings: Vec<Greeting>| -> Vec<i32> {
+        greetings.iter().map(|g| g.id - all[0].id).collect()
+    };
+
+    for (query, want, count) in [
+        (GreetingQuery::new(), vec![0, 1, 2, 3, 4, 5], 6),
+        (GreetingQuery::new().limit(2).offset(1), vec![1, 2], 6),
+        (GreetingQuery::new().offset(4), vec![4, 5], 6),
+        (
+            GreetingQuery::new().descending(true).limit(2),
+            vec![5, 4],
+            6,
+        ),
+        (GreetingQuery::new().after(&all[3]), vec![4, 5], 6),
+        (
+            GreetingQuery::new().descending(true).after(&all[3]),
+            vec![2, 1, 0],
+            6,
+        ),
+        (
+            GreetingQuery::new().order(Order::Text),
+            vec![5, 2, 1, 0, 3, 4],
+            6,
+        ),
+        (
+            GreetingQuery::new().order(Order::Text).after(&all[0]),
+            vec![3, 4],
+            6,
+        ),
+        (
+            GreetingQuery::new()
+                .order(Order::Text)
+                .descending(true)
+                .after(&all[3])
+                .limit(2),
+            vec![0, 1],
+            6,
+        ),
+        (GreetingQuery::new().contains("HI"), vec![0, 3, 4], 3),
+        (GreetingQuery::new().contains("D"), vec![2], 1),
+        (GreetingQuery::new().starts_with("h"), vec![0, 1, 3, 4], 4),
+        (GreetingQuery::new().starts_with("good"), vec![2], 1),
+        (
+            GreetingQuery::new().starts_with("h").contains("e"),
+            vec![1, 4],
+            2,
+        ),
+        // wildcards match literally
+        (GreetingQuery::new().contains("%"), vec![5], 1),
+        (GreetingQuery::new().contains("f_"), vec![5], 1),
+        (GreetingQuery::new().starts_with("_"), vec![], 0),
+        (
+            GreetingQuery::new()
+                .starts_with("hi")
+                .limit(1)
+                .after(&all[0]),
+            vec![3],
+            3,
+        ),
+    ] {
+        assert_eq!(ids(query.load(conn).unwrap()), want, "{:?}", query);
+        assert_eq!(query.count(conn).unwrap(), count, "{:?}", query);
+    }
+
+    // greetings and filters are folded alike, non-ASCII letters keep their
+    // case with SQLite
+    let umlaut = create_greeting(conn, "Über").unwrap();
+    for query in [
+        GreetingQuery::new().contains("Über"),
+        GreetingQuery::new().contains("ÜBER"),
+        GreetingQuery::new().starts_with("Üb"),
+    ] {
+        assert_eq!(
+            query.load(conn).unwrap(),
+            [greeting(umlaut.id, "Über")],
+            "{:?}",
+            query
+        );
+    }
+    delete_all(conn).unwrap();
+}
+
+fn at(timestamp: &str) -> Option<NaiveDateTime> {
+    Some(NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").unwrap())
+}
+
+fn day(date: &str, count: i64) -> DayCount {
+    DayCount {
+        day: date.parse::<NaiveDate>().unwrap(),
+        count,
+    }
+}
+
+/// record greeting events on empty tables and report on them.
+fn check_events(conn: &mut DbConnection) {
+    let hello = create_greeting(conn, "hello").unwrap();
+    for (sender, recipient, text, created_at) in [
+        ("alice", "bob", "hello", "2025-08-01 09:00:00"),
+        ("bob", "alice", "hi", "2025-08-01 09:01:00"),
+        ("alice", "carol", "hi", "2025-08-01 23:59:59"),
+        ("alice", "bob", "hi", "2025-08-02 08:00:00"),
+        ("carol", "alice", "hello", "2025-08-03 12:00:00"),
+        ("carol", "bob", "good day", "2025-08-03 12:30:00"),
+    ] {
+        let event = record_greeting(conn, sender, recipient, text, at(created_at)).unwrap();
+        assert_eq!(event.created_at, at(created_at).unwrap());
+    }
+    // users and greetings are only created once
+    let alice = user(conn, "alice").unwrap();
+    let bob = user(conn, "bob").unwrap();
+    let carol = user(conn, "carol").unwrap();
+    assert_eq!(user(conn, "alice").unwrap(), alice);
+    let hi = get_text(conn, "hi").unwrap().unwrap();
+    assert_eq!(list_text(conn, "hello").unwrap().len(), 1);
+
+    let sent = sent_greetings(conn, &alice).unwrap();
+    assert_eq!(
+        sent.iter()
+            .map(|(event, greeting)| (event.recipient_id, greeting.greeting.as_str()))
+            .collect::<Vec<_>>(),
+        [(bob.id, "hello"), (carol.id, "hi"), (bob.id, "hi")]
+    );
+
+    assert_eq!(
+        history(conn, 2).unwrap(),
+        [
+            HistoryEntry {
+                created_at: at("2025-08-03 12:30:00").unwrap(),
+                sender: "carol".to_string(),
+                recipient: "bob".to_string(),
+                greeting: "good day".to_string(),
+            },
+            HistoryEntry {
+                created_at: at("2025-08-03 12:00:00").unwrap(),
+                sender: "carol".to_string(),
+                recipient: "alice".to_string(),
+                greeting: "hello".to_string(),
+            },
+        ]
+    );
+
+    let most_used = most_used_greetings(conn).unwrap();
+    assert_eq!(
+        most_used
+            .iter()
+            .map(|m| (m.user.name.as_str(), m.greeting.id, m.count))
+            .collect::<Vec<_>>(),
+        [
+            ("alice", hi.id, 2),
+            ("bob", hi.id, 1),
+            // ties are won by the oldest greeting
+            ("carol", hello.id, 1),
+        ]
+    );
+
+    assert_eq!(
+        greetings_per_day(conn).unwrap(),
+        [
+            day("2025-08-01", 3),
+            day("2025-08-02", 1),
+            day("2025-08-03", 2)
+        ]
+    );
+
+    // events of deleted greetings are deleted
+    delete_id(conn, hi.id).unwrap();
+    assert_eq!(history(conn, 10).unwrap().len(), 3);
+    delete_all(conn).unwrap();
+    assert!(history(conn, 10).unwrap().is_empty());
+}
+
+#[test]
+fn test_sqlite() {
+    let (mut conn, path) = setup("greetings");
+    assert!(matches!(conn, DbConnection::Sqlite(_)));
+    check_greetings(&mut conn);
+    check_query(&mut conn);
+    check_events(&mut conn);
+    drop(conn);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_pool() {
+    let path = env::temp_dir().join(format!("diesel-greet-pool-{}.db", process::id()));
+    let _ = fs::remove_file(&path);
+    let pool = pool(&format!("file:{}", path.display())).unwrap();
+
+    // pooled connections are migrated and wait for each other
+    thread::scope(|s| {
+        for i in 0..8 {
+            let pool = &pool;
+            s.spawn(move || {
+                let mut conn = pool.get().unwrap();
+                create_greeting(&mut conn, &format!("hello {}", i)).unwrap();
+            });
+        }
+    });
+    let mut conn = pool.get().unwrap();
+    assert_eq!(list_greetings(&mut conn, 10).unwrap().len(), 8);
+    drop(conn);
+    drop(pool);
+    fs::remove_file(&path).unwrap();
+
+    // in memory databases are shared by limiting the pool to one connection
+    let pool = greet::pool(":memory:").unwrap();
+    assert_eq!(pool.max_size(), 1);
+    create_greeting(&mut pool.get().unwrap(), "hello").unwrap();
+    assert_eq!(
+        list_greetings(&mut pool.get().unwrap(), 10).unwrap().len(),
+        1
+    );
+}
+
+/// runs against the PostgreSQL database of GREET_TEST_POSTGRES_URL like
+/// "postgres://localhost/greet_test" if it is set. changes are rolled back.
+#[test]
+fn test_postgres() {
+    let Ok(url) = env::var("GREET_TEST_POSTGRES_URL") else {
+        eprintln!("GREET_TEST_POSTGRES_URL is not set, skipping");
+        return;
+    };
+    let mut conn = connect(&url).unwrap();
+    assert!(matches!(conn, DbConnection::Postgresql(_)));
+    conn.begin_test_transaction().unwrap();
+    run_migrations(&mut conn).unwrap();
+    delete_all(&mut conn).unwrap();
+    check_greetings(&mut conn);
+    check_query(&mut conn);
+    check_events(&mut conn);
+}
+
+#[test]
+fn test_errors() {
+    let err = connect("file:/nonexistent/greetings.db").err().unwrap();
+    assert!(matches!(err, GreetError::Connection { .. }), "{:?}", err);
+    assert!(
+        err.to_string()
+            .starts_with("error connecting to file:/nonexistent/greetings.db: "),
+        "{}",
+        err
+    );
+    let err = connect("postgres://localhost:1/greet").err().unwrap();
+    assert!(matches!(err, GreetError::Connection { .. }), "{:?}", err);
+
+    // queries fail without migrations
+    let mut conn = connect(":memory:").unwrap();
+    let err = create_greeting(&mut conn, "hello").unwrap_err();
+    assert!(matches!(err, GreetError::Query(_)), "{:?}", err);
+    assert_eq!(err.to_string(), "query failed: no such table: greetings");
+}
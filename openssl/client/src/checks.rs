@@ -0,0 +1,613 @@
+use crate::{hex, http};
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{
+    OcspCertId, OcspCertStatus, OcspFlag, OcspRequest, OcspResponse, OcspResponseStatus,
+    OcspRevokedStatus,
+};
+use openssl::ssl::SslRef;
+use openssl::stack::Stack;
+use openssl::x509::store::X509Store;
+use openssl::x509::{CrlStatus, X509Crl, X509};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::ParsedExtension;
+use x509_parser::prelude::FromDer;
+
+/// allowed clock skew in seconds when checking the validity of ocsp
+/// responses.
+const MAX_CLOCK_SKEW: u32 = 300;
+
+/// oid of the embedded signed certificate timestamp list extension.
+const SCT_OID: &str = "1.3.6.1.4.1.11129.2.4.2";
+
+/// outcome of a check.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// check could not be done, e.g. because the certificate has no ocsp
+    /// responder
+    Warning,
+    Revoked,
+    Error,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Revoked => "revoked",
+            Status::Error => "error",
+        })
+    }
+}
+
+/// result of a revocation or certificate transparency check.
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    fn from_result(name: impl Into<String>, result: Result<(Status, String), String>) -> Self {
+        match result {
+            Ok((status, detail)) => Check::new(name, status, detail),
+            Err(err) => Check::new(name, Status::Error, err),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.name, self.status, self.detail)
+    }
+}
+
+fn reason(reason: OcspRevokedStatus) -> &'static str {
+    match reason {
+        OcspRevokedStatus::UNSPECIFIED => "unspecified",
+        OcspRevokedStatus::KEY_COMPROMISE => "key compromise",
+        OcspRevokedStatus::CA_COMPROMISE => "ca compromise",
+        OcspRevokedStatus::AFFILIATION_CHANGED => "affiliation changed",
+        OcspRevokedStatus::STATUS_SUPERSEDED => "superseded",
+        OcspRevokedStatus::STATUS_CESSATION_OF_OPERATION => "cessation of operation",
+        OcspRevokedStatus::STATUS_CERTIFICATE_HOLD => "certificate hold",
+        OcspRevokedStatus::REMOVE_FROM_CRL => "remove from crl",
+        _ => "no reason",
+    }
+}
+
+/// peer certificate with its issuer, the chain sent by the server and the
+/// trust store used to verify ocsp responses.
+pub struct Checker {
+    pub cert: X509,
+    pub issuer: X509,
+    pub chain: Stack<X509>,
+    pub store: X509Store,
+}
+
+impl Checker {
+    /// create checker for the verified peer certificate of a connection.
+    pub fn from_ssl(ssl: &SslRef, store: X509Store) -> Result<Self, Box<dyn Error>> {
+        let verified = ssl
+            .verified_chain()
+            .ok_or("could not get verified certificate chain")?;
+        let cert = verified.get(0).ok_or("empty certificate chain")?;
+        // a trusted self-signed certificate is its own issuer
+        let issuer = verified.get(1).unwrap_or(cert);
+        let mut chain = Stack::new()?;
+        for cert in verified {
+            chain.push(cert.to_owned())?;
+        }
+        Ok(Checker {
+            cert: cert.to_owned(),
+            issuer: issuer.to_owned(),
+            chain,
+            store,
+        })
+    }
+
+    /// check der encoded ocsp response for the certificate.
+    fn ocsp_response(&self, der: &[u8]) -> Result<(Status, String), String> {
+        let response =
+            OcspResponse::from_der(der).map_err(|err| format!("invalid response: {}", err))?;
+        if response.status() != OcspResponseStatus::SUCCESSFUL {
+            return Err(format!(
+                "responder returned status {}",
+                response.status().as_raw()
+            ));
+        }
+        let basic = response
+            .basic()
+            .map_err(|err| format!("invalid response: {}", err))?;
+        basic
+            .verify(&self.chain, &self.store, OcspFlag::empty())
+            .map_err(|err| format!("invalid signature: {}", err))?;
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), &self.cert, &self.issuer)
+            .map_err(|err| err.to_string())?;
+        let status = basic
+            .find_status(&id)
+            .ok_or("no status for the certificate")?;
+        status
+            .check_validity(MAX_CLOCK_SKEW, None)
+            .map_err(|err| format!("response is not valid now: {}", err))?;
+        match status.status {
+            OcspCertStatus::GOOD => Ok((
+                Status::Ok,
+                match status.next_update() {
+                    Some(next_update) => format!("good until {}", next_update),
+                    None => "good".into(),
+                },
+            )),
+            OcspCertStatus::REVOKED => Ok((
+                Status::Revoked,
+                format!(
+                    "revoked at {}, {}",
+                    status
+                        .revocation_time
+                        .map_or("unknown time".into(), |time| time.to_string()),
+                    reason(status.reason)
+                ),
+            )),
+            _ => Err("certificate is unknown to the responder".into()),
+        }
+    }
+
+    /// check ocsp response stapled by the server.
+    pub fn stapled(&self, response: Option<&[u8]>) -> Check {
+        let name = "ocsp stapling";
+        match response {
+            Some(der) => Check::from_result(name, self.ocsp_response(der)),
+            None => Check::new(name, Status::Warning, "no response stapled"),
+        }
+    }
+
+    /// query an ocsp responder for the certificate.
+    fn query(&self, url: &str) -> Result<(Status, String), String> {
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), &self.cert, &self.issuer)
+            .map_err(|err| err.to_string())?;
+        let mut request = OcspRequest::new().map_err(|err| err.to_string())?;
+        request.add_id(id).map_err(|err| err.to_string())?;
+        let request = request.to_der().map_err(|err| err.to_string())?;
+        let response = http::request(url, Some(("application/ocsp-request", &request)))
+            .map_err(|err| err.to_string())?;
+        self.ocsp_response(&response)
+    }
+
+    /// query the ocsp responders of the certificate directly.
+    pub fn ocsp(&self) -> Vec<Check> {
+        let responders: Vec<String> = match self.cert.ocsp_responders() {
+            Ok(responders) => responders.iter().map(|url| url.to_string()).collect(),
+            Err(err) if !err.errors().is_empty() => {
+                return vec![Check::new("ocsp", Status::Error, err.to_string())];
+            }
+            // missing responders are reported without error details
+            Err(_) => Vec::new(),
+        };
+        if responders.is_empty() {
+            return vec![Check::new(
+                "ocsp",
+                Status::Warning,
+                "no responder in certificate",
+            )];
+        }
+        responders
+            .iter()
+            .map(|url| Check::from_result(format!("ocsp {}", url), self.query(url)))
+            .collect()
+    }
+
+    /// check der or pem encoded crl for the certificate.
+    fn crl_status(&self, data: &[u8]) -> Result<(Status, String), String> {
+        let crl = X509Crl::from_der(data)
+            .or_else(|_| X509Crl::from_pem(data))
+            .map_err(|err| format!("invalid crl: {}", err))?;
+        let key = self.issuer.public_key().map_err(|err| err.to_string())?;
+        let issuer = crl
+            .issuer_name()
+            .try_cmp(self.issuer.subject_name())
+            .map_err(|err| err.to_string())?;
+        if issuer != Ordering::Equal || !crl.verify(&key).map_err(|err| err.to_string())? {
+            return Err("crl is not signed by the issuer".into());
+        }
+        let now = Asn1Time::days_from_now(0).map_err(|err| err.to_string())?;
+        if let Some(next_update) = crl.next_update()
+            && next_update < now
+        {
+            return Err(format!("crl expired at {}", next_update));
+        }
+        match crl.get_by_cert(&self.cert) {
+            CrlStatus::Revoked(entry) => Ok((
+                Status::Revoked,
+                format!("revoked at {}", entry.revocation_date()),
+            )),
+            CrlStatus::NotRevoked | CrlStatus::RemoveFromCrl(_) => Ok((
+                Status::Ok,
+                match crl.next_update() {
+                    Some(next_update) => format!("not revoked, next update {}", next_update),
+                    None => "not revoked".into(),
+                },
+            )),
+        }
+    }
+
+    /// download the crls of the distribution points of the certificate and
+    /// check them.
+    pub fn crl(&self) -> Vec<Check> {
+        let mut urls = Vec::new();
+        for point in self.cert.crl_distribution_points().iter().flatten() {
+            let names = point.distpoint().and_then(|name| name.fullname());
+            for name in names.into_iter().flatten() {
+                if let Some(uri) = name.uri() {
+                    urls.push(uri.to_string());
+                }
+            }
+        }
+        if urls.is_empty() {
+            return vec![Check::new(
+                "crl",
+                Status::Warning,
+                "no distribution point in certificate",
+            )];
+        }
+        urls.iter()
+            .map(|url| {
+                let result = http::request(url, None)
+                    .map_err(|err| err.to_string())
+                    .and_then(|data| self.crl_status(&data));
+                Check::from_result(format!("crl {}", url), result)
+            })
+            .collect()
+    }
+
+    /// report signed certificate timestamps embedded in the certificate. the
+    /// signatures are not verified, as that needs the keys of the logs.
+    pub fn sct(&self) -> Vec<Check> {
+        let der = match self.cert.to_der() {
+            Ok(der) => der,
+            Err(err) => return vec![Check::new("sct", Status::Error, err.to_string())],
+        };
+        let cert = match X509Certificate::from_der(&der) {
+            Ok((_, cert)) => cert,
+            Err(err) => return vec![Check::new("sct", Status::Error, err.to_string())],
+        };
+        let mut checks = Vec::new();
+        for ext in cert.extensions() {
+            match ext.parsed_extension() {
+                ParsedExtension::SCT(scts) => {
+                    for sct in scts {
+                        let time = Asn1Time::from_unix((sct.timestamp / 1000) as _)
+                            .map_or_else(|err| err.to_string(), |time| time.to_string());
+                        checks.push(Check::new(
+                            "sct",
+                            Status::Ok,
+                            format!("log {} at {}", hex(sct.id.key_id), time),
+                        ));
+                    }
+                }
+                ParsedExtension::ParseError { error } if ext.oid.to_id_string() == SCT_OID => {
+                    checks.push(Check::new("sct", Status::Error, error.to_string()));
+                }
+                _ => {}
+            }
+        }
+        if checks.is_empty() {
+            checks.push(Check::new("sct", Status::Warning, "no embedded timestamps"));
+        }
+        checks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use openssl::x509::store::X509StoreBuilder;
+    use rcgen::{
+        date_time_ymd, CertificateParams, CertificateRevocationListParams, CertifiedIssuer,
+        CrlDistributionPoint, CustomExtension, IsCa, Issuer, KeyIdMethod, KeyPair,
+        RevokedCertParams, SerialNumber,
+    };
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const SERIAL: &[u8] = &[0x12, 0x34];
+    const LOG_ID: [u8; 32] = [0xab; 32];
+
+    /// encode der tag, length and contents.
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        let len = contents.len().to_be_bytes();
+        let len = &len[len.iter().position(|&b| b != 0).unwrap_or(len.len() - 1)..];
+        if contents.len() >= 0x80 {
+            out.push(0x80 | len.len() as u8);
+        }
+        out.extend(len);
+        out.extend(contents);
+        out
+    }
+
+    /// start http server on a random local port serving the bodies of the
+    /// routes by path.
+    fn server(routes: Arc<Mutex<HashMap<String, Vec<u8>>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                let mut reader = BufReader::new(sock.unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                let mut len = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        len = value.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                reader.read_exact(&mut vec![0; len]).unwrap();
+                let mut sock = reader.into_inner();
+                match routes.lock().unwrap().get(&path) {
+                    Some(body) => {
+                        sock.write_all(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+                        // clients may close the connection of large bodies
+                        let _ = sock.write_all(body);
+                    }
+                    None => sock.write_all(b"HTTP/1.0 404 Not Found\r\n\r\n").unwrap(),
+                }
+            }
+        });
+        port
+    }
+
+    /// certificate authority.
+    struct Ca {
+        issuer: CertifiedIssuer<'static, KeyPair>,
+        cert: X509,
+    }
+
+    fn ca() -> Ca {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let issuer = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        let cert = X509::from_der(issuer.der()).unwrap();
+        Ca { issuer, cert }
+    }
+
+    /// create checker for a certificate issued by ca with crl distribution
+    /// point, ocsp responder and optionally an embedded sct on port.
+    fn checker(ca: &Ca, port: u16, sct: bool) -> Checker {
+        let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+        params.serial_number = Some(SerialNumber::from_slice(SERIAL));
+        params.crl_distribution_points = vec![CrlDistributionPoint {
+            uris: vec![format!("http://127.0.0.1:{}/crl", port)],
+        }];
+        // authority information access with ocsp responder
+        let url = format!("http://127.0.0.1:{}/ocsp", port);
+        let mut access = tlv(0x06, &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01]);
+        access.extend(tlv(0x86, url.as_bytes()));
+        params.custom_extensions = vec![CustomExtension::from_oid_content(
+            &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+            tlv(0x30, &tlv(0x30, &access)),
+        )];
+        if sct {
+            // version, log id, timestamp, extensions and signature
+            let mut sct = vec![0];
+            sct.extend(LOG_ID);
+            sct.extend(1_700_000_000_000u64.to_be_bytes());
+            sct.extend([0, 0, 4, 3, 0, 2, 0x30, 0]);
+            let mut list = ((sct.len() + 2) as u16).to_be_bytes().to_vec();
+            list.extend((sct.len() as u16).to_be_bytes());
+            list.extend(sct);
+            params
+                .custom_extensions
+                .push(CustomExtension::from_oid_content(
+                    &[1, 3, 6, 1, 4, 1, 11129, 2, 4, 2],
+                    tlv(0x04, &list),
+                ));
+        }
+        let key = KeyPair::generate().unwrap();
+        let cert = X509::from_der(params.signed_by(&key, &ca.issuer).unwrap().der()).unwrap();
+
+        let mut chain = Stack::new().unwrap();
+        chain.push(cert.clone()).unwrap();
+        chain.push(ca.cert.clone()).unwrap();
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca.cert.clone()).unwrap();
+        Checker {
+            cert,
+            issuer: ca.cert.clone(),
+            chain,
+            store: store.build(),
+        }
+    }
+
+    /// create ocsp response of ca for the test certificate with cert status
+    /// and next update, signed with key.
+    fn ocsp_response(ca: &Ca, status: &[u8], next_update: &str, key: &KeyPair) -> Vec<u8> {
+        let sha1 = |data: &[u8]| openssl::sha::sha1(data);
+        let key_hash = sha1(ca.issuer.key().public_key_raw());
+
+        let mut cert_id = tlv(
+            0x30,
+            &[0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00],
+        );
+        cert_id.extend(tlv(0x04, &sha1(&ca.cert.subject_name().to_der().unwrap())));
+        cert_id.extend(tlv(0x04, &key_hash));
+        cert_id.extend(tlv(0x02, SERIAL));
+        let mut single = tlv(0x30, &cert_id);
+        single.extend(status);
+        single.extend(tlv(0x18, b"20240101000000Z"));
+        single.extend(tlv(0xa0, &tlv(0x18, next_update.as_bytes())));
+
+        let mut data = tlv(0xa2, &tlv(0x04, &key_hash));
+        data.extend(tlv(0x18, b"20240101000000Z"));
+        data.extend(tlv(0x30, &tlv(0x30, &single)));
+        let data = tlv(0x30, &data);
+
+        let pkey = PKey::private_key_from_pkcs8(&key.serialize_der()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey).unwrap();
+        let mut signature = vec![0];
+        signature.extend(signer.sign_oneshot_to_vec(&data).unwrap());
+        let mut basic = data;
+        // ecdsa with sha-256
+        basic.extend(tlv(
+            0x30,
+            &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02],
+        ));
+        basic.extend(tlv(0x03, &signature));
+
+        let mut bytes = vec![
+            0x06, 0x09, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01,
+        ];
+        bytes.extend(tlv(0x04, &tlv(0x30, &basic)));
+        let mut response = vec![0x0a, 0x01, 0x00];
+        response.extend(tlv(0xa0, &tlv(0x30, &bytes)));
+        tlv(0x30, &response)
+    }
+
+    #[test]
+    fn test_ocsp() {
+        let routes = Arc::new(Mutex::new(HashMap::new()));
+        let port = server(routes.clone());
+        let ca = ca();
+        let checker = checker(&ca, port, false);
+        let other = KeyPair::generate().unwrap();
+        let revoked = tlv(0xa1, &tlv(0x18, b"20240601000000Z"));
+
+        assert_eq!(checker.stapled(None).status, Status::Warning);
+        for (status, next_update, key, want) in [
+            (
+                &[0x80, 0x00][..],
+                "29991231000000Z",
+                ca.issuer.key(),
+                Status::Ok,
+            ),
+            (
+                &revoked,
+                "29991231000000Z",
+                ca.issuer.key(),
+                Status::Revoked,
+            ),
+            (
+                &[0x82, 0x00],
+                "29991231000000Z",
+                ca.issuer.key(),
+                Status::Error,
+            ),
+            // outdated response
+            (
+                &[0x80, 0x00],
+                "20240102000000Z",
+                ca.issuer.key(),
+                Status::Error,
+            ),
+            // signed by another key
+            (&[0x80, 0x00], "29991231000000Z", &other, Status::Error),
+        ] {
+            let response = ocsp_response(&ca, status, next_update, key);
+            let check = checker.stapled(Some(&response));
+            assert_eq!(check.status, want, "{}", check);
+
+            routes.lock().unwrap().insert("/ocsp".into(), response);
+            let checks = checker.ocsp();
+            assert_eq!(checks.len(), 1);
+            assert_eq!(checks[0].status, want, "{}", checks[0]);
+        }
+
+        assert_eq!(checker.stapled(Some(b"invalid")).status, Status::Error);
+    }
+
+    #[test]
+    fn test_crl() {
+        let routes = Arc::new(Mutex::new(HashMap::new()));
+        let port = server(routes.clone());
+        let ca = ca();
+        let checker = checker(&ca, port, false);
+        // same name as the ca, but another key
+        let other = Issuer::new(
+            CertificateParams::new(vec![]).unwrap(),
+            KeyPair::generate().unwrap(),
+        );
+
+        assert_eq!(checker.crl()[0].status, Status::Error);
+        for (revoked, next_update, issuer, want) in [
+            (false, 2999, &*ca.issuer, Status::Ok),
+            (true, 2999, &*ca.issuer, Status::Revoked),
+            (false, 2024, &*ca.issuer, Status::Error),
+            (false, 2999, &other, Status::Error),
+        ] {
+            let crl = CertificateRevocationListParams {
+                this_update: date_time_ymd(2023, 1, 1),
+                next_update: date_time_ymd(next_update, 1, 1),
+                crl_number: SerialNumber::from_slice(&[1]),
+                issuing_distribution_point: None,
+                revoked_certs: revoked
+                    .then(|| RevokedCertParams {
+                        serial_number: SerialNumber::from_slice(SERIAL),
+                        revocation_time: date_time_ymd(2024, 6, 1),
+                        reason_code: None,
+                        invalidity_date: None,
+                    })
+                    .into_iter()
+                    .collect(),
+                key_identifier_method: KeyIdMethod::Sha256,
+            }
+            .signed_by(issuer)
+            .unwrap();
+            routes
+                .lock()
+                .unwrap()
+                .insert("/crl".into(), crl.der().to_vec());
+            let checks = checker.crl();
+            assert_eq!(checks.len(), 1);
+            assert_eq!(checks[0].status, want, "{}", checks[0]);
+        }
+
+        // responses are not read beyond the maximum size
+        routes
+            .lock()
+            .unwrap()
+            .insert("/crl".into(), vec![0; http::MAX_RESPONSE as usize + 1]);
+        let checks = checker.crl();
+        assert_eq!(checks[0].status, Status::Error);
+        assert!(checks[0].detail.contains("exceeds"), "{}", checks[0]);
+    }
+
+    #[test]
+    fn test_sct() {
+        let ca = ca();
+        let checks = checker(&ca, 80, true).sct();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Ok);
+        assert!(checks[0]
+            .detail
+            .starts_with(&format!("log {}", hex(&LOG_ID))));
+
+        let checks = checker(&ca, 80, false).sct();
+        assert_eq!(checks[0].status, Status::Warning);
+    }
+}
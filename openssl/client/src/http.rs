@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::io::{Read, Write};
+
+/// maximum size of a response with headers in bytes, large crls are a few
+/// megabytes.
+pub const MAX_RESPONSE: u64 = 16 * 1024 * 1024;
+
+/// send a plain http request to url and return the response body. ocsp
+/// responders and crl distribution points are served over plain http, so
+/// https is not supported. the request has a body if content is given as
+/// content type and data.
+pub fn request(url: &str, content: Option<(&str, &[u8])>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported url: {}", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let addr = match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+        _ => format!("{}:80", host),
+    };
+
+    // http/1.0 without keep alive, the response ends when the connection
+    // is closed
+    let mut stream = crate::connect(addr)?;
+    let mut req = format!(
+        "{} {} HTTP/1.0\r\n\
+        Host: {}\r\n",
+        if content.is_some() { "POST" } else { "GET" },
+        path,
+        host
+    )
+    .into_bytes();
+    if let Some((content_type, data)) = content {
+        req.extend(
+            format!(
+                "Content-Type: {}\r\n\
+                Content-Length: {}\r\n",
+                content_type,
+                data.len()
+            )
+            .as_bytes(),
+        );
+        req.extend(b"\r\n");
+        req.extend(data);
+    } else {
+        req.extend(b"\r\n");
+    }
+    stream.write_all(&req)?;
+    let mut res = Vec::new();
+    stream.take(MAX_RESPONSE + 1).read_to_end(&mut res)?;
+    if res.len() as u64 > MAX_RESPONSE {
+        return Err(format!("{}: response exceeds {} bytes", url, MAX_RESPONSE).into());
+    }
+
+    // check status and strip headers
+    let end = res
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| format!("{}: invalid response", url))?;
+    let head = String::from_utf8_lossy(&res[..end]);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split(' ').nth(1) != Some("200") {
+        return Err(format!("{}: {}", url, status).into());
+    }
+    Ok(res[end + 4..].to_vec())
+}
@@ -0,0 +1,87 @@
+use openssl::hash::MessageDigest;
+use openssl::ssl::{ConnectConfiguration, SslStream};
+use std::error::Error;
+use std::fmt::Write as FmtWrite;
+use std::io::{Read, Write as IoWrite};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use summary::{Cert, Summary};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub mod checks;
+pub mod http;
+pub mod monitor;
+
+pub fn connect(addr: String) -> Result<TcpStream, Box<dyn Error>> {
+    // try to resolve address to socket addresses
+    let sock_addrs = addr.to_socket_addrs()?;
+    if sock_addrs.len() == 0 {
+        return Err("could not resolve address".into());
+    }
+
+    // try connecting to each socket address with a short connect timeout,
+    // set read and write timeout on first successfull connection and return it
+    for sock_addr in sock_addrs {
+        if let Ok(sock) = TcpStream::connect_timeout(&sock_addr, CONNECT_TIMEOUT) {
+            sock.set_read_timeout(Some(READ_TIMEOUT))?;
+            sock.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            return Ok(sock);
+        };
+    }
+
+    Err("failed to connect".into())
+}
+
+/// format bytes as lowercase hex.
+pub fn hex(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for byte in bytes {
+        write!(&mut s, "{:02x}", byte).unwrap();
+    }
+    s
+}
+
+/// connect to the server with config, send the http request and read the
+/// response. summary is filled with the results until an error occurs.
+pub fn get(
+    config: ConnectConfiguration,
+    addr: &str,
+    port: u16,
+    summary: &mut Summary,
+) -> Result<(SslStream<TcpStream>, Vec<u8>), Box<dyn Error>> {
+    let stream = connect(format!("{}:{}", addr, port))?;
+    let mut stream = config.connect(addr, stream)?;
+
+    let ssl = stream.ssl();
+    summary.version = Some(ssl.version_str().to_string());
+    summary.cipher = ssl
+        .current_cipher()
+        .map(|cipher| cipher.standard_name().unwrap_or(cipher.name()).to_string());
+    for cert in ssl.peer_cert_chain().into_iter().flatten() {
+        summary.chain.push(Cert::from_der(&cert.to_der()?)?);
+    }
+    summary.digest = match ssl.peer_certificate() {
+        Some(cert) => Some(hex(&cert.digest(MessageDigest::sha256())?)),
+        None => None,
+    };
+
+    // run http request
+    stream.write_all(
+        format!(
+            "GET / HTTP/1.1\r\n\
+            Host: {}\r\n\
+            Connection: close\r\n\
+            Accept-Encoding: identity\r\n\
+            \r\n",
+            addr
+        )
+        .as_bytes(),
+    )?;
+    let mut res = vec![];
+    stream.read_to_end(&mut res)?;
+    summary.status = summary::status(&res);
+    Ok((stream, res))
+}
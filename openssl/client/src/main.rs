@@ -1,83 +1,159 @@
-use openssl::hash::MessageDigest;
-use openssl::ssl::{SslConnector, SslMethod};
+use clap::Parser;
+use openssl::ssl::{SslConnector, SslMethod, StatusType};
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::X509;
+use openssl_client::{checks, monitor};
 use std::error::Error;
-use std::fmt::Write as FmtWrite;
-use std::io::{Read, Write as IoWrite};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::time::Duration;
+use std::fs;
+use std::path::{Path, PathBuf};
+use summary::Summary;
 
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
-const READ_TIMEOUT: Duration = Duration::from_secs(5);
-const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Send a HTTP request to a TLS server and check the revocation status and
+/// certificate transparency of its certificate, or monitor the certificate
+/// expiry of many servers
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Hostname of the server
+    #[clap(default_value = "www.rust-lang.org")]
+    addr: String,
+    /// Port of the server
+    #[clap(default_value_t = 443)]
+    port: u16,
+    /// Trust anchors in PEM format instead of the default certificate store
+    #[clap(long)]
+    ca: Option<PathBuf>,
+    /// Query the OCSP responders of the certificate directly in addition to
+    /// checking the stapled response
+    #[clap(long)]
+    ocsp: bool,
+    /// Download the CRLs of the certificate and check them
+    #[clap(long)]
+    crl: bool,
+    /// Monitor the certificates of the servers in this file instead of
+    /// sending a request, one host:port[/sni] per line with IPv6 addresses in
+    /// brackets
+    #[clap(long, conflicts_with_all = ["ocsp", "crl"])]
+    targets: Option<PathBuf>,
+    /// Output format of the monitor
+    #[clap(long, value_enum, default_value = "table", requires = "targets")]
+    format: monitor::Format,
+    /// Write the monitor output to this file instead of stdout, the file is
+    /// replaced atomically
+    #[clap(short, long, requires = "targets")]
+    output: Option<PathBuf>,
+    /// Warn about certificates expiring within this many days, exits with 1
+    #[clap(long, default_value_t = 30, requires = "targets")]
+    warning_days: i32,
+    /// Fail on certificates expiring within this many days and on chain or
+    /// connection problems, exits with 2
+    #[clap(long, default_value_t = 7, requires = "targets")]
+    critical_days: i32,
+    /// Maximum number of concurrent connections of the monitor
+    #[clap(long, default_value_t = 16, requires = "targets")]
+    jobs: usize,
+}
+
+/// create trust store with the certificates of the ca file or the default
+/// certificate store.
+fn trust_store(ca: Option<&Path>) -> Result<X509Store, Box<dyn Error>> {
+    let mut store = X509StoreBuilder::new()?;
+    match ca {
+        Some(path) => {
+            let pem = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            for cert in X509::stack_from_pem(&pem)? {
+                store.add_cert(cert)?;
+            }
+        }
+        None => store.set_default_paths()?,
+    }
+    Ok(store.build())
+}
 
-fn connect(addr: String) -> Result<TcpStream, Box<dyn Error>> {
-    // try to resolve address to socket addresses
-    let sock_addrs = addr.to_socket_addrs()?;
-    if sock_addrs.len() == 0 {
-        return Err("could not resolve address".into());
+/// check the certificates of the targets in the file and write the reports.
+/// returns the exit code.
+fn monitor(cli: &Cli, targets: &Path) -> Result<i32, Box<dyn Error>> {
+    let content =
+        fs::read_to_string(targets).map_err(|err| format!("{}: {}", targets.display(), err))?;
+    let targets = monitor::Target::parse_file(&content)
+        .map_err(|err| format!("{}: {}", targets.display(), err))?;
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ca) = &cli.ca {
+        builder.set_ca_file(ca)?;
     }
+    let thresholds = monitor::Thresholds {
+        warning: cli.warning_days,
+        critical: cli.critical_days,
+    };
+    let reports = monitor::run(&builder.build(), &targets, thresholds, cli.jobs);
 
-    // try connecting to each socket address with a short connect timeout,
-    // set read and write timeout on first successfull connection and return it
-    for sock_addr in sock_addrs {
-        if let Ok(sock) = TcpStream::connect_timeout(&sock_addr, CONNECT_TIMEOUT) {
-            sock.set_read_timeout(Some(READ_TIMEOUT))?;
-            sock.set_write_timeout(Some(WRITE_TIMEOUT))?;
-            return Ok(sock);
-        };
+    let out = match cli.format {
+        monitor::Format::Table => monitor::table(&reports),
+        monitor::Format::Json => serde_json::to_string_pretty(&reports)? + "\n",
+        monitor::Format::Prometheus => monitor::prometheus(&reports),
+    };
+    match &cli.output {
+        Some(path) => {
+            // write to temporary file first, so readers never see a partial
+            // file
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".tmp");
+            fs::write(&tmp, out).map_err(|err| format!("{}: {}", path.display(), err))?;
+            fs::rename(&tmp, path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        None => print!("{}", out),
     }
 
-    Err("failed to connect".into())
+    let worst = reports.iter().map(|report| match report.status {
+        checks::Status::Ok => 0,
+        checks::Status::Warning => 1,
+        checks::Status::Revoked | checks::Status::Error => 2,
+    });
+    Ok(worst.max().unwrap_or(0))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // get address from command line
-    let addr = match std::env::args().nth(1) {
-        Some(addr) => addr,
-        None => "www.rust-lang.org".to_string(),
-    };
-
-    // get port from command line
-    let port = match std::env::args().nth(2) {
-        Some(port) => port,
-        None => "443".to_string(),
-    };
+    let cli = Cli::parse();
+    if let Some(targets) = &cli.targets {
+        let code = monitor(&cli, targets)?;
+        std::process::exit(code);
+    }
+    let addr = cli.addr;
 
-    // connect to server
-    let connector = SslConnector::builder(SslMethod::tls())?.build();
-    let stream = connect(format!("{}:{}", addr, port))?;
-    let mut stream = connector.connect(addr.as_str(), stream)?;
+    // connect to server and request ocsp stapling
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ca) = &cli.ca {
+        builder.set_ca_file(ca)?;
+    }
+    let connector = builder.build();
+    let mut config = connector.configure()?;
+    config.set_status_type(StatusType::OCSP)?;
 
-    // run http request
-    stream.write_all(
-        format!(
-            "GET / HTTP/1.1\r\n\
-            Host: {}\r\n\
-            Connection: close\r\n\
-            Accept-Encoding: identity\r\n\
-            \r\n",
-            addr
-        )
-        .as_bytes(),
-    )?;
-    let mut res = vec![];
-    stream.read_to_end(&mut res)?;
+    // run http request and print the response and the connection summary
+    let mut summary = Summary::default();
+    let (stream, res) = openssl_client::get(config, &addr, cli.port, &mut summary)?;
     println!("{}", String::from_utf8_lossy(&res));
+    print!("{}", summary);
 
-    // get peer certificate
-    let certificate = stream
-        .ssl()
-        .peer_certificate()
-        .ok_or("could not get certificate")?;
-    println!("{:?}", certificate);
-
-    // get digest
-    let digest = certificate.digest(MessageDigest::sha256())?;
-    let mut digest_hex = String::new();
-    for byte in digest.iter() {
-        write!(&mut digest_hex, "{:02x}", byte)?;
+    // check revocation status and certificate transparency
+    let checker = checks::Checker::from_ssl(stream.ssl(), trust_store(cli.ca.as_deref())?)?;
+    let mut checks = vec![checker.stapled(stream.ssl().ocsp_status())];
+    if cli.ocsp {
+        checks.extend(checker.ocsp());
+    }
+    if cli.crl {
+        checks.extend(checker.crl());
+    }
+    checks.extend(checker.sct());
+    for check in &checks {
+        println!("{}", check);
+    }
+    if checks
+        .iter()
+        .any(|check| check.status == checks::Status::Revoked)
+    {
+        return Err("certificate is revoked".into());
     }
-    println!("Digest: {}", digest_hex);
 
     Ok(())
 }
@@ -0,0 +1,515 @@
+use crate::checks::Status;
+use clap::ValueEnum;
+use openssl::asn1::Asn1Time;
+use openssl::ssl::{SslConnector, SslVerifyMode};
+use openssl::x509::{X509NameRef, X509};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// output format of the monitor.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// table for humans
+    Table,
+    /// JSON array with one object per target
+    Json,
+    /// Prometheus text format, e.g. for the textfile collector
+    Prometheus,
+}
+
+/// server to monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    /// host and port to connect to
+    pub addr: String,
+    /// server name to send and verify
+    pub sni: String,
+}
+
+impl Target {
+    /// parse target from "host:port[/sni]" or "[ipv6]:port[/sni]", the port
+    /// defaults to 443 and the server name to the host.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (addr, sni) = match s.split_once('/') {
+            Some((addr, sni)) => (addr, Some(sni)),
+            None => (s, None),
+        };
+        // ipv6 addresses must be enclosed in brackets, as their colons could
+        // not be told apart from the port
+        let (host, name, port) = match addr.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((ip, port)) if !ip.is_empty() => (&addr[..ip.len() + 2], ip, port),
+                _ => return Err(format!("invalid target: {}", s)),
+            },
+            None if addr.matches(':').count() > 1 => {
+                return Err(format!("IPv6 address without brackets in target: {}", s));
+            }
+            None => {
+                let (host, port) = addr.split_at(addr.find(':').unwrap_or(addr.len()));
+                (host, host, port)
+            }
+        };
+        let port = match port {
+            "" => 443,
+            port => port
+                .strip_prefix(':')
+                .and_then(|port| port.parse::<u16>().ok())
+                .ok_or_else(|| format!("invalid port in target: {}", s))?,
+        };
+        if host.is_empty() || sni == Some("") {
+            return Err(format!("invalid target: {}", s));
+        }
+        Ok(Target {
+            addr: format!("{}:{}", host, port),
+            sni: sni.unwrap_or(name).to_string(),
+        })
+    }
+
+    /// parse targets of a file with one target per line, empty lines and
+    /// lines starting with # are ignored.
+    pub fn parse_file(content: &str) -> Result<Vec<Self>, String> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Target::parse)
+            .collect()
+    }
+}
+
+/// days before expiry at which a target is reported.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    pub warning: i32,
+    pub critical: i32,
+}
+
+/// certificate status of a target.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub target: String,
+    pub sni: String,
+    pub status: Status,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub not_after: Option<String>,
+    /// not after as unix time
+    pub expires: Option<i64>,
+    /// days until expiry, negative if expired
+    pub days: Option<i32>,
+    /// chain verification failures and connection errors
+    pub problems: Vec<String>,
+}
+
+/// format distinguished name like "CN=example.com, O=Example".
+fn name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            match entry.data().to_string() {
+                Ok(value) => format!("{}={}", key, value),
+                Err(_) => format!("{}=?", key),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// connect to target and get the peer certificate. verification failures
+/// of the chain and the server name do not abort the handshake, but are
+/// returned.
+fn fetch(connector: &SslConnector, target: &Target) -> Result<(X509, Vec<String>), Box<dyn Error>> {
+    let problems = Arc::new(Mutex::new(Vec::new()));
+    let mut config = connector.configure()?;
+    let recorded = problems.clone();
+    config.set_verify_callback(SslVerifyMode::PEER, move |ok, ctx| {
+        if !ok {
+            let problem = format!("{} (depth {})", ctx.error(), ctx.error_depth());
+            let mut problems = recorded.lock().unwrap();
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+        true
+    });
+    let stream = crate::connect(target.addr.clone())?;
+    let stream = config.connect(&target.sni, stream)?;
+    let cert = stream
+        .ssl()
+        .peer_certificate()
+        .ok_or("could not get certificate")?;
+    let problems = problems.lock().unwrap().clone();
+    Ok((cert, problems))
+}
+
+/// check certificate of target.
+pub fn check(connector: &SslConnector, target: &Target, thresholds: Thresholds) -> Report {
+    let mut report = Report {
+        target: target.addr.clone(),
+        sni: target.sni.clone(),
+        status: Status::Error,
+        subject: None,
+        issuer: None,
+        not_after: None,
+        expires: None,
+        days: None,
+        problems: Vec::new(),
+    };
+    let (cert, problems) = match fetch(connector, target) {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            report.problems.push(err.to_string());
+            return report;
+        }
+    };
+    report.subject = Some(name(cert.subject_name()));
+    report.issuer = Some(name(cert.issuer_name()));
+    report.not_after = Some(cert.not_after().to_string());
+    report.problems = problems;
+
+    let expiry = Asn1Time::from_unix(0)
+        .and_then(|epoch| epoch.diff(cert.not_after()))
+        .and_then(|expires| Ok((expires, Asn1Time::days_from_now(0)?.diff(cert.not_after())?)));
+    let days = match expiry {
+        Ok((expires, left)) => {
+            report.expires = Some(expires.days as i64 * 86400 + expires.secs as i64);
+            report.days = Some(left.days);
+            left.days
+        }
+        Err(err) => {
+            report.problems.push(err.to_string());
+            return report;
+        }
+    };
+    report.status = if !report.problems.is_empty() || days < thresholds.critical {
+        Status::Error
+    } else if days < thresholds.warning {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+    report
+}
+
+/// check targets concurrently with up to jobs connections at a time. the
+/// reports are in the order of the targets.
+pub fn run(
+    connector: &SslConnector,
+    targets: &[Target],
+    thresholds: Thresholds,
+    jobs: usize,
+) -> Vec<Report> {
+    let next = Mutex::new(targets.iter().enumerate());
+    let reports = Mutex::new(Vec::with_capacity(targets.len()));
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, targets.len().max(1)) {
+            s.spawn(|| loop {
+                let Some((i, target)) = next.lock().unwrap().next() else {
+                    break;
+                };
+                let report = check(connector, target, thresholds);
+                reports.lock().unwrap().push((i, report));
+            });
+        }
+    });
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(i, _)| *i);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+/// format reports as table.
+pub fn table(reports: &[Report]) -> String {
+    let mut rows = vec![[
+        "TARGET".to_string(),
+        "SNI".into(),
+        "STATUS".into(),
+        "DAYS".into(),
+        "NOT AFTER".into(),
+        "ISSUER".into(),
+        "PROBLEMS".into(),
+    ]];
+    for report in reports {
+        let or_dash = |s: &Option<String>| s.clone().unwrap_or_else(|| "-".into());
+        rows.push([
+            report.target.clone(),
+            report.sni.clone(),
+            report.status.to_string(),
+            report.days.map_or("-".into(), |days| days.to_string()),
+            or_dash(&report.not_after),
+            or_dash(&report.issuer),
+            report.problems.join("; "),
+        ]);
+    }
+    let mut widths = [0; 7];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(&row) {
+            write!(line, "{:width$}  ", cell, width = width).unwrap();
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// escape prometheus label value.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// prometheus metric with name, help text and value of a report.
+type Metric = (&'static str, &'static str, fn(&Report) -> Option<i64>);
+
+/// format reports in prometheus text format.
+pub fn prometheus(reports: &[Report]) -> String {
+    let metrics: [Metric; 4] = [
+        (
+            "tls_cert_probe_success",
+            "Whether the certificate could be retrieved",
+            |report| Some(report.not_after.is_some() as i64),
+        ),
+        (
+            "tls_cert_not_after_seconds",
+            "Expiry of the certificate as unix time",
+            |report| report.expires,
+        ),
+        (
+            "tls_cert_expiry_days",
+            "Days until the certificate expires",
+            |report| report.days.map(i64::from),
+        ),
+        (
+            "tls_cert_chain_problems",
+            "Number of chain verification problems",
+            |report| {
+                report
+                    .not_after
+                    .as_ref()
+                    .map(|_| report.problems.len() as i64)
+            },
+        ),
+    ];
+    let mut out = String::new();
+    for (metric, help, value) in metrics {
+        writeln!(out, "# HELP {} {}", metric, help).unwrap();
+        writeln!(out, "# TYPE {} gauge", metric).unwrap();
+        for report in reports {
+            if let Some(value) = value(report) {
+                writeln!(
+                    out,
+                    "{}{{target=\"{}\",sni=\"{}\",issuer=\"{}\"}} {}",
+                    metric,
+                    label(&report.target),
+                    label(&report.sni),
+                    label(report.issuer.as_deref().unwrap_or_default()),
+                    value
+                )
+                .unwrap();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Integer;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::X509NameBuilder;
+    use std::net::TcpListener;
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// create certificate for name valid for days, issued by issuer or self
+    /// signed.
+    fn cert(
+        name: &str,
+        days: u32,
+        key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+    ) -> X509 {
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+        let subject = subject.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = Asn1Integer::from_bn(&BigNum::from_u32(days + 1).unwrap()).unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder
+            .set_issuer_name(issuer.map_or(&subject, |(cert, _)| cert.subject_name()))
+            .unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(days).unwrap())
+            .unwrap();
+        if issuer.is_none() && name == "CA" {
+            let ca = BasicConstraints::new().critical().ca().build().unwrap();
+            builder.append_extension(ca).unwrap();
+        } else {
+            let context = builder.x509v3_context(issuer.map(|(cert, _)| &**cert), None);
+            let san = SubjectAlternativeName::new()
+                .dns(name)
+                .build(&context)
+                .unwrap();
+            builder.append_extension(san).unwrap();
+        }
+        let signer = issuer.map_or(key, |(_, key)| key);
+        builder.sign(signer, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    /// start tls server on a random local port accepting one connection.
+    fn server(cert: X509, key: PKey<Private>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+        thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let _ = acceptor.accept(sock);
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse() {
+        let target = |addr: &str, sni: &str| {
+            Ok(Target {
+                addr: addr.into(),
+                sni: sni.into(),
+            })
+        };
+        for (s, want) in [
+            (
+                "example.com:8443",
+                target("example.com:8443", "example.com"),
+            ),
+            ("example.com", target("example.com:443", "example.com")),
+            (
+                "10.0.0.1:443/example.com",
+                target("10.0.0.1:443", "example.com"),
+            ),
+            ("[::1]:443", target("[::1]:443", "::1")),
+            ("[2001:db8::1]", target("[2001:db8::1]:443", "2001:db8::1")),
+            (
+                "[2001:db8::1]:8443/example.com",
+                target("[2001:db8::1]:8443", "example.com"),
+            ),
+            ("::1", Err(())),
+            ("2001:db8::1:443", Err(())),
+            ("[::1", Err(())),
+            ("[::1]443", Err(())),
+            ("[]:443", Err(())),
+            ("example.com:https", Err(())),
+            ("example.com:443/", Err(())),
+            (":443", Err(())),
+        ] {
+            assert_eq!(Target::parse(s).map_err(|_| ()), want, "{}", s);
+        }
+
+        let targets = Target::parse_file("# services\na.example.com\n\n  b.example.com:8443\n");
+        assert_eq!(targets.unwrap().len(), 2);
+        assert!(Target::parse_file("a.example.com\nb:x\n").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let ca_key = key();
+        let ca = cert("CA", 365, &ca_key, None);
+        let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+        builder.cert_store_mut().add_cert(ca.clone()).unwrap();
+        let connector = builder.build();
+        let thresholds = Thresholds {
+            warning: 30,
+            critical: 7,
+        };
+
+        // certificate name, validity in days, issued by ca, sni, status,
+        // problems
+        for (name, days, issued, sni, status, problems) in [
+            ("localhost", 90, true, "localhost", Status::Ok, 0),
+            ("localhost", 20, true, "localhost", Status::Warning, 0),
+            ("localhost", 3, true, "localhost", Status::Error, 0),
+            ("localhost", 90, false, "localhost", Status::Error, 1),
+            ("localhost", 90, true, "example.com", Status::Error, 1),
+        ] {
+            let key = key();
+            let cert = cert(name, days, &key, issued.then_some((&ca, &ca_key)));
+            let port = server(cert, key);
+            let target = Target {
+                addr: format!("127.0.0.1:{}", port),
+                sni: sni.into(),
+            };
+            let report = check(&connector, &target, thresholds);
+            assert_eq!(report.status, status, "{:?}", report);
+            assert_eq!(report.problems.len(), problems, "{:?}", report);
+            assert!(matches!(report.days, Some(d) if d == days as i32 || d == days as i32 - 1));
+            assert_eq!(report.subject.as_deref(), Some("CN=localhost"));
+        }
+
+        // connection refused
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let targets = [Target::parse(&format!("127.0.0.1:{}", port)).unwrap()];
+        let reports = run(&connector, &targets, thresholds, 4);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].status, Status::Error);
+        assert_eq!(reports[0].days, None);
+        assert!(prometheus(&reports).contains(&format!(
+            "tls_cert_probe_success{{target=\"127.0.0.1:{}\",sni=\"127.0.0.1\",issuer=\"\"}} 0\n",
+            port
+        )));
+        assert!(!prometheus(&reports).contains("tls_cert_expiry_days{"));
+    }
+
+    #[test]
+    fn test_table() {
+        let report = Report {
+            target: "example.com:443".into(),
+            sni: "example.com".into(),
+            status: Status::Warning,
+            subject: Some("CN=example.com".into()),
+            issuer: Some("CN=CA".into()),
+            not_after: Some("Jan  1 00:00:00 2030 GMT".into()),
+            expires: Some(1893456000),
+            days: Some(20),
+            problems: Vec::new(),
+        };
+        assert_eq!(
+            table(&[report]),
+            "TARGET           SNI          STATUS   DAYS  NOT AFTER                 ISSUER  PROBLEMS\n\
+             example.com:443  example.com  warning  20    Jan  1 00:00:00 2030 GMT  CN=CA\n"
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use rcgen::{
+    Certificate, CertificateRevocationListParams, Issuer, KeyIdMethod, KeyPair, RevocationReason,
+    RevokedCertParams, SerialNumber,
+};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::{Duration, OffsetDateTime};
+
+/// validity of a generated certificate revocation list in days.
+const CRL_DAYS: i64 = 30;
+
+/// entry in the ca's certificate index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub serial: u64,
+    pub not_after: i64,
+    pub revoked: Option<(i64, Option<RevocationReason>)>,
+    pub subject: String,
+}
+
+fn reason_to_str(reason: RevocationReason) -> &'static str {
+    match reason {
+        RevocationReason::Unspecified => "unspecified",
+        RevocationReason::KeyCompromise => "keyCompromise",
+        RevocationReason::CaCompromise => "caCompromise",
+        RevocationReason::AffiliationChanged => "affiliationChanged",
+        RevocationReason::Superseded => "superseded",
+        RevocationReason::CessationOfOperation => "cessationOfOperation",
+        RevocationReason::CertificateHold => "certificateHold",
+        RevocationReason::RemoveFromCrl => "removeFromCRL",
+        RevocationReason::PrivilegeWithdrawn => "privilegeWithdrawn",
+        RevocationReason::AaCompromise => "aaCompromise",
+    }
+}
+
+fn reason_from_str(s: &str) -> Option<RevocationReason> {
+    Some(match s {
+        "unspecified" => RevocationReason::Unspecified,
+        "keyCompromise" => RevocationReason::KeyCompromise,
+        "caCompromise" => RevocationReason::CaCompromise,
+        "affiliationChanged" => RevocationReason::AffiliationChanged,
+        "superseded" => RevocationReason::Superseded,
+        "cessationOfOperation" => RevocationReason::CessationOfOperation,
+        "certificateHold" => RevocationReason::CertificateHold,
+        "removeFromCRL" => RevocationReason::RemoveFromCrl,
+        "privilegeWithdrawn" => RevocationReason::PrivilegeWithdrawn,
+        "aaCompromise" => RevocationReason::AaCompromise,
+        _ => return None,
+    })
+}
+
+impl IndexEntry {
+    /// parse index entry from a tab separated line in the format
+    /// "<V|R>\t<not after>\t<revocation time>[,<reason>]\t<serial>\t<subject>".
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        let status = fields.next()?;
+        let not_after = fields.next()?.parse().ok()?;
+        let revocation = fields.next()?;
+        let serial = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let subject = fields.next()?.to_string();
+
+        let revoked = match status {
+            "V" => None,
+            "R" => {
+                let (time, reason) = match revocation.split_once(',') {
+                    Some((time, reason)) => (time, Some(reason_from_str(reason)?)),
+                    None => (revocation, None),
+                };
+                Some((time.parse().ok()?, reason))
+            }
+            _ => return None,
+        };
+
+        Some(IndexEntry {
+            serial,
+            not_after,
+            revoked,
+            subject,
+        })
+    }
+
+    /// format index entry as tab separated line.
+    pub fn format(&self) -> String {
+        let (status, revocation) = match self.revoked {
+            None => ("V", String::new()),
+            Some((time, None)) => ("R", time.to_string()),
+            Some((time, Some(reason))) => ("R", format!("{},{}", time, reason_to_str(reason))),
+        };
+        format!(
+            "{}\t{}\t{}\t{:x}\t{}",
+            status, self.not_after, revocation, self.serial, self.subject
+        )
+    }
+}
+
+/// certificate authority stored in a directory.
+pub struct Ca {
+    dir: PathBuf,
+    cert_pem: String,
+    key: KeyPair,
+}
+
+impl Ca {
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// create ca directory with certificate, key and certificate chain.
+    pub fn create(
+        dir: &Path,
+        cert: &Certificate,
+        key: KeyPair,
+        parent_chain: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        if dir.join("ca.pem").exists() {
+            return Err(format!("ca already exists in {}", dir.display()).into());
+        }
+        fs::create_dir_all(dir.join("certs"))?;
+
+        let ca = Ca {
+            dir: dir.into(),
+            cert_pem: cert.pem(),
+            key,
+        };
+        fs::write(ca.path("ca.pem"), &ca.cert_pem)?;
+        crate::output::write_key_file(&ca.path("ca.key"), ca.key.serialize_pem().as_bytes())?;
+        fs::write(ca.path("chain.pem"), ca.cert_pem.clone() + parent_chain)?;
+        fs::write(ca.path("serial"), "1\n")?;
+        fs::write(ca.path("crlnumber"), "1\n")?;
+        fs::write(ca.path("index.txt"), "")?;
+        ca.write_crl()?;
+        Ok(ca)
+    }
+
+    /// open existing ca directory.
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let cert_pem = fs::read_to_string(dir.join("ca.pem"))
+            .map_err(|err| format!("could not read ca in {}: {}", dir.display(), err))?;
+        let key = KeyPair::from_pem(&fs::read_to_string(dir.join("ca.key"))?)?;
+        Ok(Ca {
+            dir: dir.into(),
+            cert_pem,
+            key,
+        })
+    }
+
+    /// get issuer for signing certificates and revocation lists.
+    pub fn issuer(&self) -> Result<Issuer<'static, &KeyPair>, rcgen::Error> {
+        Issuer::from_ca_cert_pem(&self.cert_pem, &self.key)
+    }
+
+    /// get certificate chain from this ca up to the root ca in pem format.
+    pub fn chain_pem(&self) -> std::io::Result<String> {
+        fs::read_to_string(self.path("chain.pem"))
+    }
+
+    /// get certificate chain from this ca up to the root ca in der format.
+    pub fn chain_der(&self) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+        Ok(CertificateDer::pem_file_iter(self.path("chain.pem"))?.collect::<Result<_, _>>()?)
+    }
+
+    /// read and increment a counter file.
+    fn next_number(&self, name: &str) -> Result<u64, Box<dyn Error>> {
+        let path = self.path(name);
+        let number = u64::from_str_radix(fs::read_to_string(&path)?.trim(), 16)?;
+        fs::write(&path, format!("{:x}\n", number + 1))?;
+        Ok(number)
+    }
+
+    /// get serial number for the next certificate. the serial number is
+    /// only used up when the certificate is recorded.
+    pub fn next_serial(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_str_radix(
+            fs::read_to_string(self.path("serial"))?.trim(),
+            16,
+        )?)
+    }
+
+    fn read_index(&self) -> Result<Vec<IndexEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for line in fs::read_to_string(self.path("index.txt"))?.lines() {
+            match IndexEntry::parse(line) {
+                Some(entry) => entries.push(entry),
+                None => return Err(format!("invalid index entry: {}", line).into()),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn write_index(&self, entries: &[IndexEntry]) -> std::io::Result<()> {
+        let mut index = String::new();
+        for entry in entries {
+            index += &entry.format();
+            index += "\n";
+        }
+        fs::write(self.path("index.txt"), index)
+    }
+
+    /// record issued certificate in index and certs directory and advance
+    /// the serial number.
+    pub fn record(
+        &self,
+        serial: u64,
+        not_after: OffsetDateTime,
+        subject: &str,
+        cert: &Certificate,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut entries = self.read_index()?;
+        entries.push(IndexEntry {
+            serial,
+            not_after: not_after.unix_timestamp(),
+            revoked: None,
+            subject: subject.into(),
+        });
+        self.write_index(&entries)?;
+        fs::write(self.path(&format!("certs/{:x}.pem", serial)), cert.pem())?;
+        fs::write(self.path("serial"), format!("{:x}\n", serial + 1))?;
+        Ok(())
+    }
+
+    /// revoke certificate with serial number and update revocation list.
+    pub fn revoke(
+        &self,
+        serial: u64,
+        reason: Option<RevocationReason>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut entries = self.read_index()?;
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry.serial == serial)
+            .ok_or(format!("unknown serial number {:x}", serial))?;
+        if entry.revoked.is_some() {
+            return Err(format!("certificate {:x} already revoked", serial).into());
+        }
+        entry.revoked = Some((OffsetDateTime::now_utc().unix_timestamp(), reason));
+        self.write_index(&entries)?;
+        self.write_crl()
+    }
+
+    /// generate certificate revocation list from index.
+    pub fn write_crl(&self) -> Result<(), Box<dyn Error>> {
+        let mut revoked_certs = Vec::new();
+        for entry in self.read_index()? {
+            if let Some((time, reason)) = entry.revoked {
+                revoked_certs.push(RevokedCertParams {
+                    serial_number: SerialNumber::from(entry.serial),
+                    revocation_time: OffsetDateTime::from_unix_timestamp(time)?,
+                    reason_code: reason,
+                    invalidity_date: None,
+                });
+            }
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let crl = CertificateRevocationListParams {
+            this_update: now,
+            next_update: now + Duration::days(CRL_DAYS),
+            crl_number: SerialNumber::from(self.next_number("crlnumber")?),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&self.issuer()?)?;
+        fs::write(self.path("crl.pem"), crl.pem()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Format;
+    use crate::{CaArgs, IssueArgs, KeyAlg, OutputArgs, Profile};
+    use std::os::unix::fs::PermissionsExt;
+    use x509_parser::prelude::*;
+
+    fn ca_args(dir: &Path, cn: &str) -> CaArgs {
+        CaArgs {
+            dir: dir.into(),
+            cn: cn.into(),
+            days: 30,
+            key_alg: KeyAlg::P256,
+        }
+    }
+
+    fn issue_args(dir: &Path, out: &Path, format: Format) -> IssueArgs {
+        IssueArgs {
+            dir: dir.into(),
+            cn: "example.com".into(),
+            san: vec!["example.com".into()],
+            ip: Vec::new(),
+            profile: Profile::Server,
+            key_usage: Vec::new(),
+            eku: Vec::new(),
+            days: 30,
+            key_alg: KeyAlg::P256,
+            output: OutputArgs {
+                out: Some(out.into()),
+                format,
+                password: "secret".into(),
+            },
+        }
+    }
+
+    /// check that each certificate is signed by the next one and the last
+    /// one is self-signed.
+    fn check_chain(chain: &[&[u8]]) {
+        let certs: Vec<_> = chain
+            .iter()
+            .map(|der| X509Certificate::from_der(der).unwrap().1)
+            .collect();
+        for (i, cert) in certs.iter().enumerate() {
+            let issuer = certs.get(i + 1).unwrap_or(cert);
+            assert_eq!(cert.issuer(), issuer.subject(), "{}", cert.subject());
+            cert.verify_signature(Some(issuer.public_key())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ca() {
+        let dir = std::env::temp_dir().join(format!("simple-cert-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (root, intermediate, leaf) = (dir.join("root"), dir.join("int"), dir.join("leaf"));
+
+        crate::init(ca_args(&root, "Root")).unwrap();
+        crate::intermediate(
+            ca_args(&root, "Intermediate"),
+            intermediate.clone(),
+            Some(0),
+        )
+        .unwrap();
+        // an existing ca is neither replaced nor recorded again
+        assert!(crate::intermediate(ca_args(&root, "Other"), intermediate.clone(), None).is_err());
+        let root_ca = Ca::open(&root).unwrap();
+        assert_eq!(root_ca.read_index().unwrap().len(), 1);
+        assert_eq!(root_ca.next_serial().unwrap(), 2);
+
+        // leaf certificate with serial 1 in pem format, replacing a readable key
+        fs::write(dir.join("leaf.key"), "").unwrap();
+        fs::set_permissions(dir.join("leaf.key"), fs::Permissions::from_mode(0o644)).unwrap();
+        crate::issue(issue_args(&intermediate, &leaf, Format::Pem)).unwrap();
+        let chain: Vec<_> = CertificateDer::pem_file_iter(dir.join("leaf.chain.pem"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(chain.len(), 3);
+        check_chain(&chain.iter().map(|cert| cert.as_ref()).collect::<Vec<_>>());
+        let mode = fs::metadata(dir.join("leaf.key"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // revoked serial is listed in the crl of the intermediate ca
+        let ca = Ca::open(&intermediate).unwrap();
+        ca.revoke(1, Some(RevocationReason::KeyCompromise)).unwrap();
+        let crl_pem = ::pem::parse(fs::read(intermediate.join("crl.pem")).unwrap()).unwrap();
+        let (_, crl) = CertificateRevocationList::from_der(crl_pem.contents()).unwrap();
+        assert_eq!(
+            crl.issuer(),
+            X509Certificate::from_der(&chain[1]).unwrap().1.subject()
+        );
+        let revoked: Vec<_> = crl
+            .iter_revoked_certificates()
+            .map(|cert| cert.raw_serial_as_string())
+            .collect();
+        assert_eq!(revoked, ["01"]);
+
+        // leaf certificate with serial 2 in a pkcs#12 archive with its chain
+        crate::issue(issue_args(&intermediate, &leaf, Format::P12)).unwrap();
+        let p12 = fs::read(dir.join("leaf.p12")).unwrap();
+        let key_store =
+            p12_keystore::KeyStore::from_pkcs12(&p12, "secret", Default::default()).unwrap();
+        let (name, key_chain) = key_store.private_key_chain().unwrap();
+        assert_eq!(name, "leaf");
+        let certs: Vec<_> = key_chain.certs().iter().map(|cert| cert.as_der()).collect();
+        assert_eq!(certs.len(), 3);
+        assert_eq!(certs[1..], [chain[1].as_ref(), chain[2].as_ref()]);
+        check_chain(&certs);
+        let (_, cert) = X509Certificate::from_der(certs[0]).unwrap();
+        assert_eq!(cert.raw_serial_as_string(), "02");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_entry() {
+        for (line, want) in [
+            (
+                "V\t1700000000\t\t1f\tCN=example.com",
+                Some(IndexEntry {
+                    serial: 0x1f,
+                    not_after: 1700000000,
+                    revoked: None,
+                    subject: "CN=example.com".into(),
+                }),
+            ),
+            (
+                "R\t1700000000\t1600000000\t2\tCN=client",
+                Some(IndexEntry {
+                    serial: 2,
+                    not_after: 1700000000,
+                    revoked: Some((1600000000, None)),
+                    subject: "CN=client".into(),
+                }),
+            ),
+            (
+                "R\t1700000000\t1600000000,keyCompromise\t3\tCN=host\twith tab",
+                Some(IndexEntry {
+                    serial: 3,
+                    not_after: 1700000000,
+                    revoked: Some((1600000000, Some(RevocationReason::KeyCompromise))),
+                    subject: "CN=host\twith tab".into(),
+                }),
+            ),
+            ("X\t1700000000\t\t1\tCN=invalid", None),
+            ("R\t1700000000\t1600000000,unknown\t1\tCN=invalid", None),
+            ("V\t1700000000\t\tzz\tCN=invalid", None),
+            ("V\t1700000000", None),
+        ] {
+            let entry = IndexEntry::parse(line);
+            assert_eq!(entry, want);
+            if let Some(entry) = entry {
+                assert_eq!(entry.format(), line);
+            }
+        }
+    }
+}
@@ -1,15 +1,588 @@
-use rcgen::generate_simple_self_signed;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rcgen::{
+    generate_simple_self_signed, BasicConstraints, CertificateParams,
+    CertificateSigningRequestParams, DistinguishedName, DnType, DnValue, ExtendedKeyUsagePurpose,
+    IsCa, KeyPair, KeyUsagePurpose, RevocationReason, RsaKeySize, SanType, SerialNumber,
+};
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use time::{Duration, OffsetDateTime};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // set subject alternative names
-    let subject_alt_names: Vec<String> = ["example.com", "localhost", "other.example.com"]
-        .map(String::from)
-        .to_vec();
+mod ca;
+mod output;
+mod policy;
+
+use ca::Ca;
+use output::Format;
+use policy::Policy;
+
+/// Create certificates and manage a small certificate authority
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// subject alternative names of self-signed certificates by default.
+const DEFAULT_NAMES: [&str; 3] = ["example.com", "localhost", "other.example.com"];
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a self-signed certificate and key
+    SelfSigned {
+        /// Subject alternative names
+        #[clap(name = "NAME", default_values = DEFAULT_NAMES)]
+        names: Vec<String>,
+    },
+    /// Initialize a root certificate authority
+    Init(CaArgs),
+    /// Create an intermediate certificate authority signed by a certificate authority
+    Intermediate {
+        #[clap(flatten)]
+        ca: CaArgs,
+        /// Directory of the new intermediate certificate authority
+        #[clap(short, long)]
+        out: PathBuf,
+        /// Maximum number of intermediate certificate authorities below this one
+        #[clap(long)]
+        path_len: Option<u8>,
+    },
+    /// Issue a leaf certificate signed by a certificate authority
+    Issue(IssueArgs),
+    /// Create a certificate signing request
+    Csr(CsrArgs),
+    /// Sign a certificate signing request with a certificate authority
+    Sign(SignArgs),
+    /// Revoke a certificate and update the certificate revocation list
+    Revoke {
+        /// Directory of the certificate authority
+        #[clap(short, long)]
+        dir: PathBuf,
+        /// Serial number of the certificate in hex
+        #[clap(short, long)]
+        serial: String,
+        /// Revocation reason
+        #[clap(short, long, value_enum)]
+        reason: Option<Reason>,
+    },
+    /// Regenerate the certificate revocation list
+    Crl {
+        /// Directory of the certificate authority
+        #[clap(short, long)]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct CaArgs {
+    /// Directory of the certificate authority
+    #[clap(short, long)]
+    dir: PathBuf,
+    /// Common name of the certificate authority
+    #[clap(long, default_value = "simple-cert CA")]
+    cn: String,
+    /// Validity in days
+    #[clap(long, default_value_t = 3650)]
+    days: i64,
+    /// Key algorithm
+    #[clap(short, long, value_enum, default_value_t = KeyAlg::P256)]
+    key_alg: KeyAlg,
+}
+
+#[derive(Args)]
+struct IssueArgs {
+    /// Directory of the certificate authority
+    #[clap(short, long)]
+    dir: PathBuf,
+    /// Common name of the certificate
+    #[clap(long)]
+    cn: String,
+    /// DNS subject alternative name, can be used multiple times
+    #[clap(long)]
+    san: Vec<String>,
+    /// IP address subject alternative name, can be used multiple times
+    #[clap(long)]
+    ip: Vec<IpAddr>,
+    /// Certificate profile with default key usages and extended key usages
+    #[clap(short, long, value_enum, default_value_t = Profile::Server)]
+    profile: Profile,
+    /// Key usage, overrides the profile, can be used multiple times
+    #[clap(long, value_enum)]
+    key_usage: Vec<KeyUsage>,
+    /// Extended key usage, overrides the profile, can be used multiple times
+    #[clap(long, value_enum)]
+    eku: Vec<Eku>,
+    /// Validity in days
+    #[clap(long, default_value_t = 365)]
+    days: i64,
+    /// Key algorithm
+    #[clap(short, long, value_enum, default_value_t = KeyAlg::P256)]
+    key_alg: KeyAlg,
+    #[clap(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct CsrArgs {
+    /// Common name of the certificate
+    #[clap(long)]
+    cn: String,
+    /// Organization of the subject
+    #[clap(long)]
+    org: Option<String>,
+    /// Organizational unit of the subject
+    #[clap(long)]
+    org_unit: Option<String>,
+    /// Country code of the subject
+    #[clap(long)]
+    country: Option<String>,
+    /// DNS subject alternative name, can be used multiple times
+    #[clap(long)]
+    san: Vec<String>,
+    /// IP address subject alternative name, can be used multiple times
+    #[clap(long)]
+    ip: Vec<IpAddr>,
+    /// Requested extended key usage, can be used multiple times
+    #[clap(long, value_enum)]
+    eku: Vec<Eku>,
+    /// Existing private key in PEM format instead of generating a new one
+    #[clap(long)]
+    key: Option<PathBuf>,
+    /// Key algorithm of a generated key
+    #[clap(short, long, value_enum, default_value_t = KeyAlg::P256)]
+    key_alg: KeyAlg,
+    /// Output file prefix, defaults to the common name
+    #[clap(short, long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SignArgs {
+    /// Directory of the certificate authority
+    #[clap(short, long)]
+    dir: PathBuf,
+    /// Certificate signing request in PEM format
+    #[clap(name = "CSR")]
+    csr: PathBuf,
+    /// Signing policy the request must satisfy
+    #[clap(long)]
+    policy: Option<PathBuf>,
+    /// Certificate profile, overrides the requested key usages and extended key usages
+    #[clap(short, long, value_enum)]
+    profile: Option<Profile>,
+    /// Validity in days
+    #[clap(long, default_value_t = 365)]
+    days: i64,
+    /// Output file prefix, defaults to the common name
+    #[clap(short, long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Output file prefix, defaults to the common name
+    #[clap(short, long)]
+    out: Option<PathBuf>,
+    /// Output format
+    #[clap(short, long, value_enum, default_value_t = Format::Pem)]
+    format: Format,
+    /// Password of PKCS#12 files
+    #[clap(long, default_value = "")]
+    password: String,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum KeyAlg {
+    P256,
+    P384,
+    Ed25519,
+    Rsa2048,
+    Rsa3072,
+    Rsa4096,
+}
+
+impl KeyAlg {
+    fn generate(self) -> Result<KeyPair, rcgen::Error> {
+        match self {
+            KeyAlg::P256 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256),
+            KeyAlg::P384 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384),
+            KeyAlg::Ed25519 => KeyPair::generate_for(&rcgen::PKCS_ED25519),
+            KeyAlg::Rsa2048 => {
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048)
+            }
+            KeyAlg::Rsa3072 => {
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_3072)
+            }
+            KeyAlg::Rsa4096 => {
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_4096)
+            }
+        }
+    }
+
+    fn is_rsa(self) -> bool {
+        matches!(self, KeyAlg::Rsa2048 | KeyAlg::Rsa3072 | KeyAlg::Rsa4096)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Profile {
+    /// TLS server certificate
+    Server,
+    /// TLS client certificate
+    Client,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum KeyUsage {
+    DigitalSignature,
+    ContentCommitment,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+    KeyCertSign,
+    CrlSign,
+}
+
+impl From<KeyUsage> for KeyUsagePurpose {
+    fn from(usage: KeyUsage) -> Self {
+        match usage {
+            KeyUsage::DigitalSignature => KeyUsagePurpose::DigitalSignature,
+            KeyUsage::ContentCommitment => KeyUsagePurpose::ContentCommitment,
+            KeyUsage::KeyEncipherment => KeyUsagePurpose::KeyEncipherment,
+            KeyUsage::DataEncipherment => KeyUsagePurpose::DataEncipherment,
+            KeyUsage::KeyAgreement => KeyUsagePurpose::KeyAgreement,
+            KeyUsage::KeyCertSign => KeyUsagePurpose::KeyCertSign,
+            KeyUsage::CrlSign => KeyUsagePurpose::CrlSign,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Eku {
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    TimeStamping,
+    OcspSigning,
+}
+
+impl From<Eku> for ExtendedKeyUsagePurpose {
+    fn from(eku: Eku) -> Self {
+        match eku {
+            Eku::ServerAuth => ExtendedKeyUsagePurpose::ServerAuth,
+            Eku::ClientAuth => ExtendedKeyUsagePurpose::ClientAuth,
+            Eku::CodeSigning => ExtendedKeyUsagePurpose::CodeSigning,
+            Eku::EmailProtection => ExtendedKeyUsagePurpose::EmailProtection,
+            Eku::TimeStamping => ExtendedKeyUsagePurpose::TimeStamping,
+            Eku::OcspSigning => ExtendedKeyUsagePurpose::OcspSigning,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Reason {
+    Unspecified,
+    KeyCompromise,
+    CaCompromise,
+    AffiliationChanged,
+    Superseded,
+    CessationOfOperation,
+    CertificateHold,
+    PrivilegeWithdrawn,
+}
+
+impl From<Reason> for RevocationReason {
+    fn from(reason: Reason) -> Self {
+        match reason {
+            Reason::Unspecified => RevocationReason::Unspecified,
+            Reason::KeyCompromise => RevocationReason::KeyCompromise,
+            Reason::CaCompromise => RevocationReason::CaCompromise,
+            Reason::AffiliationChanged => RevocationReason::AffiliationChanged,
+            Reason::Superseded => RevocationReason::Superseded,
+            Reason::CessationOfOperation => RevocationReason::CessationOfOperation,
+            Reason::CertificateHold => RevocationReason::CertificateHold,
+            Reason::PrivilegeWithdrawn => RevocationReason::PrivilegeWithdrawn,
+        }
+    }
+}
+
+/// create certificate parameters with common name, serial and validity.
+fn base_params(cn: &str, serial: u64, days: i64) -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, cn);
+    params.serial_number = Some(SerialNumber::from(serial));
+    params.not_before = OffsetDateTime::now_utc();
+    params.not_after = params.not_before + Duration::days(days);
+    params.use_authority_key_identifier_extension = true;
+    params
+}
+
+/// create subject alternative names from dns names and ip addresses.
+fn subject_alt_names(names: Vec<String>, ips: Vec<IpAddr>) -> Result<Vec<SanType>, rcgen::Error> {
+    let mut sans = Vec::new();
+    for name in names {
+        sans.push(SanType::DnsName(name.try_into()?));
+    }
+    for ip in ips {
+        sans.push(SanType::IpAddress(ip));
+    }
+    Ok(sans)
+}
+
+/// get default key usages of a profile.
+fn profile_key_usages(profile: Profile, rsa: bool) -> Vec<KeyUsagePurpose> {
+    match profile {
+        Profile::Server if rsa => vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ],
+        Profile::Server | Profile::Client => vec![KeyUsagePurpose::DigitalSignature],
+    }
+}
+
+/// get default extended key usages of a profile.
+fn profile_ekus(profile: Profile) -> Vec<ExtendedKeyUsagePurpose> {
+    match profile {
+        Profile::Server => vec![ExtendedKeyUsagePurpose::ServerAuth],
+        Profile::Client => vec![ExtendedKeyUsagePurpose::ClientAuth],
+    }
+}
 
+/// get common name of a distinguished name.
+fn common_name(dn: &DistinguishedName) -> Option<String> {
+    match dn.get(&DnType::CommonName)? {
+        DnValue::Utf8String(s) => Some(s.clone()),
+        DnValue::PrintableString(s) => Some(s.as_str().into()),
+        DnValue::Ia5String(s) => Some(s.as_str().into()),
+        _ => None,
+    }
+}
+
+/// create certificate parameters for a certificate authority.
+fn ca_params(cn: &str, serial: u64, days: i64, path_len: Option<u8>) -> CertificateParams {
+    let mut params = base_params(cn, serial, days);
+    params.is_ca = match path_len {
+        Some(path_len) => IsCa::Ca(BasicConstraints::Constrained(path_len)),
+        None => IsCa::Ca(BasicConstraints::Unconstrained),
+    };
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params
+}
+
+fn self_signed(names: Vec<String>) -> Result<(), Box<dyn Error>> {
     // create certificate
-    let cert = generate_simple_self_signed(subject_alt_names)?;
+    let cert = generate_simple_self_signed(names)?;
     println!("{}", cert.cert.pem());
     println!("{}", cert.signing_key.serialize_pem());
 
     Ok(())
 }
+
+fn init(args: CaArgs) -> Result<(), Box<dyn Error>> {
+    let key = args.key_alg.generate()?;
+    let mut params = ca_params(&args.cn, 0, args.days, None);
+    // let rcgen derive the root's serial number from its public key
+    params.serial_number = None;
+    let cert = params.self_signed(&key)?;
+    Ca::create(&args.dir, &cert, key, "")?;
+    println!("Created root CA \"{}\" in {}", args.cn, args.dir.display());
+    Ok(())
+}
+
+fn intermediate(args: CaArgs, out: PathBuf, path_len: Option<u8>) -> Result<(), Box<dyn Error>> {
+    let parent = Ca::open(&args.dir)?;
+    let serial = parent.next_serial()?;
+    let key = args.key_alg.generate()?;
+    let params = ca_params(&args.cn, serial, args.days, path_len);
+    let cert = params.signed_by(&key, &parent.issuer()?)?;
+    // only record the certificate once the new ca exists
+    Ca::create(&out, &cert, key, &parent.chain_pem()?)?;
+    parent.record(serial, params.not_after, &args.cn, &cert)?;
+    println!(
+        "Created intermediate CA \"{}\" with serial {:x} in {}",
+        args.cn,
+        serial,
+        out.display()
+    );
+    Ok(())
+}
+
+fn issue(args: IssueArgs) -> Result<(), Box<dyn Error>> {
+    let ca = Ca::open(&args.dir)?;
+    let serial = ca.next_serial()?;
+    let key = args.key_alg.generate()?;
+
+    // set subject alternative names
+    let mut params = base_params(&args.cn, serial, args.days);
+    params.subject_alt_names = subject_alt_names(args.san, args.ip)?;
+
+    // set key usages from profile or command line
+    params.key_usages = profile_key_usages(args.profile, args.key_alg.is_rsa());
+    if !args.key_usage.is_empty() {
+        params.key_usages = args.key_usage.into_iter().map(Into::into).collect();
+    }
+    params.extended_key_usages = profile_ekus(args.profile);
+    if !args.eku.is_empty() {
+        params.extended_key_usages = args.eku.into_iter().map(Into::into).collect();
+    }
+
+    // create certificate and write it
+    let cert = params.signed_by(&key, &ca.issuer()?)?;
+    ca.record(serial, params.not_after, &args.cn, &cert)?;
+    let out = args.output.out.unwrap_or(args.cn.clone().into());
+    let files = output::write(
+        &out,
+        args.output.format,
+        &cert,
+        &key,
+        &ca.chain_der()?,
+        &args.output.password,
+    )?;
+    println!(
+        "Issued certificate \"{}\" with serial {:x}",
+        args.cn, serial
+    );
+    for file in files {
+        println!("  {}", file.display());
+    }
+    Ok(())
+}
+
+fn csr(args: CsrArgs) -> Result<(), Box<dyn Error>> {
+    let out = args.out.unwrap_or(args.cn.clone().into());
+    let mut files = Vec::new();
+
+    // load existing key or generate a new one
+    let key = match &args.key {
+        Some(path) => KeyPair::from_pem(&fs::read_to_string(path)?)?,
+        None => {
+            let key = args.key_alg.generate()?;
+            let path = output::with_suffix(&out, ".key");
+            output::write_key_file(&path, key.serialize_pem().as_bytes())?;
+            files.push(path);
+            key
+        }
+    };
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, &args.cn);
+    if let Some(org) = args.org {
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, org);
+    }
+    if let Some(org_unit) = args.org_unit {
+        params
+            .distinguished_name
+            .push(DnType::OrganizationalUnitName, org_unit);
+    }
+    if let Some(country) = args.country {
+        params.distinguished_name.push(DnType::CountryName, country);
+    }
+    params.subject_alt_names = subject_alt_names(args.san, args.ip)?;
+    params.extended_key_usages = args.eku.into_iter().map(Into::into).collect();
+
+    let path = output::with_suffix(&out, ".csr");
+    fs::write(&path, params.serialize_request(&key)?.pem()?)?;
+    files.push(path);
+    println!("Created certificate signing request \"{}\"", args.cn);
+    for file in files {
+        println!("  {}", file.display());
+    }
+    Ok(())
+}
+
+fn sign(args: SignArgs) -> Result<(), Box<dyn Error>> {
+    let ca = Ca::open(&args.dir)?;
+    let mut csr = CertificateSigningRequestParams::from_pem(&fs::read_to_string(&args.csr)?)?;
+    let cn = common_name(&csr.params.distinguished_name)
+        .ok_or("certificate signing request has no common name")?;
+
+    // leaf certificates cannot sign certificates or crls
+    csr.params.key_usages.retain(|usage| {
+        !matches!(
+            usage,
+            KeyUsagePurpose::KeyCertSign | KeyUsagePurpose::CrlSign
+        )
+    });
+
+    // apply profile, requests without key usages get the default ones
+    let rsa = [
+        &rcgen::PKCS_RSA_SHA256,
+        &rcgen::PKCS_RSA_SHA384,
+        &rcgen::PKCS_RSA_SHA512,
+    ]
+    .contains(&csr.public_key.algorithm());
+    if let Some(profile) = args.profile {
+        csr.params.key_usages = profile_key_usages(profile, rsa);
+        csr.params.extended_key_usages = profile_ekus(profile);
+    } else if csr.params.key_usages.is_empty() {
+        csr.params.key_usages = profile_key_usages(Profile::Server, rsa);
+    }
+
+    // check request against policy before using a serial number
+    if let Some(path) = &args.policy
+        && let Err(violations) = Policy::load(path)?.check(&csr.params, args.days)
+    {
+        return Err(format!(
+            "certificate signing request violates policy: {}",
+            violations.join("; ")
+        )
+        .into());
+    }
+
+    let serial = ca.next_serial()?;
+    let base = base_params(&cn, serial, args.days);
+    csr.params.serial_number = base.serial_number;
+    csr.params.not_before = base.not_before;
+    csr.params.not_after = base.not_after;
+    csr.params.use_authority_key_identifier_extension = true;
+    csr.params.is_ca = IsCa::NoCa;
+
+    let cert = csr.signed_by(&ca.issuer()?)?;
+    ca.record(serial, csr.params.not_after, &cn, &cert)?;
+    let out = args.out.unwrap_or(cn.clone().into());
+    let files = output::write_cert(&out, &cert, &ca.chain_der()?)?;
+    println!("Signed certificate \"{}\" with serial {:x}", cn, serial);
+    for file in files {
+        println!("  {}", file.display());
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    let Some(command) = cli.command else {
+        return self_signed(DEFAULT_NAMES.map(String::from).into());
+    };
+    match command {
+        Command::SelfSigned { names } => self_signed(names),
+        Command::Init(args) => init(args),
+        Command::Intermediate { ca, out, path_len } => intermediate(ca, out, path_len),
+        Command::Issue(args) => issue(args),
+        Command::Csr(args) => csr(args),
+        Command::Sign(args) => sign(args),
+        Command::Revoke {
+            dir,
+            serial,
+            reason,
+        } => {
+            let serial = u64::from_str_radix(&serial, 16)?;
+            Ca::open(&dir)?.revoke(serial, reason.map(Into::into))?;
+            println!("Revoked certificate with serial {:x}", serial);
+            Ok(())
+        }
+        Command::Crl { dir } => Ca::open(&dir)?.write_crl(),
+    }
+}
@@ -0,0 +1,128 @@
+use clap::ValueEnum;
+use p12_keystore::{
+    Certificate as P12Certificate, KeyStore, KeyStoreEntry, PrivateKey, PrivateKeyChain,
+};
+use rcgen::{Certificate, KeyPair};
+use rustls_pki_types::CertificateDer;
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// output file format of certificates and keys.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// PEM encoded certificate, chain and PKCS#8 key
+    Pem,
+    /// DER encoded certificate and PKCS#8 key
+    Der,
+    /// PKCS#12 archive with key, certificate and chain
+    P12,
+}
+
+/// append suffix to path, e.g., "example.com" and ".pem" to "example.com.pem".
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// write private key to file only readable by the current user.
+pub fn write_key_file(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // the mode only applies to new files, restrict existing ones too
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(key)
+}
+
+/// create PKCS#12 archive with key, certificate and chain.
+pub fn pkcs12(
+    name: &str,
+    cert: &CertificateDer,
+    key: &[u8],
+    chain: &[CertificateDer],
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut certs = vec![P12Certificate::from_der(cert)?];
+    for cert in chain {
+        certs.push(P12Certificate::from_der(cert)?);
+    }
+    // use sha-1 digest of the certificate as local key id like openssl
+    let key_id = Sha1::digest(cert).to_vec();
+    let chain = PrivateKeyChain::new(key_id, PrivateKey::from_der(key)?, certs);
+
+    let mut key_store = KeyStore::new();
+    key_store.add_entry(name, KeyStoreEntry::PrivateKeyChain(chain));
+    Ok(key_store.writer(password).write()?)
+}
+
+/// encode certificate followed by its chain in pem format.
+fn full_chain_pem(cert: &Certificate, chain: &[CertificateDer]) -> String {
+    let mut full_chain = cert.pem();
+    for cert in chain {
+        // use the same line endings as rcgen
+        let pem = pem::Pem::new("CERTIFICATE", cert.to_vec());
+        let config = pem::EncodeConfig::new().set_line_ending(pem::LineEnding::LF);
+        full_chain += &pem::encode_config(&pem, config);
+    }
+    full_chain
+}
+
+/// write certificate without key and its chain in pem format. returns
+/// written files.
+pub fn write_cert(
+    out: &Path,
+    cert: &Certificate,
+    chain: &[CertificateDer],
+) -> std::io::Result<Vec<PathBuf>> {
+    let files = vec![with_suffix(out, ".pem"), with_suffix(out, ".chain.pem")];
+    fs::write(&files[0], cert.pem())?;
+    fs::write(&files[1], full_chain_pem(cert, chain))?;
+    Ok(files)
+}
+
+/// write certificate and key to files in format. returns written files.
+pub fn write(
+    out: &Path,
+    format: Format,
+    cert: &Certificate,
+    key: &KeyPair,
+    chain: &[CertificateDer],
+    password: &str,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let files = match format {
+        Format::Pem => {
+            let files = vec![
+                with_suffix(out, ".pem"),
+                with_suffix(out, ".key"),
+                with_suffix(out, ".chain.pem"),
+            ];
+            fs::write(&files[0], cert.pem())?;
+            write_key_file(&files[1], key.serialize_pem().as_bytes())?;
+            fs::write(&files[2], full_chain_pem(cert, chain))?;
+            files
+        }
+        Format::Der => {
+            let files = vec![with_suffix(out, ".der"), with_suffix(out, ".key.der")];
+            fs::write(&files[0], cert.der())?;
+            write_key_file(&files[1], &key.serialize_der())?;
+            files
+        }
+        Format::P12 => {
+            let files = vec![with_suffix(out, ".p12")];
+            let name = out.file_name().unwrap_or_default().to_string_lossy();
+            let p12 = pkcs12(&name, cert.der(), &key.serialize_der(), chain, password)?;
+            write_key_file(&files[0], &p12)?;
+            files
+        }
+    };
+    Ok(files)
+}
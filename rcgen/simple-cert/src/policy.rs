@@ -0,0 +1,371 @@
+use clap::ValueEnum;
+use rcgen::{CertificateParams, DnType, ExtendedKeyUsagePurpose, KeyUsagePurpose, SanType};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::{common_name, Eku};
+
+/// policy for signing certificate signing requests. policy files contain
+/// one directive per line, empty lines and lines starting with '#' are
+/// ignored. allow-san patterns apply to the common name too:
+///
+/// ```text
+/// allow-san *.example.com
+/// allow-san 10.0.0.*
+/// max-days 90
+/// require-eku server-auth
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct Policy {
+    allowed_sans: Vec<String>,
+    max_days: Option<i64>,
+    required_ekus: Vec<ExtendedKeyUsagePurpose>,
+}
+
+/// match name against pattern, '*' matches any characters except '.'.
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, suffix)) => {
+            let Some(rest) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            // try every length of the wildcard within the current label
+            for (i, c) in rest.char_indices() {
+                if matches(suffix, &rest[i..]) {
+                    return true;
+                }
+                if c == '.' {
+                    return false;
+                }
+            }
+            matches(suffix, "")
+        }
+    }
+}
+
+/// format subject alternative name for matching and error messages.
+fn san_to_string(san: &SanType) -> String {
+    match san {
+        SanType::DnsName(name) => name.as_str().to_ascii_lowercase(),
+        SanType::IpAddress(ip) => ip.to_string(),
+        SanType::Rfc822Name(name) => name.as_str().to_ascii_lowercase(),
+        SanType::URI(uri) => uri.as_str().into(),
+        SanType::OtherName((oid, _)) => format!("othername:{:?}", oid),
+        _ => format!("{:?}", san),
+    }
+}
+
+impl Policy {
+    /// parse policy from its text form.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut policy = Policy::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {}: {}", n + 1, msg, line);
+            let (key, value) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| err("missing value"))?;
+            let value = value.trim();
+            match key {
+                "allow-san" => policy.allowed_sans.push(value.to_ascii_lowercase()),
+                "max-days" => {
+                    policy.max_days = Some(value.parse().map_err(|_| err("invalid days"))?)
+                }
+                "require-eku" => {
+                    let eku = Eku::from_str(value, false).map_err(|_| err("unknown eku"))?;
+                    policy.required_ekus.push(eku.into());
+                }
+                _ => return Err(err("unknown directive")),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// read policy from file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let s = fs::read_to_string(path)
+            .map_err(|err| format!("could not read policy {}: {}", path.display(), err))?;
+        Ok(Policy::parse(&s).map_err(|err| format!("{}: {}", path.display(), err))?)
+    }
+
+    /// check certificate parameters with validity in days against the
+    /// policy. returns all violations.
+    pub fn check(&self, params: &CertificateParams, days: i64) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+
+        // clients may still match the common name, so it must be allowed too
+        if let Some(value) = params.distinguished_name.get(&DnType::CommonName) {
+            let name = match common_name(&params.distinguished_name) {
+                Some(name) => name.to_ascii_lowercase(),
+                None => format!("{:?}", value),
+            };
+            if !self
+                .allowed_sans
+                .iter()
+                .any(|pattern| matches(pattern, &name))
+            {
+                violations.push(format!("common name {} not allowed", name));
+            }
+        }
+
+        for san in &params.subject_alt_names {
+            let name = san_to_string(san);
+            if !self
+                .allowed_sans
+                .iter()
+                .any(|pattern| matches(pattern, &name))
+            {
+                violations.push(format!("subject alternative name {} not allowed", name));
+            }
+        }
+
+        if let Some(max_days) = self.max_days
+            && days > max_days
+        {
+            violations.push(format!(
+                "validity of {} days exceeds maximum of {} days",
+                days, max_days
+            ));
+        }
+
+        for eku in &self.required_ekus {
+            if !params.extended_key_usages.contains(eku) {
+                violations.push(format!("required extended key usage {:?} missing", eku));
+            }
+        }
+
+        for usage in [KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign] {
+            if params.key_usages.contains(&usage) {
+                violations.push(format!("key usage {:?} not allowed", usage));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::with_suffix;
+    use crate::{CaArgs, CsrArgs, KeyAlg, SignArgs};
+    use rcgen::{DistinguishedName, KeyPair};
+    use x509_parser::prelude::*;
+
+    #[test]
+    fn test_matches() {
+        for (pattern, name, want) in [
+            ("example.com", "example.com", true),
+            ("example.com", "www.example.com", false),
+            ("*.example.com", "www.example.com", true),
+            ("*.example.com", "example.com", false),
+            ("*.example.com", "a.b.example.com", false),
+            ("*.example.com", "www.example.org", false),
+            ("web-*.example.com", "web-1.example.com", true),
+            ("web-*.example.com", "db-1.example.com", false),
+            ("10.0.0.*", "10.0.0.17", true),
+            ("10.0.0.*", "10.0.1.17", false),
+            ("*", "localhost", true),
+            ("*", "www.example.com", false),
+        ] {
+            assert_eq!(matches(pattern, name), want, "{} {}", pattern, name);
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::parse(
+            "# web servers\n\
+             allow-san *.example.com\n\
+             allow-san 10.0.0.*\n\
+             \n\
+             max-days 90\n\
+             require-eku server-auth\n",
+        )
+        .unwrap();
+        assert_eq!(
+            policy,
+            Policy {
+                allowed_sans: vec!["*.example.com".into(), "10.0.0.*".into()],
+                max_days: Some(90),
+                required_ekus: vec![ExtendedKeyUsagePurpose::ServerAuth],
+            }
+        );
+
+        let params = |sans: &[&str], ekus: &[ExtendedKeyUsagePurpose]| {
+            let mut params = CertificateParams::new(
+                sans.iter()
+                    .filter(|san| san.parse::<std::net::IpAddr>().is_err())
+                    .map(|san| san.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+            for san in sans {
+                if let Ok(ip) = san.parse() {
+                    params.subject_alt_names.push(SanType::IpAddress(ip));
+                }
+            }
+            params.extended_key_usages = ekus.to_vec();
+            // without the default common name of rcgen
+            params.distinguished_name = DistinguishedName::new();
+            params
+        };
+        let with_cn = |mut params: CertificateParams, cn: &str| {
+            params.distinguished_name.push(DnType::CommonName, cn);
+            params
+        };
+        let server = [ExtendedKeyUsagePurpose::ServerAuth];
+
+        for (params, days, want) in [
+            (params(&["www.example.com", "10.0.0.1"], &server), 90, 0),
+            (params(&["WWW.Example.COM"], &server), 30, 0),
+            (params(&["www.example.org", "10.0.1.1"], &server), 90, 2),
+            (params(&["www.example.com"], &server), 365, 1),
+            (params(&["www.example.com"], &[]), 90, 1),
+            (
+                with_cn(params(&["www.example.com"], &server), "WWW.example.com"),
+                90,
+                0,
+            ),
+            (
+                with_cn(params(&["www.example.com"], &server), "www.example.org"),
+                90,
+                1,
+            ),
+            (
+                params(&["www.example.com"], &[ExtendedKeyUsagePurpose::ClientAuth]),
+                90,
+                1,
+            ),
+        ] {
+            let got = policy.check(&params, days).err().unwrap_or_default();
+            assert_eq!(got.len(), want, "{:?}", got);
+        }
+
+        for s in [
+            "allow-san",
+            "max-days many",
+            "require-eku everything",
+            "deny-san *.example.com",
+        ] {
+            assert!(Policy::parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let dir = std::env::temp_dir().join(format!("simple-cert-sign-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        crate::init(CaArgs {
+            dir: dir.join("ca"),
+            cn: "Root".into(),
+            days: 30,
+            key_alg: KeyAlg::P256,
+        })
+        .unwrap();
+        let policy = dir.join("policy");
+        fs::write(&policy, "allow-san *.example.com\nmax-days 90\n").unwrap();
+
+        for (cn, san, days, want) in [
+            ("www.example.com", "www.example.com", 90, Ok(())),
+            ("www.example.com", "www.example.com", 365, Err("validity")),
+            (
+                "www.example.com",
+                "www.example.org",
+                90,
+                Err("subject alternative name"),
+            ),
+            ("www.example.org", "www.example.com", 90, Err("common name")),
+        ] {
+            let out = dir.join(format!("{}-{}-{}", cn, san, days));
+            crate::csr(CsrArgs {
+                cn: cn.into(),
+                org: None,
+                org_unit: None,
+                country: None,
+                san: vec![san.into()],
+                ip: Vec::new(),
+                eku: Vec::new(),
+                key: None,
+                key_alg: KeyAlg::P256,
+                out: Some(out.clone()),
+            })
+            .unwrap();
+            let got = crate::sign(SignArgs {
+                dir: dir.join("ca"),
+                csr: with_suffix(&out, ".csr"),
+                policy: Some(policy.clone()),
+                profile: None,
+                days,
+                out: Some(out.clone()),
+            });
+            match want {
+                Ok(()) => {
+                    got.unwrap();
+                    assert!(with_suffix(&out, ".pem").exists());
+                }
+                Err(msg) => {
+                    let err = got.unwrap_err().to_string();
+                    assert!(err.contains(msg), "{}", err);
+                    assert!(!with_suffix(&out, ".pem").exists());
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sign_key_usages() {
+        let dir = std::env::temp_dir().join(format!("simple-cert-usages-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        crate::init(CaArgs {
+            dir: dir.join("ca"),
+            cn: "Root".into(),
+            days: 30,
+            key_alg: KeyAlg::P256,
+        })
+        .unwrap();
+
+        // request with the key usages of a certificate authority
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["www.example.com".into()]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "www.example.com");
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+        ];
+        let out = dir.join("www");
+        let csr = with_suffix(&out, ".csr");
+        fs::write(&csr, params.serialize_request(&key).unwrap().pem().unwrap()).unwrap();
+
+        crate::sign(SignArgs {
+            dir: dir.join("ca"),
+            csr,
+            policy: None,
+            profile: None,
+            days: 30,
+            out: Some(out.clone()),
+        })
+        .unwrap();
+        let pem = fs::read_to_string(with_suffix(&out, ".pem")).unwrap();
+        let der = ::pem::parse(pem).unwrap();
+        let (_, cert) = X509Certificate::from_der(der.contents()).unwrap();
+        let usage = cert.key_usage().unwrap().unwrap().value;
+        assert!(usage.digital_signature());
+        assert!(!usage.key_cert_sign());
+        assert!(!usage.crl_sign());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, MAIN_DB};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+/// up-migrations of the schema, the schema version stored in user_version is
+/// the number of applied migrations.
+const MIGRATIONS: &[&str] = &[
+    // 1: greetings
+    "CREATE TABLE greetings (
+        id        INTEGER PRIMARY KEY,
+        greeting  TEXT NOT NULL
+    );",
+    // 2: full-text index of greetings, kept up to date by triggers
+    "CREATE VIRTUAL TABLE greetings_fts USING fts5(
+        greeting, content='greetings', content_rowid='id'
+    );
+    CREATE TRIGGER greetings_fts_insert AFTER INSERT ON greetings BEGIN
+        INSERT INTO greetings_fts (rowid, greeting) VALUES (new.id, new.greeting);
+    END;
+    CREATE TRIGGER greetings_fts_delete AFTER DELETE ON greetings BEGIN
+        INSERT INTO greetings_fts (greetings_fts, rowid, greeting)
+            VALUES ('delete', old.id, old.greeting);
+    END;
+    CREATE TRIGGER greetings_fts_update AFTER UPDATE ON greetings BEGIN
+        INSERT INTO greetings_fts (greetings_fts, rowid, greeting)
+            VALUES ('delete', old.id, old.greeting);
+        INSERT INTO greetings_fts (rowid, greeting) VALUES (new.id, new.greeting);
+    END;
+    INSERT INTO greetings_fts (greetings_fts) VALUES ('rebuild');",
+    // 3: language as BCP 47 tag, formality, region and usage counter, the
+    // full-text index is only updated if the text changes
+    "ALTER TABLE greetings ADD COLUMN language TEXT NOT NULL DEFAULT 'en';
+    ALTER TABLE greetings ADD COLUMN formality TEXT NOT NULL DEFAULT 'neutral'
+        CHECK (formality IN ('informal', 'neutral', 'formal'));
+    ALTER TABLE greetings ADD COLUMN region TEXT;
+    ALTER TABLE greetings ADD COLUMN uses INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX greetings_language ON greetings (language, formality);
+    DROP TRIGGER greetings_fts_update;
+    CREATE TRIGGER greetings_fts_update AFTER UPDATE OF greeting ON greetings BEGIN
+        INSERT INTO greetings_fts (greetings_fts, rowid, greeting)
+            VALUES ('delete', old.id, old.greeting);
+        INSERT INTO greetings_fts (rowid, greeting) VALUES (new.id, new.greeting);
+    END;",
+];
+
+/// open database file or an in memory database if no path is given. file
+/// databases use write-ahead logging.
+pub fn open(path: Option<&Path>) -> Result<Connection, Box<dyn Error>> {
+    let conn = match path {
+        Some(path) => {
+            let conn =
+                Connection::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn
+        }
+        None => Connection::open_in_memory()?,
+    };
+    conn.busy_timeout(Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+/// get schema version of the database.
+pub fn version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// apply pending migrations, each in its own transaction. returns the
+/// schema version before the migration, which is 0 for new databases.
+pub fn migrate(conn: &mut Connection) -> Result<usize, Box<dyn Error>> {
+    let previous = version(conn)?;
+    if previous > MIGRATIONS.len() {
+        return Err(format!(
+            "database schema version {} is newer than the supported version {}",
+            previous,
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(previous) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(previous)
+}
+
+/// copy database to a file with the online backup api, other connections
+/// can keep using the database during the backup. an existing file is
+/// overwritten.
+pub fn backup(
+    conn: &Connection,
+    path: &Path,
+    progress: Option<fn(Progress)>,
+) -> Result<(), Box<dyn Error>> {
+    conn.backup(MAIN_DB, path, progress)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_migrate() {
+        let path = std::env::temp_dir().join(format!("greet-migrate-{}.db", process::id()));
+        let _ = fs::remove_file(&path);
+
+        // new database is migrated to the latest version
+        let mut conn = open(Some(&path)).unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), 0);
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+        let mode: String = conn
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+        conn.execute("INSERT INTO greetings (greeting) VALUES ('hello')", ())
+            .unwrap();
+        drop(conn);
+
+        // existing database keeps its data
+        let mut conn = open(Some(&path)).unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), MIGRATIONS.len());
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM greetings", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // newer databases are rejected
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+        drop(conn);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_backup() {
+        let path = std::env::temp_dir().join(format!("greet-backup-{}.db", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut conn = open(None).unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute("INSERT INTO greetings (greeting) VALUES ('hello')", ())
+            .unwrap();
+        backup(&conn, &path, None).unwrap();
+
+        // backup is a migrated copy of the database
+        let mut copy = open(Some(&path)).unwrap();
+        assert_eq!(migrate(&mut copy).unwrap(), MIGRATIONS.len());
+        let greeting: String = copy
+            .query_row("SELECT greeting FROM greetings", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(greeting, "hello");
+        drop(copy);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        assert!(backup(&conn, Path::new("/nonexistent/greet.db"), None).is_err());
+    }
+}
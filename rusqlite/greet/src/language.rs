@@ -0,0 +1,137 @@
+use std::env;
+
+/// language of greetings without a language and the last fallback of
+/// lookups.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// normalize BCP 47 language tag like "de_at" to "de-AT": the language is
+/// lowercase, scripts are titlecase and regions uppercase. returns None if
+/// the tag is not well-formed.
+pub fn normalize(tag: &str) -> Option<String> {
+    let mut subtags = Vec::new();
+    // subtags after a singleton like "x" or "u" are extensions
+    let mut extension = false;
+    for (i, subtag) in tag.split(['-', '_']).enumerate() {
+        if subtag.is_empty()
+            || subtag.len() > 8
+            || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        let alphabetic = subtag.chars().all(|c| c.is_ascii_alphabetic());
+        let numeric = subtag.chars().all(|c| c.is_ascii_digit());
+        let subtag = if i == 0 {
+            if !alphabetic || subtag.len() < 2 {
+                return None;
+            }
+            subtag.to_ascii_lowercase()
+        } else if subtag.len() == 1 {
+            extension = true;
+            subtag.to_ascii_lowercase()
+        } else if !extension && alphabetic && subtag.len() == 4 {
+            let (first, rest) = subtag.split_at(1);
+            first.to_ascii_uppercase() + &rest.to_ascii_lowercase()
+        } else if !extension && (alphabetic && subtag.len() == 2 || numeric && subtag.len() == 3) {
+            subtag.to_ascii_uppercase()
+        } else {
+            subtag.to_ascii_lowercase()
+        };
+        subtags.push(subtag);
+    }
+    Some(subtags.join("-"))
+}
+
+/// language tag of a posix locale like "de_AT.UTF-8@euro". the "C" and
+/// "POSIX" locales have no language.
+pub fn from_locale(locale: &str) -> Option<String> {
+    let locale = locale.split(['.', '@']).next()?;
+    match locale {
+        "C" | "POSIX" => None,
+        locale => normalize(locale),
+    }
+}
+
+/// language of the user's locale from LC_ALL, LC_MESSAGES or LANG, the
+/// first variable which is set wins.
+pub fn locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| from_locale(&value))
+}
+
+/// languages to look up for tag, from the most to the least specific like
+/// "de-AT", "de" and the default. the last subtag is removed on each step
+/// together with a preceding singleton, see RFC 4647 section 3.4.
+pub fn fallbacks(tag: &str, default: &str) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut subtags: Vec<&str> = tag.split('-').collect();
+    while !subtags.is_empty() {
+        chain.push(subtags.join("-"));
+        subtags.pop();
+        if subtags.last().is_some_and(|subtag| subtag.len() == 1) {
+            subtags.pop();
+        }
+    }
+    if !chain.iter().any(|language| language == default) {
+        chain.push(default.to_string());
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        for (tag, want) in [
+            ("en", Some("en")),
+            ("DE-at", Some("de-AT")),
+            ("de_AT", Some("de-AT")),
+            ("zh-hant-tw", Some("zh-Hant-TW")),
+            ("es-419", Some("es-419")),
+            ("sl-rozaj-biske", Some("sl-rozaj-biske")),
+            ("en-US-x-Twain", Some("en-US-x-twain")),
+            ("de-CH-u-co-phonebk", Some("de-CH-u-co-phonebk")),
+            ("", None),
+            ("e", None),
+            ("en-", None),
+            ("en--US", None),
+            ("1a", None),
+            ("en-US!", None),
+            ("en-verylongsubtag", None),
+        ] {
+            assert_eq!(normalize(tag).as_deref(), want, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_from_locale() {
+        for (locale, want) in [
+            ("de_AT.UTF-8", Some("de-AT")),
+            ("de_DE@euro", Some("de-DE")),
+            ("fr", Some("fr")),
+            ("C", None),
+            ("C.UTF-8", None),
+            ("POSIX", None),
+            ("", None),
+        ] {
+            assert_eq!(from_locale(locale).as_deref(), want, "{}", locale);
+        }
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        for (tag, want) in [
+            ("de-AT", vec!["de-AT", "de", "en"]),
+            ("en-GB", vec!["en-GB", "en"]),
+            ("en", vec!["en"]),
+            ("zh-Hant-TW", vec!["zh-Hant-TW", "zh-Hant", "zh", "en"]),
+            ("en-US-x-twain", vec!["en-US-x-twain", "en-US", "en"]),
+        ] {
+            assert_eq!(fallbacks(tag, DEFAULT_LANGUAGE), want, "{}", tag);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod db;
+pub mod language;
+pub mod repo;
+pub mod transfer;
+
+pub use repo::{Formality, FromRow, Greeting, GreetingRepository, NewGreeting, RepoError};
@@ -1,151 +1,382 @@
-use rusqlite::{Connection, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use greet::language::{self, DEFAULT_LANGUAGE};
+use greet::transfer::{self, FileFormat};
+use greet::{db, Formality, Greeting, GreetingRepository, NewGreeting, RepoError};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
-struct Greeting {
-    id: i32,
-    greeting: String,
+/// output format of greetings.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Csv,
 }
 
-fn insert(conn: &Connection, greeting: Greeting) -> Result<()> {
-    conn.execute(
-        "INSERT INTO greetings (greeting) VALUES (?1)",
-        (&greeting.greeting,),
-    )?;
-    Ok(())
-}
+/// format greetings.
+fn format(greetings: &[Greeting], format: Format) -> Result<String, Box<dyn Error>> {
+    Ok(match format {
+        Format::Table => {
+            let mut rows = vec![[
+                "ID".to_string(),
+                "LANGUAGE".into(),
+                "FORMALITY".into(),
+                "REGION".into(),
+                "USES".into(),
+                "GREETING".into(),
+            ]];
+            for g in greetings {
+                rows.push([
+                    g.id.to_string(),
+                    g.language.clone(),
+                    g.formality.to_string(),
+                    g.region.clone().unwrap_or_else(|| "-".into()),
+                    g.uses.to_string(),
+                    g.greeting.clone(),
+                ]);
+            }
+            let mut widths = [0; 5];
+            for row in &rows {
+                for (width, column) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(column.chars().count());
+                }
+            }
 
-fn list(conn: &Connection) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT id, greeting FROM greetings")?;
-    let greeting_iter = stmt.query_map([], |row| {
-        Ok(Greeting {
-            id: row.get(0)?,
-            greeting: row.get(1)?,
-        })
-    })?;
-
-    for greeting in greeting_iter {
-        let g = greeting.unwrap();
-        println!("list: {} {}", g.id, g.greeting);
-    }
-    Ok(())
+            // numbers are aligned right
+            let mut out = String::new();
+            for [id, language, formality, region, uses, greeting] in &rows {
+                writeln!(
+                    out,
+                    "{:>w0$}  {:w1$}  {:w2$}  {:w3$}  {:>w4$}  {}",
+                    id,
+                    language,
+                    formality,
+                    region,
+                    uses,
+                    greeting,
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2],
+                    w3 = widths[3],
+                    w4 = widths[4]
+                )?;
+            }
+            out
+        }
+        Format::Json => serde_json::to_string_pretty(greetings)? + "\n",
+        Format::Csv => {
+            let mut out = Vec::new();
+            transfer::write_csv(greetings, &mut out)?;
+            String::from_utf8(out)?
+        }
+    })
 }
 
-fn get_id(conn: &Connection, id: i32) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT id, greeting FROM greetings WHERE id = ?1")?;
-    let greeting_iter = stmt.query_map((id,), |row| {
-        Ok(Greeting {
-            id: row.get(0)?,
-            greeting: row.get(1)?,
-        })
-    })?;
-
-    for greeting in greeting_iter {
-        let g = greeting.unwrap();
-        println!("get_id {}: {} {}", id, g.id, g.greeting);
-    }
-    Ok(())
+/// Store greetings in a SQLite database
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Database file, defaults to an in memory database
+    #[clap(long, global = true)]
+    db: Option<PathBuf>,
+    /// Output format of greetings
+    #[clap(long, global = true, value_enum, default_value = "table")]
+    format: Format,
+    #[clap(subcommand)]
+    command: Command,
 }
 
-fn get_greeting(conn: &Connection, greeting: String) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT id, greeting FROM greetings WHERE greeting = ?1")?;
-    let greeting_iter = stmt.query_map((&greeting,), |row| {
-        Ok(Greeting {
-            id: row.get(0)?,
-            greeting: row.get(1)?,
-        })
-    })?;
-
-    for g in greeting_iter {
-        let g = g.unwrap();
-        println!("get_greeting {}: {} {}", greeting, g.id, g.greeting);
-    }
-    Ok(())
+#[derive(Subcommand)]
+enum Command {
+    /// Run some test commands on an in memory database
+    Run,
+    /// List greetings
+    List {
+        /// Maximum number of greetings
+        #[clap(long)]
+        limit: Option<u32>,
+        /// Number of greetings to skip
+        #[clap(long, default_value_t = 0)]
+        offset: u32,
+    },
+    /// Get greeting by id
+    Id { id: i32 },
+    /// Get greetings by text
+    Greeting { greeting: String },
+    /// Add greeting
+    Add {
+        greeting: String,
+        /// BCP 47 language tag, defaults to the language of the locale
+        #[clap(long)]
+        language: Option<String>,
+        #[clap(long, value_enum, default_value = "neutral")]
+        formality: Formality,
+        /// Region where the greeting is used, e.g. "Northern Germany"
+        #[clap(long)]
+        region: Option<String>,
+    },
+    /// Pick the least used greeting in a language and count its use, falls
+    /// back to less specific languages and English
+    Lookup {
+        /// BCP 47 language tag, defaults to the language of the locale
+        #[clap(long)]
+        language: Option<String>,
+        #[clap(long, value_enum)]
+        formality: Option<Formality>,
+    },
+    /// Update text of greeting
+    Update { id: i32, greeting: String },
+    /// Delete greeting
+    Delete { id: i32 },
+    /// Search greetings
+    Search {
+        /// LIKE pattern, e.g. "h%", or full-text query with --fts
+        pattern: String,
+        /// Use a FTS5 full-text query, e.g. "good OR morning"
+        #[clap(long)]
+        fts: bool,
+    },
+    /// Import greetings from a file in a single transaction
+    Import {
+        /// File to import, "-" for stdin
+        file: PathBuf,
+        /// Format of the file, guessed from its extension by default
+        #[clap(long, value_enum)]
+        file_format: Option<FileFormat>,
+        /// Import the valid records if some records are invalid
+        #[clap(long)]
+        skip_invalid: bool,
+    },
+    /// Export all greetings to a file
+    Export {
+        /// File to export to, "-" for stdout
+        file: PathBuf,
+        /// Format of the file, guessed from its extension by default
+        #[clap(long, value_enum)]
+        file_format: Option<FileFormat>,
+    },
+    /// Copy the database to a file with the online backup API
+    Backup { file: PathBuf },
 }
 
-fn delete_all(conn: &Connection) -> Result<()> {
-    conn.execute("DELETE FROM greetings", ())?;
-    Ok(())
+/// language of the locale or the default language.
+fn default_language() -> String {
+    language::locale().unwrap_or_else(|| DEFAULT_LANGUAGE.into())
 }
 
-fn print_usage() {
-    print! {"Usage:
-  run                       run some test commands
-  list                      list greetings
-  id <id>                   get greeting by id
-  greeting <greeting>       get greeting
-"}
+/// format given on the command line or guessed from the extension of file.
+fn file_format(file: &Path, format: Option<FileFormat>) -> Result<FileFormat, Box<dyn Error>> {
+    match format.or_else(|| FileFormat::from_path(file)) {
+        Some(format) => Ok(format),
+        None => Err(format!("{}: unknown file format, use --file-format", file.display()).into()),
+    }
 }
 
-fn main() -> Result<()> {
-    // open in memory db
-    let conn = Connection::open_in_memory()?;
-
-    // create table
-    conn.execute(
-        "CREATE TABLE greetings (
-            id        INTEGER PRIMARY KEY,
-            greeting  TEXT NOT NULL
-        )",
-        (),
-    )?;
-
-    // insert greetings
-    let greetings = vec![
-        Greeting {
-            id: 0,
-            greeting: "hello".to_string(),
-        },
-        Greeting {
-            id: 0,
-            greeting: "hi".to_string(),
-        },
-        Greeting {
-            id: 0,
-            greeting: "good day".to_string(),
-        },
-    ];
-    for g in greetings {
-        insert(&conn, g)?;
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    // open db and migrate schema, run deletes all greetings and always uses
+    // an in memory db
+    let path = match cli.command {
+        Command::Run => None,
+        _ => cli.db.as_deref(),
+    };
+    let mut conn = db::open(path)?;
+    let previous = db::migrate(&mut conn)?;
+    let mut repo = GreetingRepository::new(conn);
+
+    // insert greetings into new db
+    if previous == 0 {
+        let greeting =
+            |greeting: &str, language: &str, formality, region: Option<&str>| NewGreeting {
+                greeting: greeting.into(),
+                language: language.into(),
+                formality,
+                region: region.map(Into::into),
+            };
+        repo.insert_all([
+            greeting("hello", "en", Formality::Neutral, None),
+            greeting("hi", "en", Formality::Informal, None),
+            greeting("good day", "en", Formality::Formal, None),
+            greeting("Hallo", "de", Formality::Neutral, None),
+            greeting("Guten Tag", "de", Formality::Formal, None),
+            greeting("Moin", "de", Formality::Informal, Some("Northern Germany")),
+            greeting("Servus", "de-AT", Formality::Informal, None),
+            greeting("Grüß Gott", "de-AT", Formality::Formal, None),
+            greeting("Bonjour", "fr", Formality::Neutral, None),
+            greeting("Salut", "fr", Formality::Informal, None),
+        ])?;
     }
 
-    // command line arguments
-    match std::env::args().nth(1).as_deref() {
-        Some("run") => {
+    let print = |greetings: &[Greeting]| -> Result<(), Box<dyn Error>> {
+        print!("{}", format(greetings, cli.format)?);
+        Ok(())
+    };
+    match cli.command {
+        Command::Run => {
             // list
-            list(&conn)?;
+            print(&repo.list(None, 0)?)?;
 
             // get id
-            get_id(&conn, 1)?;
+            print(Vec::from_iter(repo.get(1)?).as_slice())?;
 
             // get greeting
-            get_greeting(&conn, "hi".to_string())?;
+            print(&repo.find_by_text("hi")?)?;
 
             // delete all
-            delete_all(&conn)?;
+            repo.delete_all()?;
 
             // list
-            list(&conn)?;
+            print(&repo.list(None, 0)?)?;
+        }
+        Command::List { limit, offset } => print(&repo.list(limit, offset)?)?,
+        Command::Id { id } => print(&[repo.get(id)?.ok_or(RepoError::NotFound(id))?])?,
+        Command::Greeting { greeting } => print(&repo.find_by_text(&greeting)?)?,
+        Command::Add {
+            greeting,
+            language,
+            formality,
+            region,
+        } => {
+            let greeting = NewGreeting {
+                greeting,
+                language: language.unwrap_or_else(default_language),
+                formality,
+                region,
+            };
+            print(&[repo.insert(&greeting)?])?;
         }
-        Some("list") => {
-            list(&conn)?;
+        Command::Lookup {
+            language,
+            formality,
+        } => {
+            let language = language.unwrap_or_else(default_language);
+            let Some(greeting) = repo.lookup(&language, formality)? else {
+                return Err(format!("no greeting for language {}", language).into());
+            };
+            print(&[repo.record_use(greeting.id)?])?;
         }
-        Some("id") => {
-            if let Some(s) = std::env::args().nth(2)
-                && let Ok(id) = s.parse::<i32>()
-            {
-                get_id(&conn, id)?;
+        Command::Update { id, greeting } => print(&[repo.update(id, &greeting)?])?,
+        Command::Delete { id } => repo.delete(id)?,
+        Command::Search { pattern, fts } => print(&repo.search(&pattern, fts)?)?,
+        Command::Import {
+            file,
+            file_format: format,
+            skip_invalid,
+        } => {
+            let format = file_format(&file, format)?;
+            let reader: Box<dyn Read> = if file == Path::new("-") {
+                Box::new(io::stdin().lock())
             } else {
-                print_usage()
+                Box::new(
+                    fs::File::open(&file).map_err(|err| format!("{}: {}", file.display(), err))?,
+                )
+            };
+            let import = transfer::import(&mut repo, reader, format, skip_invalid, |n| {
+                if n % 1000 == 0 {
+                    eprint!("\rimported {} greetings", n);
+                }
+            })?;
+            for err in &import.errors {
+                eprintln!("\r{}: {}", file.display(), err);
             }
+            if !import.errors.is_empty() && !skip_invalid {
+                return Err(format!(
+                    "{} invalid records, nothing imported, use --skip-invalid to import the valid records",
+                    import.errors.len()
+                )
+                .into());
+            }
+            eprintln!("\rimported {} greetings", import.imported.len());
         }
-        Some("greeting") => {
-            if let Some(greeting) = std::env::args().nth(2) {
-                get_greeting(&conn, greeting)?;
+        Command::Export {
+            file,
+            file_format: format,
+        } => {
+            let format = file_format(&file, format)?;
+            let mut out = Vec::new();
+            transfer::export(&repo, &mut out, format)?;
+            if file == Path::new("-") {
+                print!("{}", String::from_utf8(out)?);
             } else {
-                print_usage()
+                // replace existing file only with a complete export
+                let mut tmp = file.clone().into_os_string();
+                tmp.push(".tmp");
+                fs::write(&tmp, out).map_err(|err| format!("{}: {}", file.display(), err))?;
+                fs::rename(&tmp, &file).map_err(|err| format!("{}: {}", file.display(), err))?;
             }
         }
-        _ => print_usage(),
+        Command::Backup { file } => db::backup(repo.connection(), &file, None)?,
     }
 
     Ok(())
 }
+
+fn main() {
+    // print errors with their message instead of their debug representation
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let greetings = [
+            Greeting {
+                id: 1,
+                greeting: "hello".into(),
+                language: "en".into(),
+                formality: Formality::Neutral,
+                region: None,
+                uses: 0,
+            },
+            Greeting {
+                id: 100,
+                greeting: "Moin, \"Digga\"".into(),
+                language: "de".into(),
+                formality: Formality::Informal,
+                region: Some("Northern Germany".into()),
+                uses: 12,
+            },
+        ];
+        for (format, greetings, want) in [
+            (
+                Format::Table,
+                &greetings[..],
+                " ID  LANGUAGE  FORMALITY  REGION            USES  GREETING\n  \
+                   1  en        neutral    -                    0  hello\n\
+                 100  de        informal   Northern Germany    12  Moin, \"Digga\"\n",
+            ),
+            (
+                Format::Table,
+                &[],
+                "ID  LANGUAGE  FORMALITY  REGION  USES  GREETING\n",
+            ),
+            (
+                Format::Json,
+                &greetings[..1],
+                "[\n  {\n    \"id\": 1,\n    \"greeting\": \"hello\",\n    \"language\": \"en\",\n    \
+                 \"formality\": \"neutral\",\n    \"region\": null,\n    \"uses\": 0\n  }\n]\n",
+            ),
+            (
+                Format::Csv,
+                &greetings[..],
+                "id,greeting,language,formality,region,uses\n1,hello,en,neutral,,0\n\
+                 100,\"Moin, \"\"Digga\"\"\",de,informal,Northern Germany,12\n",
+            ),
+            (
+                Format::Csv,
+                &[],
+                "id,greeting,language,formality,region,uses\n",
+            ),
+        ] {
+            assert_eq!(super::format(greetings, format).unwrap(), want);
+        }
+    }
+}
@@ -0,0 +1,578 @@
+use crate::language::{self, DEFAULT_LANGUAGE};
+use clap::ValueEnum;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{Connection, ErrorCode, Params, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// how formal a greeting is.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Formality {
+    Informal,
+    #[default]
+    Neutral,
+    Formal,
+}
+
+impl Formality {
+    fn as_str(self) -> &'static str {
+        match self {
+            Formality::Informal => "informal",
+            Formality::Neutral => "neutral",
+            Formality::Formal => "formal",
+        }
+    }
+}
+
+impl fmt::Display for Formality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql for Formality {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for Formality {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "informal" => Ok(Formality::Informal),
+            "neutral" => Ok(Formality::Neutral),
+            "formal" => Ok(Formality::Formal),
+            other => Err(FromSqlError::Other(
+                format!("invalid formality {:?}", other).into(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Greeting {
+    pub id: i32,
+    pub greeting: String,
+    /// BCP 47 language tag like "de-AT"
+    pub language: String,
+    pub formality: Formality,
+    /// region within the language area where the greeting is used, like
+    /// "Northern Germany"
+    pub region: Option<String>,
+    /// number of lookups which picked the greeting
+    pub uses: i64,
+}
+
+/// greeting to insert, missing fields of imported greetings get the
+/// defaults.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct NewGreeting {
+    pub greeting: String,
+    #[serde(default = "default_language")]
+    pub language: String,
+    #[serde(default)]
+    pub formality: Formality,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+fn default_language() -> String {
+    DEFAULT_LANGUAGE.to_string()
+}
+
+impl From<&str> for NewGreeting {
+    /// neutral greeting in the default language.
+    fn from(greeting: &str) -> Self {
+        NewGreeting {
+            greeting: greeting.to_string(),
+            language: default_language(),
+            formality: Formality::default(),
+            region: None,
+        }
+    }
+}
+
+/// map a result row to a value.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> rusqlite::Result<Self>;
+}
+
+/// columns of greetings in the order of `Greeting::from_row`.
+const COLUMNS: &str = "id, greeting, language, formality, region, uses";
+
+impl FromRow for Greeting {
+    /// map row with the greeting columns.
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Greeting {
+            id: row.get(0)?,
+            greeting: row.get(1)?,
+            language: row.get(2)?,
+            formality: row.get(3)?,
+            region: row.get(4)?,
+            uses: row.get(5)?,
+        })
+    }
+}
+
+/// error of a repository operation.
+#[derive(Debug)]
+pub enum RepoError {
+    /// no greeting with id
+    NotFound(i32),
+    /// the language is not a well-formed BCP 47 tag
+    InvalidLanguage(String),
+    /// a constraint of the schema was violated
+    Constraint(rusqlite::Error),
+    /// the database file could not be opened, read or written
+    Io(rusqlite::Error),
+    /// any other sqlite error
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepoError::NotFound(id) => write!(f, "no greeting with id {}", id),
+            RepoError::InvalidLanguage(tag) => write!(f, "invalid language tag {:?}", tag),
+            RepoError::Constraint(err) => write!(f, "constraint violated: {}", err),
+            RepoError::Io(err) => write!(f, "i/o error: {}", err),
+            RepoError::Sqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for RepoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepoError::NotFound(_) | RepoError::InvalidLanguage(_) => None,
+            RepoError::Constraint(err) | RepoError::Io(err) | RepoError::Sqlite(err) => Some(err),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RepoError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(ErrorCode::ConstraintViolation) => RepoError::Constraint(err),
+            Some(
+                ErrorCode::SystemIoFailure
+                | ErrorCode::CannotOpen
+                | ErrorCode::DiskFull
+                | ErrorCode::ReadOnly
+                | ErrorCode::PermissionDenied
+                | ErrorCode::DatabaseBusy
+                | ErrorCode::DatabaseLocked,
+            ) => RepoError::Io(err),
+            _ => RepoError::Sqlite(err),
+        }
+    }
+}
+
+pub type Result<T, E = RepoError> = std::result::Result<T, E>;
+
+/// greetings stored in a migrated database, see `db::migrate`. statements
+/// are cached by the connection.
+pub struct GreetingRepository {
+    conn: Connection,
+}
+
+impl GreetingRepository {
+    pub fn new(conn: Connection) -> Self {
+        GreetingRepository { conn }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// run query with cached statement and map all rows.
+    fn query<T: FromRow, P: Params>(&self, sql: &str, params: P) -> Result<Vec<T>> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let rows = stmt.query_map(params, T::from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// insert greeting and return it with its new id. the language is
+    /// normalized.
+    pub fn insert(&self, greeting: &NewGreeting) -> Result<Greeting> {
+        insert(&self.conn, greeting)
+    }
+
+    /// insert greetings in a single transaction, either all or none of the
+    /// greetings are inserted.
+    pub fn insert_all<I>(&mut self, greetings: I) -> Result<Vec<Greeting>>
+    where
+        I: IntoIterator,
+        I::Item: Into<NewGreeting>,
+    {
+        let tx = self.conn.transaction()?;
+        let inserted = greetings
+            .into_iter()
+            .map(|greeting| insert(&tx, &greeting.into()))
+            .collect::<Result<_>>()?;
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// list greetings ordered by id, skipping offset greetings and returning
+    /// at most limit greetings.
+    pub fn list(&self, limit: Option<u32>, offset: u32) -> Result<Vec<Greeting>> {
+        // a negative limit means no limit
+        let limit = limit.map_or(-1, i64::from);
+        self.query(
+            &format!(
+                "SELECT {} FROM greetings ORDER BY id LIMIT ?1 OFFSET ?2",
+                COLUMNS
+            ),
+            (limit, offset),
+        )
+    }
+
+    pub fn get(&self, id: i32) -> Result<Option<Greeting>> {
+        let greetings = self.query(
+            &format!("SELECT {} FROM greetings WHERE id = ?1", COLUMNS),
+            (id,),
+        )?;
+        Ok(greetings.into_iter().next())
+    }
+
+    pub fn find_by_text(&self, greeting: &str) -> Result<Vec<Greeting>> {
+        self.query(
+            &format!(
+                "SELECT {} FROM greetings WHERE greeting = ?1 ORDER BY id",
+                COLUMNS
+            ),
+            (greeting,),
+        )
+    }
+
+    /// search greetings with a LIKE pattern or a FTS5 full-text query,
+    /// full-text results are ordered by relevance.
+    pub fn search(&self, pattern: &str, fts: bool) -> Result<Vec<Greeting>> {
+        let sql = if fts {
+            format!(
+                "SELECT {} FROM greetings
+                JOIN (SELECT rowid, rank FROM greetings_fts WHERE greetings_fts MATCH ?1) AS fts
+                ON fts.rowid = greetings.id ORDER BY fts.rank",
+                COLUMNS
+            )
+        } else {
+            format!(
+                "SELECT {} FROM greetings WHERE greeting LIKE ?1 ORDER BY id",
+                COLUMNS
+            )
+        };
+        self.query(&sql, (pattern,))
+    }
+
+    /// update text of greeting and return the updated greeting.
+    pub fn update(&self, id: i32, greeting: &str) -> Result<Greeting> {
+        self.query(
+            &format!(
+                "UPDATE greetings SET greeting = ?2 WHERE id = ?1 RETURNING {}",
+                COLUMNS
+            ),
+            (id, greeting),
+        )?
+        .pop()
+        .ok_or(RepoError::NotFound(id))
+    }
+
+    /// least used greeting in the language of tag or its fallbacks, see
+    /// `language::fallbacks`, optionally with the given formality.
+    pub fn lookup(&self, tag: &str, formality: Option<Formality>) -> Result<Option<Greeting>> {
+        let tag = language::normalize(tag).ok_or_else(|| RepoError::InvalidLanguage(tag.into()))?;
+        for language in language::fallbacks(&tag, DEFAULT_LANGUAGE) {
+            let greetings = self.query(
+                &format!(
+                    "SELECT {} FROM greetings WHERE language = ?1
+                    AND (?2 IS NULL OR formality = ?2) ORDER BY uses, id LIMIT 1",
+                    COLUMNS
+                ),
+                (&language, formality),
+            )?;
+            if let Some(greeting) = greetings.into_iter().next() {
+                return Ok(Some(greeting));
+            }
+        }
+        Ok(None)
+    }
+
+    /// count use of greeting and return the updated greeting.
+    pub fn record_use(&self, id: i32) -> Result<Greeting> {
+        self.query(
+            &format!(
+                "UPDATE greetings SET uses = uses + 1 WHERE id = ?1 RETURNING {}",
+                COLUMNS
+            ),
+            (id,),
+        )?
+        .pop()
+        .ok_or(RepoError::NotFound(id))
+    }
+
+    pub fn delete(&self, id: i32) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM greetings WHERE id = ?1")?;
+        match stmt.execute((id,))? {
+            0 => Err(RepoError::NotFound(id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// delete all greetings and return their number.
+    pub fn delete_all(&self) -> Result<usize> {
+        Ok(self.conn.execute("DELETE FROM greetings", ())?)
+    }
+}
+
+/// insert greeting with connection or transaction.
+fn insert(conn: &Connection, greeting: &NewGreeting) -> Result<Greeting> {
+    let language = language::normalize(&greeting.language)
+        .ok_or_else(|| RepoError::InvalidLanguage(greeting.language.clone()))?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "INSERT INTO greetings (greeting, language, formality, region)
+        VALUES (?1, ?2, ?3, ?4) RETURNING {}",
+        COLUMNS
+    ))?;
+    Ok(stmt.query_row(
+        (
+            &greeting.greeting,
+            language,
+            greeting.formality,
+            &greeting.region,
+        ),
+        Greeting::from_row,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    /// open repository of migrated database in a temporary file with
+    /// greetings.
+    fn open(name: &str, greetings: &[&str]) -> (GreetingRepository, PathBuf) {
+        let path = std::env::temp_dir().join(format!("greet-{}-{}.db", name, process::id()));
+        remove(&path);
+        let mut conn = db::open(Some(&path)).unwrap();
+        db::migrate(&mut conn).unwrap();
+        let mut repo = GreetingRepository::new(conn);
+        repo.insert_all(greetings.iter().copied()).unwrap();
+        (repo, path)
+    }
+
+    fn remove(path: &Path) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn ids(greetings: Vec<Greeting>) -> Vec<i32> {
+        greetings.iter().map(|g| g.id).collect()
+    }
+
+    #[test]
+    fn test_crud() {
+        let (repo, path) = open("crud", &["hello", "hi", "good day"]);
+
+        assert_eq!(ids(repo.list(None, 0).unwrap()), [1, 2, 3]);
+        assert_eq!(
+            repo.get(2).unwrap(),
+            Some(Greeting {
+                id: 2,
+                greeting: "hi".into(),
+                language: "en".into(),
+                formality: Formality::Neutral,
+                region: None,
+                uses: 0,
+            })
+        );
+        assert_eq!(repo.get(4).unwrap(), None);
+
+        assert_eq!(repo.update(2, "hey").unwrap().greeting, "hey");
+        assert!(matches!(repo.update(4, "hey"), Err(RepoError::NotFound(4))));
+        assert_eq!(ids(repo.find_by_text("hey").unwrap()), [2]);
+        assert!(repo.find_by_text("hi").unwrap().is_empty());
+
+        repo.delete(1).unwrap();
+        assert!(matches!(repo.delete(1), Err(RepoError::NotFound(1))));
+        assert_eq!(ids(repo.list(None, 0).unwrap()), [2, 3]);
+
+        assert_eq!(repo.delete_all().unwrap(), 2);
+        assert!(repo.list(None, 0).unwrap().is_empty());
+
+        drop(repo);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_insert_all() {
+        let (mut repo, path) = open("insert", &["hello"]);
+        let inserted = repo.insert_all(["hi", "good day"]).unwrap();
+        assert_eq!(ids(inserted), [2, 3]);
+
+        // a failing insert rolls back the whole batch
+        repo.connection()
+            .execute_batch(
+                "CREATE TRIGGER no_empty BEFORE INSERT ON greetings WHEN new.greeting = ''
+                BEGIN SELECT RAISE(ABORT, 'empty greeting'); END;",
+            )
+            .unwrap();
+        let err = repo.insert_all(["hey", "", "howdy"]).unwrap_err();
+        assert!(matches!(err, RepoError::Constraint(_)), "{:?}", err);
+        assert_eq!(ids(repo.list(None, 0).unwrap()), [1, 2, 3]);
+
+        drop(repo);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_list() {
+        let (repo, path) = open("list", &["a", "b", "c", "d", "e"]);
+        for (limit, offset, want) in [
+            (None, 0, vec![1, 2, 3, 4, 5]),
+            (Some(2), 0, vec![1, 2]),
+            (Some(2), 2, vec![3, 4]),
+            (Some(2), 4, vec![5]),
+            (None, 3, vec![4, 5]),
+            (Some(2), 6, vec![]),
+        ] {
+            assert_eq!(ids(repo.list(limit, offset).unwrap()), want);
+        }
+        drop(repo);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_search() {
+        let (repo, path) = open("search", &["hello", "hi", "good day", "good morning"]);
+        // update and delete are reflected in the full-text index
+        repo.update(2, "hi there").unwrap();
+        repo.delete(1).unwrap();
+
+        for (pattern, fts, want) in [
+            ("h%", false, vec![2]),
+            ("%good%", false, vec![3, 4]),
+            ("%DAY", false, vec![3]),
+            ("hello", false, vec![]),
+            ("good", true, vec![3, 4]),
+            ("day OR there", true, vec![2, 3]),
+            ("mor*", true, vec![4]),
+            ("hello", true, vec![]),
+        ] {
+            let mut got = ids(repo.search(pattern, fts).unwrap());
+            got.sort();
+            assert_eq!(got, want, "{} {}", pattern, fts);
+        }
+        assert!(matches!(
+            repo.search("\"unbalanced", true),
+            Err(RepoError::Sqlite(_))
+        ));
+        drop(repo);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let (mut repo, path) = open("lookup", &["hello"]);
+        let greeting =
+            |greeting: &str, language: &str, formality, region: Option<&str>| NewGreeting {
+                greeting: greeting.into(),
+                language: language.into(),
+                formality,
+                region: region.map(Into::into),
+            };
+        repo.insert_all([
+            greeting("Hallo", "DE", Formality::Neutral, None),
+            greeting("Guten Tag", "de", Formality::Formal, None),
+            greeting("Moin", "de", Formality::Informal, Some("Northern Germany")),
+            greeting("Servus", "de_at", Formality::Informal, None),
+            greeting("Good day, mate", "en-AU", Formality::Informal, None),
+        ])
+        .unwrap();
+        assert_eq!(repo.get(5).unwrap().unwrap().language, "de-AT");
+        assert_eq!(
+            repo.get(4).unwrap().unwrap().region.as_deref(),
+            Some("Northern Germany")
+        );
+
+        for (tag, formality, want) in [
+            ("de-AT", None, Some("Servus")),
+            ("de-AT", Some(Formality::Formal), Some("Guten Tag")),
+            ("de-CH", None, Some("Hallo")),
+            ("de", Some(Formality::Informal), Some("Moin")),
+            ("en-AU", None, Some("Good day, mate")),
+            ("en-GB", None, Some("hello")),
+            ("fr-CA", None, Some("hello")),
+            ("fr", Some(Formality::Formal), None),
+        ] {
+            let got = repo.lookup(tag, formality).unwrap();
+            assert_eq!(
+                got.as_ref().map(|g| g.greeting.as_str()),
+                want,
+                "{} {:?}",
+                tag,
+                formality
+            );
+        }
+
+        // the least used greeting is picked
+        for want in ["Hallo", "Guten Tag", "Moin", "Hallo"] {
+            let greeting = repo.lookup("de", None).unwrap().unwrap();
+            assert_eq!(greeting.greeting, want);
+            assert_eq!(
+                repo.record_use(greeting.id).unwrap().uses,
+                greeting.uses + 1
+            );
+        }
+        assert!(matches!(repo.record_use(9), Err(RepoError::NotFound(9))));
+
+        // usage counters do not touch the full-text index
+        assert_eq!(ids(repo.search("moin", true).unwrap()), [4]);
+
+        assert!(matches!(
+            repo.lookup("de--AT", None),
+            Err(RepoError::InvalidLanguage(_))
+        ));
+        assert!(matches!(
+            repo.insert(&greeting("Hi", "e", Formality::Neutral, None)),
+            Err(RepoError::InvalidLanguage(_))
+        ));
+
+        drop(repo);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_error() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1);")
+            .unwrap();
+        let constraint = conn.execute("INSERT INTO t VALUES (1)", ()).unwrap_err();
+        let io = Connection::open("/nonexistent/greet.db").unwrap_err();
+        let other = conn.execute("SELECT * FROM missing", ()).unwrap_err();
+
+        for (err, want) in [
+            (RepoError::NotFound(7), "no greeting with id 7"),
+            (
+                constraint.into(),
+                "constraint violated: UNIQUE constraint failed: t.id",
+            ),
+            (
+                io.into(),
+                "i/o error: unable to open database file: /nonexistent/greet.db",
+            ),
+            (other.into(), "no such table: missing"),
+        ] {
+            assert_eq!(err.to_string(), want);
+        }
+    }
+}
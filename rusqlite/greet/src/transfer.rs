@@ -0,0 +1,401 @@
+use crate::language;
+use crate::repo::{Greeting, GreetingRepository, NewGreeting};
+use clap::ValueEnum;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// format of imported and exported greeting files.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum FileFormat {
+    Csv,
+    Json,
+    Ndjson,
+    /// sql script like the .dump command of the sqlite3 shell, only for
+    /// export
+    Sql,
+}
+
+impl FileFormat {
+    /// guess format from the extension of path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(FileFormat::Csv),
+            "json" => Some(FileFormat::Json),
+            "ndjson" | "jsonl" => Some(FileFormat::Ndjson),
+            "sql" => Some(FileFormat::Sql),
+            _ => None,
+        }
+    }
+}
+
+/// invalid record of an imported file.
+#[derive(Debug, PartialEq)]
+pub struct RecordError {
+    /// number of the record starting at 1
+    pub record: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {}: {}", self.record, self.message)
+    }
+}
+
+/// result of an import.
+#[derive(Debug, Default, PartialEq)]
+pub struct Import {
+    pub imported: Vec<Greeting>,
+    pub errors: Vec<RecordError>,
+}
+
+/// parse records of reader, errors of single records are returned in place
+/// of the record. other fields than those of `NewGreeting` like the id are
+/// ignored, ids are assigned by the importing database.
+fn records<R: Read>(
+    reader: R,
+    format: FileFormat,
+) -> Result<Vec<Result<NewGreeting, String>>, Box<dyn Error>> {
+    Ok(match format {
+        FileFormat::Csv => csv::Reader::from_reader(reader)
+            .deserialize()
+            .map(|record| record.map_err(|err: csv::Error| err.to_string()))
+            .collect(),
+        // the document has to be a valid json array, its elements are
+        // checked one by one
+        FileFormat::Json => serde_json::from_reader::<_, Vec<serde_json::Value>>(reader)?
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+            .collect(),
+        FileFormat::Ndjson => {
+            let mut records = Vec::new();
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                records.push(serde_json::from_str(&line).map_err(|err| err.to_string()));
+            }
+            records
+        }
+        FileFormat::Sql => return Err("sql scripts can only be exported".into()),
+    })
+}
+
+/// import greetings of reader in a single transaction. if any record is
+/// invalid nothing is imported, unless skip_invalid is set, then only the
+/// valid records are imported. progress is called with the number of
+/// imported greetings after each insert.
+pub fn import<R: Read>(
+    repo: &mut GreetingRepository,
+    reader: R,
+    format: FileFormat,
+    skip_invalid: bool,
+    mut progress: impl FnMut(usize),
+) -> Result<Import, Box<dyn Error>> {
+    let mut import = Import::default();
+    let mut greetings = Vec::new();
+    for (i, record) in records(reader, format)?.into_iter().enumerate() {
+        let record = record.and_then(|record| match language::normalize(&record.language) {
+            Some(_) => Ok(record),
+            None => Err(format!("invalid language tag {:?}", record.language)),
+        });
+        match record {
+            Ok(record) => greetings.push(record),
+            Err(message) => import.errors.push(RecordError {
+                record: i + 1,
+                message,
+            }),
+        }
+    }
+    if !import.errors.is_empty() && !skip_invalid {
+        return Ok(import);
+    }
+
+    let mut n = 0;
+    import.imported = repo.insert_all(greetings.into_iter().inspect(|_| {
+        n += 1;
+        progress(n);
+    }))?;
+    Ok(import)
+}
+
+/// quote sql identifier.
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// format bytes as sql blob literal.
+fn blob(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut hex = String::new();
+    for byte in bytes {
+        write!(hex, "{:02X}", byte)?;
+    }
+    Ok(format!("X'{}'", hex))
+}
+
+/// format value as sql literal. infinite reals and text which is not valid
+/// UTF-8 are written like the .dump command of sqlite does.
+fn literal(value: ValueRef) -> Result<String, Box<dyn Error>> {
+    Ok(match value {
+        ValueRef::Null => "NULL".into(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) if f == f64::INFINITY => "1e999".into(),
+        ValueRef::Real(f) if f == f64::NEG_INFINITY => "-1e999".into(),
+        // debug formatting keeps the decimal point of integral floats
+        ValueRef::Real(f) => format!("{:?}", f),
+        ValueRef::Text(text) => match std::str::from_utf8(text) {
+            Ok(text) => format!("'{}'", text.replace('\'', "''")),
+            Err(_) => format!("CAST({} AS TEXT)", blob(text)?),
+        },
+        ValueRef::Blob(bytes) => blob(bytes)?,
+    })
+}
+
+/// write sql script which recreates the schema and the rows of all tables
+/// including the schema version. the tables of full-text indexes are not
+/// dumped, the indexes are filled by the triggers of the content tables.
+pub fn dump<W: Write>(conn: &Connection, mut writer: W) -> Result<(), Box<dyn Error>> {
+    writeln!(writer, "PRAGMA foreign_keys=OFF;")?;
+    writeln!(writer, "BEGIN TRANSACTION;")?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name, sql FROM sqlite_schema
+        WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+        AND name NOT IN (SELECT name FROM pragma_table_list WHERE type = 'shadow')
+        ORDER BY rowid",
+    )?;
+    let schema = stmt
+        .query_map((), |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    for (_, sql) in &schema {
+        writeln!(writer, "{};", sql)?;
+    }
+
+    // rows are inserted after all triggers exist, so the triggers keep the
+    // full-text indexes up to date
+    for (name, sql) in &schema {
+        if !sql.starts_with("CREATE TABLE") {
+            continue;
+        }
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {}", identifier(name)))?;
+        let columns = stmt.column_count();
+        let mut rows = stmt.query(())?;
+        while let Some(row) = rows.next()? {
+            let values = (0..columns)
+                .map(|i| literal(row.get_ref(i)?))
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+            writeln!(
+                writer,
+                "INSERT INTO {} VALUES({});",
+                identifier(name),
+                values.join(",")
+            )?;
+        }
+    }
+
+    let version = crate::db::version(conn)?;
+    writeln!(writer, "PRAGMA user_version={};", version)?;
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
+
+/// write greetings as csv with a header.
+pub fn write_csv<W: Write>(greetings: &[Greeting], writer: W) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    // the header is only written with the first record
+    if greetings.is_empty() {
+        writer.write_record(["id", "greeting", "language", "formality", "region", "uses"])?;
+    }
+    for greeting in greetings {
+        writer.serialize(greeting)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// export all greetings to writer.
+pub fn export<W: Write>(
+    repo: &GreetingRepository,
+    mut writer: W,
+    format: FileFormat,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        FileFormat::Csv => write_csv(&repo.list(None, 0)?, &mut writer)?,
+        FileFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &repo.list(None, 0)?)?;
+            writeln!(writer)?;
+        }
+        FileFormat::Ndjson => {
+            for greeting in repo.list(None, 0)? {
+                serde_json::to_writer(&mut writer, &greeting)?;
+                writeln!(writer)?;
+            }
+        }
+        FileFormat::Sql => dump(repo.connection(), &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repo::Formality;
+
+    fn repo(greetings: &[&str]) -> GreetingRepository {
+        let mut conn = db::open(None).unwrap();
+        db::migrate(&mut conn).unwrap();
+        let mut repo = GreetingRepository::new(conn);
+        repo.insert_all(greetings.iter().copied()).unwrap();
+        repo
+    }
+
+    fn texts(repo: &GreetingRepository) -> Vec<String> {
+        repo.list(None, 0)
+            .unwrap()
+            .into_iter()
+            .map(|g| g.greeting)
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let greetings = ["hello", "good day, \"friend\"", "it's\nme"];
+        let exported = repo(&greetings);
+        let moin = NewGreeting {
+            greeting: "Moin".into(),
+            language: "de".into(),
+            formality: Formality::Informal,
+            region: Some("Northern Germany".into()),
+        };
+        exported.insert(&moin).unwrap();
+        for format in [FileFormat::Csv, FileFormat::Json, FileFormat::Ndjson] {
+            let mut buf = Vec::new();
+            export(&exported, &mut buf, format).unwrap();
+
+            // ids are assigned by the importing database
+            let mut imported = repo(&["hi"]);
+            let mut calls = Vec::new();
+            let import = import(&mut imported, &buf[..], format, false, |n| calls.push(n)).unwrap();
+            assert_eq!(import.errors, [], "{:?}", format);
+            assert_eq!(
+                import.imported.iter().map(|g| g.id).collect::<Vec<_>>(),
+                [2, 3, 4, 5]
+            );
+            assert_eq!(calls, [1, 2, 3, 4]);
+            assert_eq!(
+                texts(&imported),
+                ["hi", "hello", "good day, \"friend\"", "it's\nme", "Moin"]
+            );
+            let moin = &import.imported[3];
+            assert_eq!(moin.language, "de");
+            assert_eq!(moin.formality, Formality::Informal);
+            assert_eq!(moin.region.as_deref(), Some("Northern Germany"));
+            assert_eq!(import.imported[0].region, None);
+        }
+    }
+
+    #[test]
+    fn test_import_errors() {
+        for (format, input) in [
+            (FileFormat::Csv, "greeting\nhello\n\"hi\",\"there\"\n"),
+            (FileFormat::Csv, "id,greeting\n1,hello\n2\n"),
+            (
+                FileFormat::Json,
+                r#"[{"greeting": "hello"}, {"text": "hi"}, {"greeting": 1}]"#,
+            ),
+            (
+                FileFormat::Ndjson,
+                "{\"greeting\": \"hello\"}\n\n{\"greeting\": \"hi\"\n",
+            ),
+            (
+                FileFormat::Csv,
+                "greeting,language,formality\nhello,en,neutral\nhi,en-,informal\nhey,en,casual\n",
+            ),
+        ] {
+            // nothing is imported unless invalid records are skipped
+            let mut repo = repo(&[]);
+            let import = import(&mut repo, input.as_bytes(), format, false, |_| {}).unwrap();
+            assert!(import.imported.is_empty());
+            assert_eq!(import.errors[0].record, 2, "{:?}", import.errors);
+            assert!(texts(&repo).is_empty());
+
+            let skipped = super::import(&mut repo, input.as_bytes(), format, true, |_| {}).unwrap();
+            assert_eq!(skipped.errors, import.errors);
+            assert_eq!(texts(&repo), ["hello"]);
+        }
+
+        // a broken json document is not split into records
+        let mut repo = repo(&[]);
+        assert!(import(&mut repo, &b"[{"[..], FileFormat::Json, true, |_| {}).is_err());
+        assert!(import(&mut repo, &b""[..], FileFormat::Sql, true, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_dump() {
+        let repo = repo(&["hello", "it's me"]);
+        repo.connection()
+            .execute_batch(
+                "CREATE TABLE t (i INTEGER, r REAL, b BLOB, n);
+                INSERT INTO t VALUES (-1, 2.0, X'00ff', NULL);
+                INSERT INTO t VALUES (0, 9e999, X'', CAST(X'ff' AS TEXT));
+                INSERT INTO t VALUES (1, -9e999, NULL, 'ok');",
+            )
+            .unwrap();
+        let mut buf = Vec::new();
+        export(&repo, &mut buf, FileFormat::Sql).unwrap();
+        let script = String::from_utf8(buf).unwrap();
+        assert!(script.starts_with("PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n"));
+        assert!(script.ends_with(&format!(
+            "INSERT INTO \"greetings\" VALUES(1,'hello','en','neutral',NULL,0);\n\
+            INSERT INTO \"greetings\" VALUES(2,'it''s me','en','neutral',NULL,0);\n\
+            INSERT INTO \"t\" VALUES(-1,2.0,X'00FF',NULL);\n\
+            INSERT INTO \"t\" VALUES(0,1e999,X'',CAST(X'FF' AS TEXT));\n\
+            INSERT INTO \"t\" VALUES(1,-1e999,NULL,'ok');\n\
+            PRAGMA user_version={};\n\
+            COMMIT;\n",
+            db::version(repo.connection()).unwrap()
+        )));
+        assert!(!script.contains("greetings_fts_data"));
+
+        // the restored database is up to date and has a full-text index
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&script).unwrap();
+        assert_eq!(
+            db::migrate(&mut conn).unwrap(),
+            db::version(repo.connection()).unwrap()
+        );
+        let rows = |conn: &Connection| -> Vec<(f64, String)> {
+            let mut stmt = conn.prepare("SELECT r, typeof(n) || hex(n) FROM t").unwrap();
+            stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(rows(&conn), rows(repo.connection()));
+        let restored = GreetingRepository::new(conn);
+        assert_eq!(texts(&restored), texts(&repo));
+        assert_eq!(restored.search("me", true).unwrap()[0].id, 2);
+    }
+
+    #[test]
+    fn test_from_path() {
+        for (path, want) in [
+            ("a.csv", Some(FileFormat::Csv)),
+            ("dir/a.json", Some(FileFormat::Json)),
+            ("a.ndjson", Some(FileFormat::Ndjson)),
+            ("a.jsonl", Some(FileFormat::Ndjson)),
+            ("a.sql", Some(FileFormat::Sql)),
+            ("a.txt", None),
+            ("a", None),
+        ] {
+            assert_eq!(FileFormat::from_path(Path::new(path)), want, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use fluke_hpack::{Decoder, Encoder};
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// settings
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+
+const DEFAULT_WINDOW: u32 = 65535;
+/// default SETTINGS_MAX_FRAME_SIZE, which the client does not change.
+const MAX_FRAME_SIZE: usize = 16384;
+const MAX_WINDOW: u32 = 0x7fff_ffff;
+
+/// stream of the only request.
+const STREAM: u32 = 1;
+
+/// http/2 frame.
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    /// get payload without padding and priority fields.
+    fn data(&self) -> Result<&[u8], String> {
+        let mut data = &self.payload[..];
+        let mut padding = 0;
+        if self.flags & PADDED != 0 {
+            let (&len, rest) = data.split_first().ok_or("invalid padding")?;
+            padding = len as usize;
+            data = rest;
+        }
+        if self.kind == HEADERS && self.flags & PRIORITY != 0 {
+            data = data.get(5..).ok_or("invalid priority")?;
+        }
+        if padding > data.len() {
+            return Err("invalid padding".into());
+        }
+        Ok(&data[..data.len() - padding])
+    }
+}
+
+fn write_frame<W: Write>(
+    w: &mut W,
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let len = (payload.len() as u32).to_be_bytes();
+    w.write_all(&len[1..])?;
+    w.write_all(&[kind, flags])?;
+    w.write_all(&stream.to_be_bytes())?;
+    w.write_all(payload)?;
+    w.flush()
+}
+
+fn read_frame<R: Read>(r: &mut R) -> io::Result<Frame> {
+    let mut header = [0; 9];
+    r.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the maximum frame size", len),
+        ));
+    }
+    let mut payload = vec![0; len];
+    r.read_exact(&mut payload)?;
+    Ok(Frame {
+        kind: header[3],
+        flags: header[4],
+        stream: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & MAX_WINDOW,
+        payload,
+    })
+}
+
+/// http response.
+#[derive(Debug, Default, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// format response like a http/1.1 response.
+    pub fn to_http1(&self) -> Vec<u8> {
+        let mut out = format!("HTTP/2 {}\r\n", self.status).into_bytes();
+        for (name, value) in &self.headers {
+            out.extend(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        out.extend(b"\r\n");
+        out.extend(&self.body);
+        out
+    }
+
+    /// add decoded header block to the response.
+    fn add_headers(&mut self, decoder: &mut Decoder, block: &[u8]) -> Result<(), Box<dyn Error>> {
+        let headers = decoder
+            .decode(block)
+            .map_err(|err| format!("invalid header block: {:?}", err))?;
+        for (name, value) in headers {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let value = String::from_utf8_lossy(&value).into_owned();
+            match name.as_str() {
+                ":status" => self.status = value.parse()?,
+                _ if name.starts_with(':') => {}
+                _ => self.headers.push((name, value)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// send get request for path to authority over an established connection
+/// which negotiated "h2" and read the response.
+pub fn get<S: Read + Write>(
+    stream: &mut S,
+    authority: &str,
+    path: &str,
+) -> Result<Response, Box<dyn Error>> {
+    // send preface and settings, the flow control windows are set to the
+    // maximum so no window updates are needed while reading the response
+    stream.write_all(PREFACE)?;
+    let mut settings = Vec::new();
+    for (id, value) in [
+        (SETTINGS_ENABLE_PUSH, 0),
+        (SETTINGS_INITIAL_WINDOW_SIZE, MAX_WINDOW),
+    ] {
+        settings.extend(id.to_be_bytes());
+        settings.extend(value.to_be_bytes());
+    }
+    write_frame(stream, SETTINGS, 0, 0, &settings)?;
+    let increment = MAX_WINDOW - DEFAULT_WINDOW;
+    write_frame(stream, WINDOW_UPDATE, 0, 0, &increment.to_be_bytes())?;
+
+    // send request
+    let block = Encoder::new().encode([
+        (&b":method"[..], &b"GET"[..]),
+        (b":scheme", b"https"),
+        (b":authority", authority.as_bytes()),
+        (b":path", path.as_bytes()),
+        (b"accept-encoding", b"identity"),
+    ]);
+    write_frame(stream, HEADERS, END_HEADERS | END_STREAM, STREAM, &block)?;
+
+    // read response until the end of the stream, which may be signalled by
+    // a headers frame followed by continuation frames
+    let mut decoder = Decoder::new();
+    let mut response = Response::default();
+    let mut block = Vec::new();
+    let mut end_stream = false;
+    while !end_stream || !block.is_empty() {
+        let frame = read_frame(stream)?;
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 => write_frame(stream, SETTINGS, ACK, 0, &[])?,
+            PING if frame.flags & ACK == 0 => write_frame(stream, PING, ACK, 0, &frame.payload)?,
+            GOAWAY if frame.payload.get(..4) < Some(&STREAM.to_be_bytes()[..]) => {
+                return Err("server closed the connection".into());
+            }
+            RST_STREAM if frame.stream == STREAM => return Err("server reset the stream".into()),
+            HEADERS | CONTINUATION if frame.stream == STREAM => {
+                end_stream |= frame.kind == HEADERS && frame.flags & END_STREAM != 0;
+                block.extend(frame.data()?);
+                if frame.flags & END_HEADERS != 0 {
+                    response.add_headers(&mut decoder, &block)?;
+                    block.clear();
+                }
+            }
+            DATA if frame.stream == STREAM => {
+                end_stream |= frame.flags & END_STREAM != 0;
+                response.body.extend(frame.data()?);
+            }
+            _ => {}
+        }
+    }
+
+    // close connection
+    let mut goaway = STREAM.to_be_bytes().to_vec();
+    goaway.extend(0u32.to_be_bytes());
+    write_frame(stream, GOAWAY, 0, 0, &goaway)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// stream replaying scripted server frames and recording client frames.
+    struct Script {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_frame(&mut out, kind, flags, stream, payload).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_get() {
+        let mut encoder = Encoder::new();
+        let headers = encoder.encode([
+            (&b":status"[..], &b"200"[..]),
+            (b"content-type", b"text/plain"),
+        ]);
+        let trailers = encoder.encode([(&b"x-trailer"[..], &b"done"[..])]);
+        let mut padded = vec![3];
+        padded.extend(b"hello ");
+        padded.extend([0; 3]);
+
+        let response = Response {
+            status: 200,
+            headers: vec![
+                ("content-type".into(), "text/plain".into()),
+                ("x-trailer".into(), "done".into()),
+            ],
+            body: b"hello world".to_vec(),
+        };
+        for (frames, want) in [
+            (
+                vec![
+                    frame(SETTINGS, 0, 0, &[]),
+                    frame(PING, 0, 0, b"12345678"),
+                    frame(HEADERS, 0, STREAM, &headers[..3]),
+                    frame(CONTINUATION, END_HEADERS, STREAM, &headers[3..]),
+                    frame(DATA, PADDED, STREAM, &padded),
+                    frame(DATA, 0, 3, b"other stream"),
+                    frame(DATA, 0, STREAM, b"world"),
+                    frame(HEADERS, END_HEADERS | END_STREAM, STREAM, &trailers),
+                ],
+                Ok(response),
+            ),
+            (
+                vec![frame(RST_STREAM, 0, STREAM, &[0, 0, 0, 2])],
+                Err("server reset the stream"),
+            ),
+            (
+                vec![frame(GOAWAY, 0, 0, &[0; 8])],
+                Err("server closed the connection"),
+            ),
+            (
+                vec![frame(DATA, 0, STREAM, &[0; MAX_FRAME_SIZE + 1])],
+                Err("frame of 16385 bytes exceeds the maximum frame size"),
+            ),
+        ] {
+            let mut script = Script {
+                input: io::Cursor::new(frames.concat()),
+                output: Vec::new(),
+            };
+            let got = get(&mut script, "localhost", "/").map_err(|err| err.to_string());
+            assert_eq!(got, want.map_err(String::from));
+            if got.is_err() {
+                continue;
+            }
+
+            // check preface and acknowledgements of settings and ping
+            let output = &script.output[..];
+            assert!(output.starts_with(PREFACE));
+            let mut frames = io::Cursor::new(&output[PREFACE.len()..]);
+            let mut acks = Vec::new();
+            while let Ok(frame) = read_frame(&mut frames) {
+                if frame.flags & ACK != 0 && matches!(frame.kind, SETTINGS | PING) {
+                    acks.push((frame.kind, frame.payload));
+                }
+            }
+            assert_eq!(acks, [(SETTINGS, vec![]), (PING, b"12345678".to_vec())]);
+        }
+    }
+
+    #[test]
+    fn test_to_http1() {
+        let response = Response {
+            status: 404,
+            headers: vec![("server".into(), "test".into())],
+            body: b"not found".to_vec(),
+        };
+        assert_eq!(
+            response.to_http1(),
+            b"HTTP/2 404\r\nserver: test\r\n\r\nnot found"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use rustls::client::WantsClientCert;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ClientConfig, ConfigBuilder, ProtocolVersion};
+use std::convert::TryInto;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use summary::{Cert, Summary};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub mod http2;
+pub mod probe;
+pub mod starttls;
+pub mod verify;
+
+/// client certificate chain and private key for mutual TLS.
+pub struct ClientAuth {
+    pub certs: Vec<CertificateDer<'static>>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+impl ClientAuth {
+    /// load certificate chain and private key from pem files.
+    pub fn load(cert: &Path, key: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(ClientAuth {
+            certs: verify::load_certs(cert)?,
+            key: PrivateKeyDer::from_pem_file(key)
+                .map_err(|err| format!("{}: {}", key.display(), err))?,
+        })
+    }
+
+    /// finish client config with optional client authentication.
+    pub fn configure(
+        auth: Option<&ClientAuth>,
+        builder: ConfigBuilder<ClientConfig, WantsClientCert>,
+    ) -> Result<ClientConfig, rustls::Error> {
+        match auth {
+            Some(auth) => builder.with_client_auth_cert(auth.certs.clone(), auth.key.clone_key()),
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+pub fn connect(addr: String) -> Result<TcpStream, Box<dyn Error>> {
+    // try to resolve address to socket addresses
+    let sock_addrs = addr.to_socket_addrs()?;
+    if sock_addrs.len() == 0 {
+        return Err("could not resolve address".into());
+    }
+
+    // try connecting to each socket address with a short connect timeout,
+    // set read and write timeout on first successfull connection and return it
+    for sock_addr in sock_addrs {
+        if let Ok(sock) = TcpStream::connect_timeout(&sock_addr, CONNECT_TIMEOUT) {
+            sock.set_read_timeout(Some(READ_TIMEOUT))?;
+            sock.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            return Ok(sock);
+        };
+    }
+
+    Err("failed to connect".into())
+}
+
+/// load certificates of the ca file or the native certificate store.
+pub fn load_roots(ca: Option<&Path>) -> Result<rustls::RootCertStore, Box<dyn Error>> {
+    let certs = match ca {
+        Some(path) => verify::load_certs(path)?,
+        None => rustls_native_certs::load_native_certs().certs,
+    };
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in certs {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+/// connect to the server with config, negotiate tls with the starttls
+/// protocol first if given and send the http request unless starttls is used,
+/// with http/2 if the server selects h2. summary is filled with the results
+/// until an error occurs. returns the response in http/1 format.
+pub fn get(
+    config: Arc<ClientConfig>,
+    addr: &str,
+    port: u16,
+    starttls: Option<starttls::Protocol>,
+    summary: &mut Summary,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut conn = rustls::ClientConnection::new(config, addr.to_string().try_into()?)?;
+    let mut sock = connect(format!("{}:{}", addr, port))?;
+    if let Some(protocol) = starttls {
+        starttls::negotiate(&mut sock, protocol, addr)?;
+    }
+    let mut tls = rustls::Stream::new(&mut conn, &mut sock);
+    while tls.conn.is_handshaking() {
+        tls.conn.complete_io(tls.sock)?;
+    }
+
+    summary.version = tls.conn.protocol_version().map(|version| match version {
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".into(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".into(),
+        version => format!("{:?}", version),
+    });
+    // rustls names tls 1.3 suites "TLS13_*", iana "TLS_*"
+    summary.cipher = tls.conn.negotiated_cipher_suite().map(|suite| {
+        let name = suite.suite().as_str().unwrap_or("unknown");
+        match name.strip_prefix("TLS13_") {
+            Some(name) => format!("TLS_{}", name),
+            None => name.to_string(),
+        }
+    });
+    for cert in tls.conn.peer_certificates().into_iter().flatten() {
+        summary.chain.push(Cert::from_der(cert)?);
+    }
+    summary.digest = summary.chain.first().map(|cert| cert.sha256.clone());
+
+    // send http request and get http response, use http/2 if negotiated
+    let response = if starttls.is_some() {
+        tls.conn.send_close_notify();
+        tls.flush()?;
+        Vec::new()
+    } else if tls.conn.alpn_protocol() == Some(b"h2") {
+        http2::get(&mut tls, addr, "/")?.to_http1()
+    } else {
+        tls.write_all(
+            format!(
+                "GET / HTTP/1.1\r\n\
+                Host: {}\r\n\
+                Connection: close\r\n\
+                Accept-Encoding: identity\r\n\
+                \r\n",
+                addr,
+            )
+            .as_bytes(),
+        )?;
+        let mut plaintext = Vec::new();
+        tls.read_to_end(&mut plaintext)?;
+        plaintext
+    };
+    summary.status = summary::status(&response);
+    Ok(response)
+}
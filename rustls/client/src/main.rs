@@ -1,102 +1,160 @@
-use std::convert::TryInto;
+use clap::Parser;
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::ClientConfig;
+use rustls_client::{load_roots, probe, starttls, verify, ClientAuth};
 use std::error::Error;
-use std::io::{stdout, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::io::{stdout, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use summary::Summary;
 
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
-const READ_TIMEOUT: Duration = Duration::from_secs(5);
-const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
-
-fn connect(addr: String) -> Result<TcpStream, Box<dyn Error>> {
-    // try to resolve address to socket addresses
-    let sock_addrs = addr.to_socket_addrs()?;
-    if sock_addrs.len() == 0 {
-        return Err("could not resolve address".into());
-    }
+/// Send a HTTP request to a TLS server or probe its TLS configuration
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Hostname of the server
+    #[clap(default_value = "www.rust-lang.org")]
+    addr: String,
+    /// Port of the server, defaults to 443 or the port of the STARTTLS
+    /// protocol
+    port: Option<u16>,
+    /// Probe supported protocol versions and cipher suites and print a JSON
+    /// report instead of sending a request
+    #[clap(long)]
+    probe: bool,
+    /// Negotiate TLS with this protocol before the handshake and only report
+    /// the negotiated parameters instead of sending a HTTP request
+    #[clap(long, value_enum)]
+    starttls: Option<starttls::Protocol>,
+    /// ALPN protocols to offer, HTTP/2 is used if the server selects h2.
+    /// Defaults to h2,http/1.1 and to none with STARTTLS
+    #[clap(long, value_delimiter = ',')]
+    alpn: Option<Vec<String>>,
+    /// Trust anchors in PEM format instead of the native certificate store
+    #[clap(long)]
+    ca: Option<PathBuf>,
+    /// Require the SHA-256 digest of the server public key (SPKI) in hex to
+    /// match, may be given multiple times
+    #[clap(long, value_parser = verify::parse_digest)]
+    pin_spki: Vec<Vec<u8>>,
+    /// Require the SHA-256 digest of the server certificate in hex to match,
+    /// may be given multiple times
+    #[clap(long, value_parser = verify::parse_digest)]
+    pin_cert: Vec<Vec<u8>>,
+    /// Trust the server public key on first use and require it to match on
+    /// later connections instead of verifying the certificate chain, keys are
+    /// stored in this file
+    #[clap(long)]
+    known_hosts: Option<PathBuf>,
+    /// Connect even if verification fails, failures are still reported
+    #[clap(long)]
+    insecure: bool,
+    /// Client certificate chain in PEM format for mutual TLS
+    #[clap(long, requires = "key")]
+    cert: Option<PathBuf>,
+    /// Private key of the client certificate in PEM format
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
+}
 
-    // try connecting to each socket address with a short connect timeout,
-    // set read and write timeout on first successfull connection and return it
-    for sock_addr in sock_addrs {
-        if let Ok(sock) = TcpStream::connect_timeout(&sock_addr, CONNECT_TIMEOUT) {
-            sock.set_read_timeout(Some(READ_TIMEOUT))?;
-            sock.set_write_timeout(Some(WRITE_TIMEOUT))?;
-            return Ok(sock);
-        };
+impl Cli {
+    /// get ALPN protocols to offer. the HTTP protocols are not offered by
+    /// default with starttls as the connection does not carry HTTP.
+    fn alpn_protocols(&self) -> Vec<String> {
+        match (&self.alpn, self.starttls) {
+            (Some(alpn), _) => alpn.clone(),
+            (None, Some(_)) => Vec::new(),
+            (None, None) => vec!["h2".into(), "http/1.1".into()],
+        }
     }
+}
 
-    Err("failed to connect".into())
+/// create server certificate verifier from the command line options.
+fn verifier(
+    cli: &Cli,
+    port: u16,
+    provider: Arc<CryptoProvider>,
+) -> Result<verify::Verifier, Box<dyn Error>> {
+    let roots = load_roots(cli.ca.as_deref())?;
+    let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+    let mut verifier = verify::Verifier::new(webpki);
+    verifier.spki_pins = cli.pin_spki.clone();
+    verifier.cert_pins = cli.pin_cert.clone();
+    verifier.known_hosts = cli.known_hosts.clone().map(|path| verify::KnownHosts {
+        path,
+        host: format!("{}:{}", cli.addr, port),
+    });
+    verifier.insecure = cli.insecure;
+    Ok(verifier)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // get address from command line
-    let addr = match std::env::args().nth(1) {
-        Some(addr) => addr,
-        None => "www.rust-lang.org".to_string(),
+    let cli = Cli::parse();
+    let port = cli
+        .port
+        .unwrap_or_else(|| cli.starttls.map_or(443, |protocol| protocol.port()));
+    let provider = CryptoProvider::get_default()
+        .map(|provider| (**provider).clone())
+        .unwrap_or_else(rustls::crypto::aws_lc_rs::default_provider);
+    let verifier = Arc::new(verifier(&cli, port, Arc::new(provider.clone()))?);
+    let client_auth = match (&cli.cert, &cli.key) {
+        (Some(cert), Some(key)) => Some(ClientAuth::load(cert, key)?),
+        _ => None,
     };
+    let alpn = cli.alpn_protocols();
+    let addr = cli.addr;
 
-    // get port from command line
-    let port = match std::env::args().nth(2) {
-        Some(port) => port,
-        None => "443".to_string(),
-    };
-
-    // load certificates
-    let mut roots = rustls::RootCertStore::empty();
-    for cert in rustls_native_certs::load_native_certs().certs {
-        roots.add(cert)?;
+    if cli.probe {
+        let report = probe::probe(
+            &addr,
+            port,
+            cli.starttls,
+            provider,
+            verifier,
+            client_auth.as_ref(),
+            &alpn,
+        )?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
     }
 
     // create config
-    let config = rustls::ClientConfig::builder()
-        .with_root_certificates(roots)
-        .with_no_client_auth();
-
-    // connect to server
-    let mut conn = rustls::ClientConnection::new(Arc::new(config), addr.clone().try_into()?)?;
-    let mut sock = connect(format!("{}:{}", addr, port))?;
-    let mut tls = rustls::Stream::new(&mut conn, &mut sock);
+    let builder = ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone());
+    let mut config = ClientAuth::configure(client_auth.as_ref(), builder)?;
+    config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
 
-    // send http request
-    tls.write_all(
-        format!(
-            "GET / HTTP/1.1\r\n\
-            Host: {}\r\n\
-            Connection: close\r\n\
-            Accept-Encoding: identity\r\n\
-            \r\n",
-            addr,
-        )
-        .as_bytes(),
-    )?;
-
-    // check cipher suite
-    let ciphersuite = tls
-        .conn
-        .negotiated_cipher_suite()
-        .ok_or("tls handshake failed")?;
-    writeln!(
-        &mut std::io::stderr(),
-        "Current ciphersuite: {:?}",
-        ciphersuite.suite()
-    )?;
-
-    // get http response
-    let mut plaintext = Vec::new();
-    tls.read_to_end(&mut plaintext)?;
-    stdout().write_all(&plaintext)?;
+    // connect to server and print the response and the connection summary
+    let mut summary = Summary::default();
+    let response = rustls_client::get(Arc::new(config), &addr, port, cli.starttls, &mut summary)?;
+    for failure in verifier.failures() {
+        eprintln!("Verification failed: {}", failure);
+    }
+    stdout().write_all(&response)?;
+    print!("{}", summary);
 
-    // get peer certificate
-    let certificates = tls
-        .conn
-        .peer_certificates()
-        .ok_or("getting peer certificates failed")?;
-    println!("{:?}", certificates[0]);
+    Ok(())
+}
 
-    // get digest
-    let digest = ring::digest::digest(&ring::digest::SHA256, certificates[0].as_ref());
-    println!("{:?}", digest);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn test_alpn_protocols() {
+        for (args, want) in [
+            (&["client"][..], &["h2", "http/1.1"][..]),
+            (&["client", "--alpn", "http/1.1"], &["http/1.1"]),
+            (&["client", "--starttls", "smtp"], &[]),
+            (
+                &["client", "--starttls", "imap", "--alpn", "imap"],
+                &["imap"],
+            ),
+        ] {
+            assert_eq!(Cli::parse_from(args).alpn_protocols(), want, "{:?}", args);
+        }
+    }
 }
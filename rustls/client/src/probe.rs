@@ -0,0 +1,540 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::version;
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, Error as TlsError, SignatureScheme,
+    SupportedCipherSuite, SupportedProtocolVersion,
+};
+use serde::Serialize;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+
+use crate::starttls::{self, Protocol};
+use crate::{connect, ClientAuth};
+
+/// protocol versions to probe, newest first.
+static VERSIONS: &[&SupportedProtocolVersion] = &[&version::TLS13, &version::TLS12];
+
+/// probed protocol version and its accepted cipher suites.
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: String,
+    pub supported: bool,
+    pub cipher_suites: Vec<String>,
+}
+
+/// parameters of a handshake with all versions and cipher suites enabled.
+#[derive(Debug, Serialize)]
+pub struct Negotiated {
+    pub version: Option<String>,
+    pub cipher_suite: Option<String>,
+    pub key_exchange_group: Option<String>,
+    pub alpn: Option<String>,
+}
+
+/// certificate of the server chain.
+#[derive(Debug, Serialize)]
+pub struct ChainCert {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+    pub sha256: String,
+}
+
+/// probe report of a server.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub host: String,
+    pub port: u16,
+    pub versions: Vec<VersionInfo>,
+    pub negotiated: Negotiated,
+    pub ocsp_stapled: bool,
+    pub verified: bool,
+    pub verify_error: Option<String>,
+    pub chain: Vec<ChainCert>,
+}
+
+/// server certificates seen during the handshake.
+#[derive(Debug, Default)]
+struct Seen {
+    chain: Vec<CertificateDer<'static>>,
+    ocsp_response: Vec<u8>,
+    verify_error: Option<String>,
+}
+
+/// verifier which records the server certificates and the verification
+/// result but accepts every certificate, so handshakes with servers with
+/// invalid certificates can be probed as well. handshake signatures are
+/// still checked.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    seen: Mutex<Seen>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        );
+        let mut seen = self.seen.lock().unwrap();
+        seen.chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.clone().into_owned())
+            .collect();
+        seen.ocsp_response = ocsp_response.to_vec();
+        seen.verify_error = result.err().map(|err| err.to_string());
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// prober of a single server.
+struct Prober<'a> {
+    host: String,
+    port: u16,
+    starttls: Option<Protocol>,
+    server_name: ServerName<'static>,
+    provider: CryptoProvider,
+    verifier: Arc<dyn ServerCertVerifier>,
+    client_auth: Option<&'a ClientAuth>,
+    alpn: Vec<Vec<u8>>,
+}
+
+impl Prober<'_> {
+    /// create client config restricted to versions and cipher suites with a
+    /// fresh recording verifier.
+    fn config(
+        &self,
+        versions: &[&'static SupportedProtocolVersion],
+        cipher_suites: Vec<SupportedCipherSuite>,
+    ) -> Result<(ClientConfig, Arc<RecordingVerifier>), Box<dyn Error>> {
+        let provider = Arc::new(CryptoProvider {
+            cipher_suites,
+            ..self.provider.clone()
+        });
+        let verifier = Arc::new(RecordingVerifier {
+            inner: self.verifier.clone(),
+            seen: Mutex::new(Seen::default()),
+        });
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(versions)?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier.clone());
+        let mut config = ClientAuth::configure(self.client_auth, builder)?;
+        config.alpn_protocols = self.alpn.clone();
+        Ok((config, verifier))
+    }
+
+    /// run handshake with config. returns None if the server rejected the
+    /// handshake, errors are only returned if the server is unreachable.
+    fn handshake(&self, config: ClientConfig) -> Result<Option<ClientConnection>, Box<dyn Error>> {
+        let mut conn = ClientConnection::new(Arc::new(config), self.server_name.clone())?;
+        let mut sock = connect(format!("{}:{}", self.host, self.port))?;
+        if let Some(protocol) = self.starttls {
+            starttls::negotiate(&mut sock, protocol, &self.host)?;
+        }
+        while conn.is_handshaking() {
+            if conn.complete_io(&mut sock).is_err() {
+                return Ok(None);
+            }
+        }
+        // tls 1.3 servers reject client certificates only after the client
+        // finished its side of the handshake, so wait for the answer to the
+        // close notification
+        conn.send_close_notify();
+        let _ = conn.write_tls(&mut sock);
+        if let Err(err) = conn.complete_io(&mut sock)
+            && err.get_ref().is_some_and(|err| err.is::<TlsError>())
+        {
+            return Ok(None);
+        }
+        Ok(Some(conn))
+    }
+
+    /// check whether the server accepts version with the given cipher suites.
+    fn accepts(
+        &self,
+        version: &'static SupportedProtocolVersion,
+        cipher_suites: Vec<SupportedCipherSuite>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (config, _) = self.config(&[version], cipher_suites)?;
+        Ok(self.handshake(config)?.is_some())
+    }
+
+    fn probe_version(
+        &self,
+        version: &'static SupportedProtocolVersion,
+    ) -> Result<VersionInfo, Box<dyn Error>> {
+        let suites = self
+            .provider
+            .cipher_suites
+            .iter()
+            .filter(|suite| suite.version() == version)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut info = VersionInfo {
+            version: format!("{:?}", version.version),
+            supported: !suites.is_empty() && self.accepts(version, suites.clone())?,
+            cipher_suites: Vec::new(),
+        };
+        if info.supported {
+            // offer one cipher suite at a time
+            for suite in suites {
+                if self.accepts(version, vec![suite])? {
+                    info.cipher_suites.push(format!("{:?}", suite.suite()));
+                }
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// describe certificate of the server chain.
+fn chain_cert(der: &CertificateDer) -> ChainCert {
+    let sha256 = ring::digest::digest(&ring::digest::SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    match X509Certificate::from_der(der) {
+        Ok((_, cert)) => ChainCert {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            not_after: cert.validity().not_after.to_string(),
+            sha256,
+        },
+        Err(err) => ChainCert {
+            subject: format!("invalid certificate: {}", err),
+            issuer: String::new(),
+            not_after: String::new(),
+            sha256,
+        },
+    }
+}
+
+/// probe which protocol versions and cipher suites the server accepts and
+/// what it negotiates with all of them enabled. the server certificate is
+/// checked with verifier, but failures are only reported. with starttls the
+/// protocol is negotiated before every handshake.
+pub fn probe(
+    host: &str,
+    port: u16,
+    starttls: Option<Protocol>,
+    provider: CryptoProvider,
+    verifier: Arc<dyn ServerCertVerifier>,
+    client_auth: Option<&ClientAuth>,
+    alpn: &[String],
+) -> Result<Report, Box<dyn Error>> {
+    let prober = Prober {
+        host: host.into(),
+        port,
+        starttls,
+        server_name: ServerName::try_from(host.to_string())?,
+        provider,
+        verifier,
+        client_auth,
+        alpn: alpn.iter().map(|p| p.as_bytes().to_vec()).collect(),
+    };
+
+    let mut versions = Vec::new();
+    for version in VERSIONS {
+        versions.push(prober.probe_version(version)?);
+    }
+
+    let (config, verifier) = prober.config(VERSIONS, prober.provider.cipher_suites.clone())?;
+    let conn = prober
+        .handshake(config)?
+        .ok_or("handshake with all protocol versions and cipher suites failed")?;
+    let negotiated = Negotiated {
+        version: conn.protocol_version().map(|v| format!("{:?}", v)),
+        cipher_suite: conn
+            .negotiated_cipher_suite()
+            .map(|s| format!("{:?}", s.suite())),
+        key_exchange_group: conn
+            .negotiated_key_exchange_group()
+            .map(|g| format!("{:?}", g.name())),
+        alpn: conn
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+    };
+
+    let seen = verifier.seen.lock().unwrap();
+    Ok(Report {
+        host: host.into(),
+        port,
+        versions,
+        negotiated,
+        ocsp_stapled: !seen.ocsp_response.is_empty(),
+        verified: seen.verify_error.is_none(),
+        verify_error: seen.verify_error.clone(),
+        chain: seen.chain.iter().map(chain_cert).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::crypto::aws_lc_rs::{cipher_suite, default_provider};
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{RootCertStore, ServerConfig, ServerConnection};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// start server with the given settings on a random local port, client
+    /// certificates issued by client_ca are required if given. returns the
+    /// port and the certificate to trust.
+    fn server(
+        version: &'static SupportedProtocolVersion,
+        cipher_suites: &[SupportedCipherSuite],
+        alpn: &[&str],
+        ocsp: &[u8],
+        client_ca: Option<CertificateDer<'static>>,
+    ) -> (u16, CertificateDer<'static>) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let provider = CryptoProvider {
+            cipher_suites: cipher_suites.to_vec(),
+            ..default_provider()
+        };
+        let provider = Arc::new(provider);
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[version])
+            .unwrap();
+        let builder = match client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                roots.add(ca).unwrap();
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .unwrap();
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder
+            .with_single_cert_with_ocsp(
+                vec![cert.der().clone()],
+                PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                ocsp.to_vec(),
+            )
+            .unwrap();
+        config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for mut sock in listener.incoming().flatten() {
+                let mut conn = ServerConnection::new(config.clone()).unwrap();
+                while conn.is_handshaking() {
+                    if conn.complete_io(&mut sock).is_err() {
+                        break;
+                    }
+                }
+                conn.send_close_notify();
+                let _ = conn.complete_io(&mut sock);
+            }
+        });
+        (port, cert.der().clone())
+    }
+
+    /// create verifier trusting cert.
+    fn verifier(cert: CertificateDer<'static>) -> Arc<dyn ServerCertVerifier> {
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(default_provider()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_probe() {
+        for (version, suites, alpn, ocsp, want_versions, want_alpn) in [
+            (
+                &version::TLS13,
+                vec![cipher_suite::TLS13_AES_256_GCM_SHA384],
+                vec!["http/1.1"],
+                &b""[..],
+                [vec!["TLS13_AES_256_GCM_SHA384"], vec![]],
+                Some("http/1.1"),
+            ),
+            (
+                &version::TLS12,
+                vec![
+                    cipher_suite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                    cipher_suite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                ],
+                vec![],
+                &b"ocsp response"[..],
+                [
+                    vec![],
+                    vec![
+                        "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+                        "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+                    ],
+                ],
+                None,
+            ),
+        ] {
+            let (port, cert) = server(version, &suites, &alpn, ocsp, None);
+            let report = probe(
+                "localhost",
+                port,
+                None,
+                default_provider(),
+                verifier(cert.clone()),
+                None,
+                &["h2".into(), "http/1.1".into()],
+            )
+            .unwrap();
+
+            let got = report
+                .versions
+                .iter()
+                .map(|v| {
+                    let suites = v
+                        .cipher_suites
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>();
+                    (v.version.as_str(), v.supported, suites)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                got,
+                [
+                    (
+                        "TLSv1_3",
+                        !want_versions[0].is_empty(),
+                        want_versions[0].clone()
+                    ),
+                    (
+                        "TLSv1_2",
+                        !want_versions[1].is_empty(),
+                        want_versions[1].clone()
+                    ),
+                ]
+            );
+            assert_eq!(
+                report.negotiated.version.as_deref(),
+                Some(format!("{:?}", version.version).as_str())
+            );
+            assert_eq!(report.negotiated.alpn.as_deref(), want_alpn);
+            assert!(report.negotiated.key_exchange_group.is_some());
+            assert_eq!(report.ocsp_stapled, !ocsp.is_empty());
+            assert!(report.verified, "{:?}", report.verify_error);
+            assert_eq!(report.chain.len(), 1);
+            assert_eq!(report.chain[0].sha256, chain_cert(&cert).sha256);
+        }
+
+        // certificates which do not verify are reported, but probed anyway
+        let (port, _) = server(
+            &version::TLS13,
+            &[cipher_suite::TLS13_AES_128_GCM_SHA256],
+            &[],
+            b"",
+            None,
+        );
+        let key = rcgen::KeyPair::generate().unwrap();
+        let other = rcgen::CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let report = probe(
+            "localhost",
+            port,
+            None,
+            default_provider(),
+            verifier(other.der().clone()),
+            None,
+            &[],
+        )
+        .unwrap();
+        assert!(!report.verified);
+        assert!(report.verify_error.is_some());
+        assert!(report.versions[0].supported);
+    }
+
+    #[test]
+    fn test_client_auth() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["client".into()])
+            .unwrap()
+            .signed_by(&key, &Issuer::new(ca_params, &ca_key))
+            .unwrap();
+        let client_auth = ClientAuth {
+            certs: vec![cert.der().clone()],
+            key: PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+        };
+
+        let (port, server_cert) = server(
+            &version::TLS13,
+            &[cipher_suite::TLS13_AES_128_GCM_SHA256],
+            &[],
+            b"",
+            Some(ca.der().clone()),
+        );
+        let probe = |client_auth| {
+            probe(
+                "localhost",
+                port,
+                None,
+                default_provider(),
+                verifier(server_cert.clone()),
+                client_auth,
+                &[],
+            )
+        };
+        let report = probe(Some(&client_auth)).unwrap();
+        assert!(report.versions[0].supported);
+        assert!(report.verified);
+        assert!(probe(None).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use clap::ValueEnum;
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+/// protocol to negotiate TLS with before the handshake.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Protocol {
+    /// SMTP with EHLO and STARTTLS
+    Smtp,
+    /// IMAP with STARTTLS
+    Imap,
+    /// POP3 with STLS
+    Pop3,
+    /// XMPP client stream with starttls
+    Xmpp,
+    /// PostgreSQL SSLRequest
+    Postgres,
+}
+
+impl Protocol {
+    /// default port of the protocol.
+    pub fn port(self) -> u16 {
+        match self {
+            Protocol::Smtp => 25,
+            Protocol::Imap => 143,
+            Protocol::Pop3 => 110,
+            Protocol::Xmpp => 5222,
+            Protocol::Postgres => 5432,
+        }
+    }
+}
+
+/// maximum size of the plaintext preamble sent by the server.
+const MAX_PREAMBLE: usize = 64 * 1024;
+
+/// stream which fails when the server sent more than MAX_PREAMBLE bytes.
+struct Preamble<'a, S> {
+    stream: &'a mut S,
+    left: usize,
+}
+
+impl<S: Read> Read for Preamble<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 {
+            return Err(io::Error::other(format!(
+                "server sent more than {} bytes",
+                MAX_PREAMBLE
+            )));
+        }
+        let len = buf.len().min(self.left);
+        let n = self.stream.read(&mut buf[..len])?;
+        self.left -= n;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Preamble<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// read from stream until the data ends with one of ends. data is read byte
+/// by byte, so nothing after the plaintext preamble is consumed.
+fn read_until<S: Read>(stream: &mut S, ends: &[&str]) -> Result<String, Box<dyn Error>> {
+    let mut data = Vec::new();
+    let mut byte = [0];
+    while !ends.iter().any(|end| data.ends_with(end.as_bytes())) {
+        if stream.read(&mut byte)? == 0 {
+            return Err("connection closed by server".into());
+        }
+        data.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn read_line<S: Read>(stream: &mut S) -> Result<String, Box<dyn Error>> {
+    let line = read_until(stream, &["\n"])?;
+    Ok(line.trim_end().to_string())
+}
+
+/// read smtp reply, which may span multiple lines. returns code and lines.
+fn smtp_reply<S: Read>(stream: &mut S) -> Result<(String, Vec<String>), Box<dyn Error>> {
+    let mut lines = Vec::new();
+    loop {
+        let line = read_line(stream)?;
+        let last = line.as_bytes().get(3) != Some(&b'-');
+        lines.push(line);
+        if last {
+            break;
+        }
+    }
+    let code = lines[0].get(..3).unwrap_or_default().to_string();
+    Ok((code, lines))
+}
+
+fn smtp<S: Read + Write>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    let expect = |(code, lines): (String, Vec<String>), want: &str| {
+        if code == want {
+            Ok(lines)
+        } else {
+            Err(format!("unexpected smtp reply: {}", lines.join(" ")))
+        }
+    };
+    expect(smtp_reply(stream)?, "220")?;
+    stream.write_all(b"EHLO localhost\r\n")?;
+    let extensions = expect(smtp_reply(stream)?, "250")?;
+    if !extensions.iter().any(|line| {
+        line.get(4..)
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("STARTTLS"))
+    }) {
+        return Err("server does not support STARTTLS".into());
+    }
+    stream.write_all(b"STARTTLS\r\n")?;
+    expect(smtp_reply(stream)?, "220")?;
+    Ok(())
+}
+
+fn imap<S: Read + Write>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    let greeting = read_line(stream)?;
+    if !greeting.starts_with("* OK") {
+        return Err(format!("unexpected imap greeting: {}", greeting).into());
+    }
+    stream.write_all(b"a1 STARTTLS\r\n")?;
+    // skip untagged responses
+    loop {
+        let line = read_line(stream)?;
+        if let Some(status) = line.strip_prefix("a1 ") {
+            if status.starts_with("OK") {
+                return Ok(());
+            }
+            return Err(format!("STARTTLS failed: {}", status).into());
+        }
+    }
+}
+
+fn pop3<S: Read + Write>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    let greeting = read_line(stream)?;
+    if !greeting.starts_with("+OK") {
+        return Err(format!("unexpected pop3 greeting: {}", greeting).into());
+    }
+    stream.write_all(b"STLS\r\n")?;
+    let reply = read_line(stream)?;
+    if !reply.starts_with("+OK") {
+        return Err(format!("STLS failed: {}", reply).into());
+    }
+    Ok(())
+}
+
+fn xmpp<S: Read + Write>(stream: &mut S, host: &str) -> Result<(), Box<dyn Error>> {
+    write!(
+        stream,
+        "<?xml version='1.0'?><stream:stream to='{}' xmlns='jabber:client' \
+         xmlns:stream='http://etherx.jabber.org/streams' version='1.0'>",
+        host
+    )?;
+    let features = read_until(stream, &["</stream:features>", "</stream:stream>"])?;
+    if !features.contains("<starttls") {
+        return Err("server does not support starttls".into());
+    }
+    stream.write_all(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")?;
+    let reply = read_until(stream, &[">"])?;
+    if !reply.trim_start().starts_with("<proceed") {
+        return Err(format!("starttls failed: {}", reply.trim()).into());
+    }
+    Ok(())
+}
+
+fn postgres<S: Read + Write>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    // SSLRequest message with length and request code
+    let mut request = 8u32.to_be_bytes().to_vec();
+    request.extend(80877103u32.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut reply = [0];
+    stream.read_exact(&mut reply)?;
+    match reply[0] {
+        b'S' => Ok(()),
+        b'N' => Err("server does not support SSL".into()),
+        _ => Err("unexpected reply to SSLRequest".into()),
+    }
+}
+
+/// negotiate TLS on a plaintext connection to host, afterwards the TLS
+/// handshake can start on the stream.
+pub fn negotiate<S: Read + Write>(
+    stream: &mut S,
+    protocol: Protocol,
+    host: &str,
+) -> Result<(), Box<dyn Error>> {
+    let stream = &mut Preamble {
+        stream,
+        left: MAX_PREAMBLE,
+    };
+    match protocol {
+        Protocol::Smtp => smtp(stream),
+        Protocol::Imap => imap(stream),
+        Protocol::Pop3 => pop3(stream),
+        Protocol::Xmpp => xmpp(stream, host),
+        Protocol::Postgres => postgres(stream),
+    }
+    .map_err(|err| format!("{:?} negotiation failed: {}", protocol, err).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// marker sent by the fake servers after the preamble.
+    const MARKER: &[u8] = b"\x16TLS";
+
+    /// start fake server on a random local port. the server sends the
+    /// response of each step after it received the expected data and the
+    /// marker after the last step.
+    fn fake_server(script: Vec<(&'static [u8], &'static [u8])>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            for (expected, response) in script {
+                let mut byte = [0];
+                while !received.ends_with(expected) {
+                    if sock.read(&mut byte).unwrap() == 0 {
+                        return;
+                    }
+                    received.push(byte[0]);
+                }
+                sock.write_all(response).unwrap();
+            }
+            sock.write_all(MARKER).unwrap();
+        });
+        port
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let xmpp_header = b"version='1.0'>";
+        let xmpp_features: &[u8] = b"<?xml version='1.0'?><stream:stream from='example.com' \
+            xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' \
+            version='1.0'><stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'>\
+            <required/></starttls></stream:features>";
+        let starttls = b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>";
+        let ssl_request = b"\x00\x00\x00\x08\x04\xd2\x16\x2f";
+        let flood: &[u8] = "* NOTE untagged\r\n".repeat(5000).leak().as_bytes();
+
+        for (protocol, script, ok) in [
+            (
+                Protocol::Smtp,
+                vec![
+                    (
+                        &b""[..],
+                        &b"220-mail.example.com ESMTP\r\n220 ready\r\n"[..],
+                    ),
+                    (
+                        b"EHLO localhost\r\n",
+                        b"250-mail.example.com\r\n250-SIZE 100\r\n250 STARTTLS\r\n",
+                    ),
+                    (b"STARTTLS\r\n", b"220 go ahead\r\n"),
+                ],
+                true,
+            ),
+            (
+                Protocol::Smtp,
+                vec![
+                    (&b""[..], &b"220 ready\r\n"[..]),
+                    (
+                        b"EHLO localhost\r\n",
+                        b"250-mail.example.com\r\n250 SIZE 100\r\n",
+                    ),
+                ],
+                false,
+            ),
+            (
+                Protocol::Imap,
+                vec![
+                    (
+                        &b""[..],
+                        &b"* OK [CAPABILITY IMAP4rev1 STARTTLS] ready\r\n"[..],
+                    ),
+                    (
+                        b"a1 STARTTLS\r\n",
+                        b"* NOTE untagged\r\na1 OK begin TLS\r\n",
+                    ),
+                ],
+                true,
+            ),
+            (
+                Protocol::Imap,
+                vec![
+                    (&b""[..], &b"* OK ready\r\n"[..]),
+                    (b"a1 STARTTLS\r\n", b"a1 BAD unknown command\r\n"),
+                ],
+                false,
+            ),
+            // endless untagged responses
+            (
+                Protocol::Imap,
+                vec![
+                    (&b""[..], &b"* OK ready\r\n"[..]),
+                    (b"a1 STARTTLS\r\n", flood),
+                ],
+                false,
+            ),
+            (
+                Protocol::Pop3,
+                vec![
+                    (&b""[..], &b"+OK ready\r\n"[..]),
+                    (b"STLS\r\n", b"+OK begin TLS\r\n"),
+                ],
+                true,
+            ),
+            (
+                Protocol::Pop3,
+                vec![
+                    (&b""[..], &b"+OK ready\r\n"[..]),
+                    (b"STLS\r\n", b"-ERR unknown\r\n"),
+                ],
+                false,
+            ),
+            (
+                Protocol::Xmpp,
+                vec![
+                    (&xmpp_header[..], xmpp_features),
+                    (
+                        starttls,
+                        b"<proceed xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>",
+                    ),
+                ],
+                true,
+            ),
+            (
+                Protocol::Xmpp,
+                vec![
+                    (&xmpp_header[..], xmpp_features),
+                    (
+                        starttls,
+                        b"<failure xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>",
+                    ),
+                ],
+                false,
+            ),
+            (
+                Protocol::Postgres,
+                vec![(&ssl_request[..], &b"S"[..])],
+                true,
+            ),
+            (
+                Protocol::Postgres,
+                vec![(&ssl_request[..], &b"N"[..])],
+                false,
+            ),
+        ] {
+            let port = fake_server(script);
+            let mut sock = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let result = negotiate(&mut sock, protocol, "example.com");
+            assert_eq!(result.is_ok(), ok, "{:?} {:?}", protocol, result);
+
+            // nothing after the preamble was consumed
+            if ok {
+                let mut marker = [0; MARKER.len()];
+                sock.read_exact(&mut marker).unwrap();
+                assert_eq!(marker, MARKER);
+            }
+        }
+    }
+}
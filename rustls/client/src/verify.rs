@@ -0,0 +1,318 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, Error as TlsError, SignatureScheme};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+
+/// compute sha-256 digest.
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, data)
+        .as_ref()
+        .to_vec()
+}
+
+/// parse sha-256 digest in hex, optionally separated by colons.
+pub fn parse_digest(s: &str) -> Result<Vec<u8>, String> {
+    let digest = hex::decode(s.replace(':', "")).map_err(|err| err.to_string())?;
+    if digest.len() != 32 {
+        return Err("expected SHA-256 digest of 32 bytes".into());
+    }
+    Ok(digest)
+}
+
+/// file with the public key digests of known hosts, one "host:port digest"
+/// per line.
+#[derive(Debug)]
+pub struct KnownHosts {
+    pub path: PathBuf,
+    pub host: String,
+}
+
+impl KnownHosts {
+    /// check public key digest against the stored one, the digest is stored
+    /// if the host is not known yet.
+    fn check(&self, digest: &[u8]) -> Result<(), String> {
+        let err = |err: &dyn std::fmt::Display| format!("{}: {}", self.path.display(), err);
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(err(&e)),
+        };
+        for line in content.lines() {
+            let Some((host, known)) = line.split_once(' ') else {
+                continue;
+            };
+            if host != self.host {
+                continue;
+            }
+            return match parse_digest(known.trim()) {
+                Ok(known) if known == digest => Ok(()),
+                Ok(known) => Err(format!(
+                    "public key of {} changed from {} to {}",
+                    self.host,
+                    hex::encode(known),
+                    hex::encode(digest)
+                )),
+                Err(e) => Err(err(&e)),
+            };
+        }
+
+        // trust on first use
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| err(&e))?;
+        writeln!(file, "{} {}", self.host, hex::encode(digest)).map_err(|e| err(&e))?;
+        Ok(())
+    }
+}
+
+/// server certificate verifier with certificate chain verification, public
+/// key and certificate pinning and trust on first use. in insecure mode
+/// failures are only recorded.
+#[derive(Debug)]
+pub struct Verifier {
+    pub webpki: Arc<WebPkiServerVerifier>,
+    /// pinned sha-256 digests of subject public key infos
+    pub spki_pins: Vec<Vec<u8>>,
+    /// pinned sha-256 digests of leaf certificates
+    pub cert_pins: Vec<Vec<u8>>,
+    /// known hosts file, replaces certificate chain verification
+    pub known_hosts: Option<KnownHosts>,
+    pub insecure: bool,
+    failures: Mutex<Vec<String>>,
+}
+
+impl Verifier {
+    pub fn new(webpki: Arc<WebPkiServerVerifier>) -> Self {
+        Verifier {
+            webpki,
+            spki_pins: Vec::new(),
+            cert_pins: Vec::new(),
+            known_hosts: None,
+            insecure: false,
+            failures: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// get recorded failures of the last verification.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.lock().unwrap().clone()
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        let mut failures = Vec::new();
+        let mut chain_error = None;
+
+        if self.known_hosts.is_none()
+            && let Err(err) = self.webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )
+        {
+            failures.push(format!("certificate chain: {}", err));
+            chain_error = Some(err);
+        }
+
+        let cert_digest = sha256(end_entity);
+        let spki_digest = match X509Certificate::from_der(end_entity) {
+            Ok((_, cert)) => sha256(cert.public_key().raw),
+            Err(err) => return Err(TlsError::General(format!("invalid certificate: {}", err))),
+        };
+        if (!self.spki_pins.is_empty() || !self.cert_pins.is_empty())
+            && !self.spki_pins.contains(&spki_digest)
+            && !self.cert_pins.contains(&cert_digest)
+        {
+            failures.push(format!(
+                "pinning: neither certificate {} nor public key {} is pinned",
+                hex::encode(&cert_digest),
+                hex::encode(&spki_digest)
+            ));
+        }
+
+        // only trust keys on first use if nothing else failed
+        if let Some(known_hosts) = &self.known_hosts
+            && failures.is_empty()
+            && let Err(err) = known_hosts.check(&spki_digest)
+        {
+            failures.push(format!("known hosts: {}", err));
+        }
+
+        *self.failures.lock().unwrap() = failures.clone();
+        match chain_error {
+            _ if failures.is_empty() || self.insecure => Ok(ServerCertVerified::assertion()),
+            Some(err) => Err(err),
+            None => Err(TlsError::General(failures.join("; "))),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        self.webpki.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        self.webpki.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.webpki.supported_verify_schemes()
+    }
+}
+
+/// load certificates of a pem file.
+pub fn load_certs(path: &std::path::Path) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+    use rustls::pki_types::pem::PemObject;
+
+    let mut certs = Vec::new();
+    for cert in
+        CertificateDer::pem_file_iter(path).map_err(|err| format!("{}: {}", path.display(), err))?
+    {
+        certs.push(cert.map_err(|err| format!("{}: {}", path.display(), err))?);
+    }
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()).into());
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::crypto::aws_lc_rs::default_provider;
+    use rustls::RootCertStore;
+    use std::process;
+
+    fn cert(name: &str) -> (CertificateDer<'static>, Vec<u8>) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.into()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let spki = sha256(&rcgen::PublicKeyData::subject_public_key_info(&key));
+        (cert.der().clone(), spki)
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = vec![0xab; 32];
+        assert_eq!(parse_digest(&hex::encode(&digest)), Ok(digest.clone()));
+        assert_eq!(parse_digest(&"AB:".repeat(32)[..95]), Ok(digest));
+        assert!(parse_digest("abcd").is_err());
+        assert!(parse_digest(&"xy".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_verifier() {
+        let (trusted, trusted_spki) = cert("localhost");
+        let (other, _) = cert("localhost");
+        let mut roots = RootCertStore::empty();
+        roots.add(trusted.clone()).unwrap();
+        let webpki = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(default_provider()),
+        )
+        .build()
+        .unwrap();
+        let known_hosts =
+            std::env::temp_dir().join(format!("client-known-hosts-{}", process::id()));
+        let _ = fs::remove_file(&known_hosts);
+
+        // certificate, spki pins, cert pins, known hosts, insecure, ok, failures
+        for (cert, spki_pins, cert_pins, tofu, insecure, ok, failures) in [
+            (&trusted, vec![], vec![], false, false, true, 0),
+            (&other, vec![], vec![], false, false, false, 1),
+            (&other, vec![], vec![], false, true, true, 1),
+            (
+                &trusted,
+                vec![trusted_spki.clone()],
+                vec![],
+                false,
+                false,
+                true,
+                0,
+            ),
+            (
+                &trusted,
+                vec![],
+                vec![sha256(&trusted)],
+                false,
+                false,
+                true,
+                0,
+            ),
+            (
+                &trusted,
+                vec![vec![0; 32]],
+                vec![sha256(&other)],
+                false,
+                false,
+                false,
+                1,
+            ),
+            (&other, vec![], vec![sha256(&trusted)], false, true, true, 2),
+            // first use stores the key, later uses must match it
+            (&other, vec![], vec![], true, false, true, 0),
+            (&other, vec![], vec![], true, false, true, 0),
+            (&trusted, vec![], vec![], true, false, false, 1),
+            (&trusted, vec![], vec![], true, true, true, 1),
+        ] {
+            let verifier = Verifier {
+                spki_pins,
+                cert_pins,
+                known_hosts: tofu.then(|| KnownHosts {
+                    path: known_hosts.clone(),
+                    host: "localhost:443".into(),
+                }),
+                insecure,
+                ..Verifier::new(webpki.clone())
+            };
+            let result = verifier.verify_server_cert(
+                cert,
+                &[],
+                &ServerName::try_from("localhost").unwrap(),
+                &[],
+                UnixTime::now(),
+            );
+            assert_eq!(result.is_ok(), ok, "{:?}", result);
+            assert_eq!(
+                verifier.failures().len(),
+                failures,
+                "{:?}",
+                verifier.failures()
+            );
+        }
+
+        let content = fs::read_to_string(&known_hosts).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        fs::remove_file(&known_hosts).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use serde::Serialize;
+use std::fmt::Write;
+use summary::Summary;
+
+/// field of both summaries.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Row {
+    pub field: String,
+    pub openssl: String,
+    pub rustls: String,
+    pub differs: bool,
+}
+
+/// summaries of the connections with openssl and rustls to the same server.
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub openssl: Summary,
+    pub rustls: Summary,
+}
+
+impl Comparison {
+    /// compare the fields of both summaries. chains are compared certificate
+    /// by certificate and errors only by their presence, as the messages
+    /// always differ.
+    pub fn rows(&self) -> Vec<Row> {
+        let (a, b) = (&self.openssl, &self.rustls);
+        let show = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+        let row = |field: String, openssl: Option<String>, rustls: Option<String>| Row {
+            field,
+            differs: openssl != rustls,
+            openssl: show(openssl),
+            rustls: show(rustls),
+        };
+
+        let mut rows = vec![
+            row("version".into(), a.version.clone(), b.version.clone()),
+            row("cipher".into(), a.cipher.clone(), b.cipher.clone()),
+        ];
+        for i in 0..a.chain.len().max(b.chain.len()) {
+            let cert = |summary: &Summary| summary.chain.get(i).map(|cert| cert.to_string());
+            rows.push(row(format!("chain[{}]", i), cert(a), cert(b)));
+        }
+        rows.push(row("digest".into(), a.digest.clone(), b.digest.clone()));
+        let status = |summary: &Summary| summary.status.map(|status| status.to_string());
+        rows.push(row("status".into(), status(a), status(b)));
+        rows.push(Row {
+            field: "error".into(),
+            openssl: show(a.error.clone()),
+            rustls: show(b.error.clone()),
+            differs: a.error.is_some() != b.error.is_some(),
+        });
+        rows
+    }
+
+    /// fields which differ between the stacks.
+    pub fn differences(&self) -> Vec<Row> {
+        self.rows().into_iter().filter(|row| row.differs).collect()
+    }
+
+    /// format rows as table, differing fields are marked with "*".
+    pub fn table(&self) -> String {
+        let rows = self.rows();
+        let width = |column: fn(&Row) -> &str, title: &str| {
+            rows.iter()
+                .map(|row| column(row).chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or_default()
+        };
+        let field = width(|row| &row.field, "FIELD");
+        let openssl = width(|row| &row.openssl, "OPENSSL");
+
+        let mut out = format!(
+            "  {:field$}  {:openssl$}  RUSTLS\n",
+            "FIELD",
+            "OPENSSL",
+            field = field,
+            openssl = openssl
+        );
+        for row in &rows {
+            writeln!(
+                out,
+                "{} {:field$}  {:openssl$}  {}",
+                if row.differs { '*' } else { ' ' },
+                row.field,
+                row.openssl,
+                row.rustls,
+                field = field,
+                openssl = openssl
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use summary::Cert;
+
+    fn cert(subject: &str, sha256: char) -> Cert {
+        Cert {
+            subject: subject.into(),
+            issuer: "CN=CA".into(),
+            sha256: sha256.to_string().repeat(64),
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        let comparison = Comparison {
+            openssl: Summary {
+                version: Some("TLSv1.2".into()),
+                cipher: Some("TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256".into()),
+                chain: vec![cert("CN=localhost", 'a'), cert("CN=CA", 'b')],
+                digest: Some("aaaa".into()),
+                status: Some(200),
+                error: None,
+            },
+            rustls: Summary {
+                chain: vec![cert("CN=localhost", 'a')],
+                error: Some("handshake failure".into()),
+                ..Summary::default()
+            },
+        };
+        let fields: Vec<_> = comparison
+            .differences()
+            .into_iter()
+            .map(|row| row.field)
+            .collect();
+        assert_eq!(
+            fields,
+            ["version", "cipher", "chain[1]", "digest", "status", "error"]
+        );
+        assert_eq!(
+            comparison.table(),
+            "  FIELD     OPENSSL                                  RUSTLS\n\
+             * version   TLSv1.2                                  -\n\
+             * cipher    TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256  -\n\
+             \x20 chain[0]  CN=localhost [aaaaaaaaaaaaaaaa]          CN=localhost [aaaaaaaaaaaaaaaa]\n\
+             * chain[1]  CN=CA [bbbbbbbbbbbbbbbb]                 -\n\
+             * digest    aaaa                                     -\n\
+             * status    200                                      -\n\
+             * error     -                                        handshake failure\n"
+        );
+    }
+}
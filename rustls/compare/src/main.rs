@@ -0,0 +1,60 @@
+use clap::Parser;
+use serde::Serialize;
+use std::error::Error;
+use std::path::PathBuf;
+
+mod comparison;
+mod stacks;
+
+/// Send the same HTTP request to a TLS server with OpenSSL and rustls and
+/// compare the negotiated version and cipher suite, the certificate chain and
+/// the response status
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Hostname of the server
+    #[clap(default_value = "www.rust-lang.org")]
+    addr: String,
+    /// Port of the server
+    #[clap(default_value_t = 443)]
+    port: u16,
+    /// Trust anchors in PEM format instead of the default certificate stores
+    #[clap(long)]
+    ca: Option<PathBuf>,
+    /// Print both summaries and the differences as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+/// json report with both summaries and their differences.
+#[derive(Serialize)]
+struct Report<'a> {
+    #[serde(flatten)]
+    comparison: &'a comparison::Comparison,
+    differences: &'a [comparison::Row],
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let ca = cli.ca.as_deref();
+    let comparison = comparison::Comparison {
+        openssl: stacks::openssl(&cli.addr, cli.port, ca),
+        rustls: stacks::rustls(&cli.addr, cli.port, ca),
+    };
+    let differences = comparison.differences();
+
+    if cli.json {
+        let report = Report {
+            comparison: &comparison,
+            differences: &differences,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", comparison.table());
+    }
+
+    if !differences.is_empty() {
+        return Err(format!("stacks differ in {} fields", differences.len()).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use openssl::ssl::{SslConnector, SslMethod};
+use rustls::ClientConfig;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use summary::Summary;
+
+/// connect with the openssl client and fill summary, which keeps the results
+/// until an error occurred.
+fn openssl_session(
+    addr: &str,
+    port: u16,
+    ca: Option<&Path>,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ca) = ca {
+        builder.set_ca_file(ca)?;
+    }
+    openssl_client::get(builder.build().configure()?, addr, port, summary)?;
+    Ok(())
+}
+
+/// connect to addr with openssl.
+pub fn openssl(addr: &str, port: u16, ca: Option<&Path>) -> Summary {
+    let mut summary = Summary::default();
+    if let Err(err) = openssl_session(addr, port, ca, &mut summary) {
+        summary.error = Some(err.to_string());
+    }
+    summary
+}
+
+/// connect with the rustls client and fill summary, which keeps the results
+/// until an error occurred.
+fn rustls_session(
+    addr: &str,
+    port: u16,
+    ca: Option<&Path>,
+    summary: &mut Summary,
+) -> Result<(), Box<dyn Error>> {
+    let config = ClientConfig::builder()
+        .with_root_certificates(rustls_client::load_roots(ca)?)
+        .with_no_client_auth();
+    rustls_client::get(Arc::new(config), addr, port, None, summary)?;
+    Ok(())
+}
+
+/// connect to addr with rustls.
+pub fn rustls(addr: &str, port: u16, ca: Option<&Path>) -> Summary {
+    let mut summary = Summary::default();
+    if let Err(err) = rustls_session(addr, port, ca, &mut summary) {
+        summary.error = Some(err.to_string());
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparison::Comparison;
+    use openssl::pkey::PKey;
+    use openssl::ssl::{SslAcceptor, SslVersion};
+    use openssl::x509::X509;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::process;
+    use std::thread;
+
+    /// start tls server on a random local port which answers each request
+    /// with 204. tls 1.2 is used with the cipher list if given.
+    fn server(cert: &rcgen::CertifiedKey<rcgen::KeyPair>, ciphers: Option<&str>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate(&X509::from_der(cert.cert.der()).unwrap())
+            .unwrap();
+        acceptor
+            .set_private_key(
+                &PKey::private_key_from_der(&cert.signing_key.serialize_der()).unwrap(),
+            )
+            .unwrap();
+        if let Some(ciphers) = ciphers {
+            acceptor
+                .set_max_proto_version(Some(SslVersion::TLS1_2))
+                .unwrap();
+            acceptor.set_cipher_list(ciphers).unwrap();
+        }
+        let acceptor = acceptor.build();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                let Ok(mut stream) = acceptor.accept(sock.unwrap()) else {
+                    continue;
+                };
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n");
+                let _ = stream.shutdown();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_compare() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let ca = std::env::temp_dir().join(format!("compare-ca-{}.pem", process::id()));
+        fs::write(&ca, cert.cert.pem()).unwrap();
+
+        // cipher list, expected version and cipher of both stacks, fields
+        // which differ
+        for (ciphers, version, cipher, differences) in [
+            (None, Some("TLSv1.3"), None, vec![]),
+            (
+                Some("ECDHE-ECDSA-AES128-GCM-SHA256"),
+                Some("TLSv1.2"),
+                Some("TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+                vec![],
+            ),
+            // cbc suites are not supported by rustls
+            (
+                Some("ECDHE-ECDSA-AES128-SHA256"),
+                None,
+                None,
+                vec!["version", "cipher", "chain[0]", "digest", "status", "error"],
+            ),
+        ] {
+            let port = server(&cert, ciphers);
+            let comparison = Comparison {
+                openssl: openssl("localhost", port, Some(&ca)),
+                rustls: rustls("localhost", port, Some(&ca)),
+            };
+            let fields: Vec<_> = comparison
+                .differences()
+                .into_iter()
+                .map(|row| row.field)
+                .collect();
+            assert_eq!(fields, differences, "{}", comparison.table());
+
+            let openssl = &comparison.openssl;
+            assert_eq!(openssl.error, None);
+            assert_eq!(openssl.status, Some(204));
+            assert_eq!(openssl.chain.len(), 1);
+            assert_eq!(openssl.chain[0].subject, "CN=rcgen self signed cert");
+            if differences.is_empty() {
+                assert_eq!(openssl.version.as_deref(), version);
+            }
+            if let Some(cipher) = cipher {
+                assert_eq!(openssl.cipher.as_deref(), Some(cipher));
+            }
+        }
+        fs::remove_file(&ca).unwrap();
+    }
+}
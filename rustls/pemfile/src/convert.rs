@@ -0,0 +1,544 @@
+use clap::ValueEnum;
+use p12_keystore::{
+    Certificate as P12Certificate, KeyStore, KeyStoreEntry, Pkcs12ImportPolicy, PrivateKey,
+    PrivateKeyChain,
+};
+use pem::{EncodeConfig, LineEnding, Pem};
+use rustls_pemfile::{read_one, Item};
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fs::{OpenOptions, Permissions};
+use std::io::Write;
+use std::iter;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::certification_request::X509CertificationRequest;
+use x509_parser::prelude::FromDer;
+use x509_parser::revocation_list::CertificateRevocationList;
+
+use crate::der::{
+    algorithm, pkcs8_parts, sequence, write_tlv, BIT_STRING, CONTEXT_0, INTEGER, NULL,
+    OCTET_STRING, OID_EC_PUBLIC_KEY, OID_RSA_ENCRYPTION, SEQUENCE,
+};
+/// kind of a pem item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Certificate,
+    Pkcs1Key,
+    Pkcs8Key,
+    Sec1Key,
+    PublicKey,
+    Crl,
+    Csr,
+}
+
+impl Kind {
+    /// pem label of the kind.
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Certificate => "CERTIFICATE",
+            Kind::Pkcs1Key => "RSA PRIVATE KEY",
+            Kind::Pkcs8Key => "PRIVATE KEY",
+            Kind::Sec1Key => "EC PRIVATE KEY",
+            Kind::PublicKey => "PUBLIC KEY",
+            Kind::Crl => "X509 CRL",
+            Kind::Csr => "CERTIFICATE REQUEST",
+        }
+    }
+
+    pub fn is_private_key(self) -> bool {
+        matches!(self, Kind::Pkcs1Key | Kind::Pkcs8Key | Kind::Sec1Key)
+    }
+}
+
+/// der encoded item of a given kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerItem {
+    pub kind: Kind,
+    pub der: Vec<u8>,
+}
+
+impl TryFrom<Item> for DerItem {
+    type Error = String;
+
+    fn try_from(item: Item) -> Result<Self, Self::Error> {
+        let (kind, der) = match item {
+            Item::X509Certificate(cert) => (Kind::Certificate, cert.to_vec()),
+            Item::Pkcs1Key(key) => (Kind::Pkcs1Key, key.secret_pkcs1_der().to_vec()),
+            Item::Pkcs8Key(key) => (Kind::Pkcs8Key, key.secret_pkcs8_der().to_vec()),
+            Item::Sec1Key(key) => (Kind::Sec1Key, key.secret_sec1_der().to_vec()),
+            Item::SubjectPublicKeyInfo(spki) => (Kind::PublicKey, spki.to_vec()),
+            Item::Crl(crl) => (Kind::Crl, crl.to_vec()),
+            Item::Csr(csr) => (Kind::Csr, csr.to_vec()),
+            _ => return Err("unsupported pem item".into()),
+        };
+        Ok(DerItem { kind, der })
+    }
+}
+
+/// output file format.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// PEM file with any number of items
+    Pem,
+    /// DER file with a single item
+    Der,
+    /// PKCS#12 archive with a private key and its certificates
+    P12,
+}
+
+/// private key format.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum KeyFormat {
+    /// PKCS#1 RSA private key
+    Pkcs1,
+    /// PKCS#8 private key of any algorithm
+    Pkcs8,
+    /// SEC1 elliptic curve private key
+    Sec1,
+}
+
+/// guess kind of der encoded data.
+pub fn detect(der: &[u8]) -> Option<Kind> {
+    let elements = sequence(der)?;
+    let tags = elements.iter().map(|e| e.tag).collect::<Vec<_>>();
+    match tags[..] {
+        [SEQUENCE, SEQUENCE, BIT_STRING] => {
+            if X509Certificate::from_der(der).is_ok() {
+                Some(Kind::Certificate)
+            } else if CertificateRevocationList::from_der(der).is_ok() {
+                Some(Kind::Crl)
+            } else if X509CertificationRequest::from_der(der).is_ok() {
+                Some(Kind::Csr)
+            } else {
+                None
+            }
+        }
+        [SEQUENCE, BIT_STRING] => Some(Kind::PublicKey),
+        // version 2 keys, e.g., of Ed25519, have version 1
+        [INTEGER, SEQUENCE, OCTET_STRING, ..] if matches!(elements[0].contents, [0] | [1]) => {
+            Some(Kind::Pkcs8Key)
+        }
+        [INTEGER, OCTET_STRING, ..] if elements[0].contents == [1] => Some(Kind::Sec1Key),
+        [INTEGER, INTEGER, INTEGER, INTEGER, INTEGER, INTEGER, INTEGER, INTEGER, INTEGER, ..]
+            if elements[0].contents == [0] =>
+        {
+            Some(Kind::Pkcs1Key)
+        }
+        _ => None,
+    }
+}
+
+/// check whether der encoded data is a PKCS#12 archive.
+pub fn is_pkcs12(der: &[u8]) -> bool {
+    match sequence(der) {
+        Some(elements) => {
+            elements.len() >= 2
+                && elements[0].tag == INTEGER
+                && elements[0].contents == [3]
+                && elements[1].tag == SEQUENCE
+        }
+        None => false,
+    }
+}
+
+/// read all items of pem data.
+pub fn read_pem(mut data: &[u8]) -> Result<Vec<DerItem>, Box<dyn Error>> {
+    let mut items = Vec::new();
+    for item in iter::from_fn(|| read_one(&mut data).transpose()) {
+        items.push(item?.try_into()?);
+    }
+    Ok(items)
+}
+
+/// encode items as pem without empty lines between them.
+pub fn write_pem(items: &[DerItem]) -> String {
+    let config = EncodeConfig::new().set_line_ending(LineEnding::LF);
+    items
+        .iter()
+        .map(|item| pem::encode_config(&Pem::new(item.kind.label(), item.der.clone()), config))
+        .collect()
+}
+
+/// read items of a PKCS#12 archive, certificates come before keys.
+pub fn read_pkcs12(data: &[u8], password: &str) -> Result<Vec<DerItem>, Box<dyn Error>> {
+    let key_store = KeyStore::from_pkcs12(data, password, Pkcs12ImportPolicy::Relaxed)?;
+    let mut certs = Vec::new();
+    let mut keys = Vec::new();
+    for (_, entry) in key_store.entries() {
+        match entry {
+            KeyStoreEntry::PrivateKeyChain(chain) => {
+                certs.extend(chain.certs().iter().map(|cert| DerItem {
+                    kind: Kind::Certificate,
+                    der: cert.as_der().to_vec(),
+                }));
+                keys.push(DerItem {
+                    kind: Kind::Pkcs8Key,
+                    der: chain.key().as_der().to_vec(),
+                });
+            }
+            KeyStoreEntry::Certificate(cert) => certs.push(DerItem {
+                kind: Kind::Certificate,
+                der: cert.as_der().to_vec(),
+            }),
+            KeyStoreEntry::Secret(_) => {}
+        }
+    }
+    certs.extend(keys);
+    Ok(certs)
+}
+
+/// create PKCS#12 archive from a private key and its certificates, the leaf
+/// certificate first.
+pub fn write_pkcs12(
+    items: &[DerItem],
+    name: &str,
+    password: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut keys = items.iter().filter(|item| item.kind.is_private_key());
+    let key = keys.next().ok_or("PKCS#12 archives need a private key")?;
+    if keys.next().is_some() {
+        return Err("PKCS#12 archives hold a single private key".into());
+    }
+    let key = convert_key(key, KeyFormat::Pkcs8)?;
+
+    let mut certs = Vec::new();
+    for item in items {
+        if item.kind == Kind::Certificate {
+            certs.push(P12Certificate::from_der(&item.der)?);
+        }
+    }
+    // use sha-1 digest of the leaf certificate as local key id like openssl
+    let leaf = certs.first().ok_or("PKCS#12 archives need a certificate")?;
+    let key_id = Sha1::digest(leaf.as_der()).to_vec();
+    let chain = PrivateKeyChain::new(key_id, PrivateKey::from_der(&key.der)?, certs);
+
+    let mut key_store = KeyStore::new();
+    key_store.add_entry(name, KeyStoreEntry::PrivateKeyChain(chain));
+    Ok(key_store.writer(password).write()?)
+}
+
+/// read items from pem, der or PKCS#12 data.
+pub fn read(data: &[u8], password: &str) -> Result<Vec<DerItem>, Box<dyn Error>> {
+    if data.windows(11).any(|w| w == b"-----BEGIN ") {
+        return read_pem(data);
+    }
+    if is_pkcs12(data) {
+        return read_pkcs12(data, password);
+    }
+    match detect(data) {
+        Some(kind) => Ok(vec![DerItem {
+            kind,
+            der: data.to_vec(),
+        }]),
+        None => Err("unknown file format".into()),
+    }
+}
+
+/// create PKCS#8 key from algorithm oid, encoded parameters and private key.
+fn pkcs8(oid: &[u8], params: &[u8], key: &[u8]) -> Vec<u8> {
+    let algorithm = algorithm(oid, params);
+    let contents = [
+        &write_tlv(INTEGER, &[0])[..],
+        &algorithm,
+        &write_tlv(OCTET_STRING, key),
+    ]
+    .concat();
+    write_tlv(SEQUENCE, &contents)
+}
+
+fn pkcs1_to_pkcs8(der: &[u8]) -> Vec<u8> {
+    pkcs8(OID_RSA_ENCRYPTION, &write_tlv(NULL, &[]), der)
+}
+
+fn pkcs8_to_pkcs1(der: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (oid, _, key) = pkcs8_parts(der)?;
+    if oid != OID_RSA_ENCRYPTION {
+        return Err("only RSA keys can be converted to PKCS#1".into());
+    }
+    Ok(key.to_vec())
+}
+
+/// the curve parameters are moved from the SEC1 key to the PKCS#8 algorithm
+/// identifier like openssl does.
+fn sec1_to_pkcs8(der: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let elements = sequence(der).ok_or("invalid SEC1 key")?;
+    let params = elements
+        .iter()
+        .find(|e| e.tag == CONTEXT_0)
+        .ok_or("SEC1 key without curve parameters")?;
+    let key = elements
+        .iter()
+        .filter(|e| e.tag != CONTEXT_0)
+        .flat_map(|e| e.encoded)
+        .copied()
+        .collect::<Vec<_>>();
+    Ok(pkcs8(
+        OID_EC_PUBLIC_KEY,
+        params.contents,
+        &write_tlv(SEQUENCE, &key),
+    ))
+}
+
+fn pkcs8_to_sec1(der: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (oid, params, key) = pkcs8_parts(der)?;
+    if oid != OID_EC_PUBLIC_KEY {
+        return Err("only EC keys can be converted to SEC1".into());
+    }
+    let elements = sequence(key).ok_or("invalid SEC1 key")?;
+    if elements.iter().any(|e| e.tag == CONTEXT_0) || elements.len() < 2 {
+        return Ok(key.to_vec());
+    }
+    // add curve parameters after version and private key
+    let mut contents = Vec::new();
+    for (i, element) in elements.iter().enumerate() {
+        if i == 2 {
+            contents.extend(write_tlv(CONTEXT_0, params));
+        }
+        contents.extend(element.encoded);
+    }
+    if elements.len() == 2 {
+        contents.extend(write_tlv(CONTEXT_0, params));
+    }
+    Ok(write_tlv(SEQUENCE, &contents))
+}
+
+/// convert private key to format, other items are returned unchanged.
+pub fn convert_key(item: &DerItem, format: KeyFormat) -> Result<DerItem, Box<dyn Error>> {
+    let (kind, der) = match (item.kind, format) {
+        (Kind::Pkcs1Key, KeyFormat::Pkcs8) => (Kind::Pkcs8Key, pkcs1_to_pkcs8(&item.der)),
+        (Kind::Sec1Key, KeyFormat::Pkcs8) => (Kind::Pkcs8Key, sec1_to_pkcs8(&item.der)?),
+        (Kind::Pkcs8Key, KeyFormat::Pkcs1) => (Kind::Pkcs1Key, pkcs8_to_pkcs1(&item.der)?),
+        (Kind::Pkcs8Key, KeyFormat::Sec1) => (Kind::Sec1Key, pkcs8_to_sec1(&item.der)?),
+        (Kind::Pkcs1Key, KeyFormat::Sec1) => {
+            return Err("RSA keys cannot be converted to SEC1".into());
+        }
+        (Kind::Sec1Key, KeyFormat::Pkcs1) => {
+            return Err("EC keys cannot be converted to PKCS#1".into());
+        }
+        _ => return Ok(item.clone()),
+    };
+    Ok(DerItem { kind, der })
+}
+
+/// write data to file, files with private keys are only readable by the
+/// current user.
+pub fn write_file(path: &Path, data: &[u8], private: bool) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(if private { 0o600 } else { 0o644 })
+        .open(path)?;
+    // the mode only applies to new files, restrict existing ones too
+    if private {
+        file.set_permissions(Permissions::from_mode(0o600))?;
+    }
+    file.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::der;
+    use crate::inspect::crypto_provider;
+    use rcgen::{
+        CertificateParams, CertificateRevocationListParams, Issuer, KeyIdMethod, KeyPair,
+        PublicKeyData, RsaKeySize, SerialNumber,
+    };
+    use rustls::pki_types::PrivateKeyDer;
+    use std::slice;
+
+    fn pkcs8_key(key: &KeyPair) -> DerItem {
+        DerItem {
+            kind: Kind::Pkcs8Key,
+            der: key.serialize_der(),
+        }
+    }
+
+    /// create items of every kind.
+    fn items() -> Vec<DerItem> {
+        let ec_key = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let rsa_key =
+            KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap();
+        let params = CertificateParams::new(vec!["example.com".into()]).unwrap();
+        let cert = params.self_signed(&ec_key).unwrap();
+        let csr = params.serialize_request(&rsa_key).unwrap();
+        let now = time::OffsetDateTime::now_utc();
+        let crl = CertificateRevocationListParams {
+            this_update: now,
+            next_update: now + time::Duration::days(1),
+            crl_number: SerialNumber::from(1),
+            issuing_distribution_point: None,
+            revoked_certs: Vec::new(),
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&Issuer::new(params, &ec_key))
+        .unwrap();
+
+        vec![
+            DerItem {
+                kind: Kind::Certificate,
+                der: cert.der().to_vec(),
+            },
+            pkcs8_key(&ec_key),
+            convert_key(&pkcs8_key(&ec_key), KeyFormat::Sec1).unwrap(),
+            convert_key(&pkcs8_key(&rsa_key), KeyFormat::Pkcs1).unwrap(),
+            DerItem {
+                kind: Kind::PublicKey,
+                der: rsa_key.subject_public_key_info(),
+            },
+            DerItem {
+                kind: Kind::Crl,
+                der: crl.der().to_vec(),
+            },
+            DerItem {
+                kind: Kind::Csr,
+                der: csr.der().to_vec(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_pem_der_round_trip() {
+        let items = items();
+
+        // merge into one pem file and split it again
+        let pem = write_pem(&items);
+        assert_eq!(read(pem.as_bytes(), "").unwrap(), items);
+        let mut merged = String::new();
+        for item in &items {
+            let pem = write_pem(slice::from_ref(item));
+            assert_eq!(read(pem.as_bytes(), "").unwrap(), slice::from_ref(item));
+            merged += &pem;
+        }
+        assert_eq!(merged, pem);
+
+        // der files hold a single item of a detected kind
+        for item in &items {
+            assert_eq!(
+                read(&item.der, "").unwrap(),
+                slice::from_ref(item),
+                "{:?}",
+                item.kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let ec_key = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384).unwrap();
+        let rsa_key =
+            KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap();
+        let ed_key = KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+
+        for (key, format, kind) in [
+            (&ec_key, KeyFormat::Sec1, Kind::Sec1Key),
+            (&rsa_key, KeyFormat::Pkcs1, Kind::Pkcs1Key),
+        ] {
+            let pkcs8 = pkcs8_key(key);
+            let converted = convert_key(&pkcs8, format).unwrap();
+            assert_eq!(converted.kind, kind);
+            assert_eq!(convert_key(&converted, KeyFormat::Pkcs8).unwrap(), pkcs8);
+            assert_eq!(convert_key(&converted, format).unwrap(), converted);
+
+            // the converted key is the same key for the crypto provider
+            let der = match kind {
+                Kind::Sec1Key => PrivateKeyDer::Sec1(converted.der.into()),
+                _ => PrivateKeyDer::Pkcs1(converted.der.into()),
+            };
+            let signing_key = crypto_provider()
+                .key_provider
+                .load_private_key(der)
+                .unwrap();
+            assert_eq!(
+                signing_key.public_key().unwrap().as_ref(),
+                key.subject_public_key_info()
+            );
+        }
+
+        for (key, format) in [
+            (&ec_key, KeyFormat::Pkcs1),
+            (&rsa_key, KeyFormat::Sec1),
+            (&ed_key, KeyFormat::Pkcs1),
+            (&ed_key, KeyFormat::Sec1),
+        ] {
+            assert!(convert_key(&pkcs8_key(key), format).is_err());
+        }
+    }
+
+    #[test]
+    fn test_pkcs12_round_trip() {
+        let items = items();
+        let cert = items[0].clone();
+
+        for key in [&items[1], &items[2]] {
+            let p12 = write_pkcs12(&[cert.clone(), key.clone()], "test", "secret").unwrap();
+            assert!(is_pkcs12(&p12));
+            assert_eq!(
+                read(&p12, "secret").unwrap(),
+                [cert.clone(), convert_key(key, KeyFormat::Pkcs8).unwrap()]
+            );
+            assert!(read(&p12, "wrong").is_err());
+        }
+
+        assert!(write_pkcs12(slice::from_ref(&cert), "test", "").is_err());
+        assert!(write_pkcs12(&[items[1].clone()], "test", "").is_err());
+    }
+
+    #[test]
+    fn test_public_key() {
+        let ec_key = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let rsa_key =
+            KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap();
+        for (key, format) in [
+            (&ec_key, KeyFormat::Pkcs8),
+            (&ec_key, KeyFormat::Sec1),
+            (&rsa_key, KeyFormat::Pkcs8),
+            (&rsa_key, KeyFormat::Pkcs1),
+        ] {
+            let item = convert_key(&pkcs8_key(key), format).unwrap();
+            let der = match item.kind {
+                Kind::Pkcs1Key => PrivateKeyDer::Pkcs1(item.der.into()),
+                Kind::Sec1Key => PrivateKeyDer::Sec1(item.der.into()),
+                _ => PrivateKeyDer::Pkcs8(item.der.into()),
+            };
+            assert_eq!(
+                der::public_key(&der).unwrap(),
+                key.subject_public_key_info(),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_ed25519_round_trip() {
+        // PKCS#8 version 2 key with the public key
+        let key = pkcs8_key(&KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap());
+        assert_eq!(sequence(&key.der).unwrap()[0].contents, [1]);
+
+        let pem = write_pem(slice::from_ref(&key));
+        let items = read(pem.as_bytes(), "").unwrap();
+        assert_eq!(items, slice::from_ref(&key));
+        let items = read(&items[0].der, "").unwrap();
+        assert_eq!(items, slice::from_ref(&key));
+        assert_eq!(write_pem(&items), pem);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let path = std::env::temp_dir().join(format!("pemfile-test-{}.pem", std::process::id()));
+        for (private, mode) in [(false, 0o644), (true, 0o600)] {
+            // replace a readable file
+            std::fs::write(&path, "old").unwrap();
+            std::fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+            write_file(&path, b"new", private).unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, mode, "{}", private);
+            assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use rustls::pki_types::PrivateKeyDer;
+use std::error::Error;
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+pub const CONTEXT_0: u8 = 0xa0;
+pub const CONTEXT_1: u8 = 0xa1;
+/// implicitly tagged public key of a PKCS#8 version 2 key.
+pub const CONTEXT_PRIMITIVE_1: u8 = 0x81;
+
+/// der encoded contents of the rsaEncryption object identifier.
+pub const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// der encoded contents of the id-ecPublicKey object identifier.
+pub const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+/// split der value into tag, contents and remaining input. only single byte
+/// tags are supported which is enough for the structures handled here.
+pub fn read_tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&len, mut input) = input.split_first()?;
+    let len = if len < 0x80 {
+        len as usize
+    } else {
+        let n = (len & 0x7f) as usize;
+        if n == 0 || n > 4 || input.len() < n {
+            return None;
+        }
+        let len = input[..n]
+            .iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        input = &input[n..];
+        len
+    };
+    if input.len() < len {
+        return None;
+    }
+    Some((tag, &input[..len], &input[len..]))
+}
+
+/// encode der value with tag and contents.
+pub fn write_tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if contents.len() < 0x80 {
+        out.push(contents.len() as u8);
+    } else {
+        let len = contents.len().to_be_bytes();
+        let skip = len.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (len.len() - skip) as u8);
+        out.extend(&len[skip..]);
+    }
+    out.extend(contents);
+    out
+}
+
+/// element of a der sequence.
+pub struct Element<'a> {
+    pub tag: u8,
+    pub contents: &'a [u8],
+    pub encoded: &'a [u8],
+}
+
+/// parse der sequence into its elements.
+pub fn sequence(der: &[u8]) -> Option<Vec<Element<'_>>> {
+    let (tag, mut contents, rest) = read_tlv(der)?;
+    if tag != SEQUENCE || !rest.is_empty() {
+        return None;
+    }
+    let mut elements = Vec::new();
+    while !contents.is_empty() {
+        let (tag, value, rest) = read_tlv(contents)?;
+        elements.push(Element {
+            tag,
+            contents: value,
+            encoded: &contents[..contents.len() - rest.len()],
+        });
+        contents = rest;
+    }
+    Some(elements)
+}
+
+/// algorithm identifier oid, encoded parameters and private key of a PKCS#8
+/// key.
+pub type Pkcs8Parts<'a> = (&'a [u8], &'a [u8], &'a [u8]);
+
+pub fn pkcs8_parts(der: &[u8]) -> Result<Pkcs8Parts<'_>, Box<dyn Error>> {
+    let elements = sequence(der).ok_or("invalid PKCS#8 key")?;
+    let (Some(algorithm), Some(key)) = (elements.get(1), elements.get(2)) else {
+        return Err("invalid PKCS#8 key".into());
+    };
+    let (tag, oid, params) = read_tlv(algorithm.contents).ok_or("invalid PKCS#8 key")?;
+    if algorithm.tag != SEQUENCE || tag != OID || key.tag != OCTET_STRING {
+        return Err("invalid PKCS#8 key".into());
+    }
+    Ok((oid, params, key.contents))
+}
+
+/// create algorithm identifier from oid and encoded parameters.
+pub fn algorithm(oid: &[u8], params: &[u8]) -> Vec<u8> {
+    write_tlv(SEQUENCE, &[&write_tlv(OID, oid)[..], params].concat())
+}
+
+/// create subject public key info from algorithm oid, encoded parameters and
+/// bit string contents of the public key.
+fn spki(oid: &[u8], params: &[u8], key: &[u8]) -> Vec<u8> {
+    write_tlv(
+        SEQUENCE,
+        &[&algorithm(oid, params)[..], &write_tlv(BIT_STRING, key)].concat(),
+    )
+}
+
+/// public key of a PKCS#1 key from its modulus and exponent.
+fn pkcs1_public_key(der: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let elements = sequence(der).ok_or("invalid PKCS#1 key")?;
+    let (Some(modulus), Some(exponent)) = (elements.get(1), elements.get(2)) else {
+        return Err("invalid PKCS#1 key".into());
+    };
+    let key = write_tlv(SEQUENCE, &[modulus.encoded, exponent.encoded].concat());
+    Ok(spki(
+        OID_RSA_ENCRYPTION,
+        &write_tlv(NULL, &[]),
+        &[&[0][..], &key].concat(),
+    ))
+}
+
+/// public key of a SEC1 key, params are used for keys without curve
+/// parameters like those in PKCS#8 keys.
+fn sec1_public_key(der: &[u8], params: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let elements = sequence(der).ok_or("invalid SEC1 key")?;
+    let params = elements
+        .iter()
+        .find(|e| e.tag == CONTEXT_0)
+        .map_or(params, |e| e.contents);
+    let key = elements
+        .iter()
+        .find(|e| e.tag == CONTEXT_1)
+        .ok_or("SEC1 key without public key")?;
+    match read_tlv(key.contents) {
+        Some((BIT_STRING, key, _)) if !params.is_empty() => {
+            Ok(spki(OID_EC_PUBLIC_KEY, params, key))
+        }
+        _ => Err("invalid SEC1 key".into()),
+    }
+}
+
+/// get der encoded subject public key info of a private key from the public
+/// key stored in it, without loading the key.
+pub fn public_key(key: &PrivateKeyDer) -> Result<Vec<u8>, Box<dyn Error>> {
+    match key {
+        PrivateKeyDer::Pkcs1(key) => pkcs1_public_key(key.secret_pkcs1_der()),
+        PrivateKeyDer::Sec1(key) => sec1_public_key(key.secret_sec1_der(), &[]),
+        PrivateKeyDer::Pkcs8(key) => {
+            let der = key.secret_pkcs8_der();
+            let (oid, params, key) = pkcs8_parts(der)?;
+            if oid == OID_RSA_ENCRYPTION {
+                return pkcs1_public_key(key);
+            }
+            if oid == OID_EC_PUBLIC_KEY {
+                return sec1_public_key(key, params);
+            }
+            // version 2 keys, e.g., of Ed25519, may include the public key
+            let elements = sequence(der).ok_or("invalid PKCS#8 key")?;
+            let key = elements
+                .iter()
+                .find(|e| e.tag == CONTEXT_PRIMITIVE_1)
+                .ok_or("PKCS#8 key without public key")?;
+            Ok(spki(oid, params, key.contents))
+        }
+        _ => Err("unsupported private key".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{KeyPair, PublicKeyData, RsaKeySize};
+    use x509_parser::prelude::FromDer;
+    use x509_parser::x509::SubjectPublicKeyInfo;
+
+    #[test]
+    fn test_public_key() {
+        let ec_key = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let rsa_key =
+            KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap();
+        let ed_key = KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        for key in [&ec_key, &rsa_key, &ed_key] {
+            let der = key.serialize_der();
+            assert_eq!(
+                public_key(&PrivateKeyDer::Pkcs8(der.into())).unwrap(),
+                key.subject_public_key_info(),
+                "{:?}",
+                key.algorithm()
+            );
+        }
+
+        // PKCS#1 key of a PKCS#8 RSA key
+        let der = rsa_key.serialize_der();
+        let (_, _, pkcs1) = pkcs8_parts(&der).unwrap();
+        assert_eq!(
+            public_key(&PrivateKeyDer::Pkcs1(pkcs1.to_vec().into())).unwrap(),
+            rsa_key.subject_public_key_info()
+        );
+
+        // RSA key with a 1024 bit modulus which the crypto provider rejects
+        let mut modulus = vec![0x00, 0xc0];
+        modulus.resize(129, 0x01);
+        let mut contents = write_tlv(INTEGER, &[0]);
+        contents.extend(write_tlv(INTEGER, &modulus));
+        contents.extend(write_tlv(INTEGER, &[0x01, 0x00, 0x01]));
+        for _ in 0..6 {
+            contents.extend(write_tlv(INTEGER, &[0x01]));
+        }
+        let key = PrivateKeyDer::Pkcs1(write_tlv(SEQUENCE, &contents).into());
+        let der = public_key(&key).unwrap();
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&der).unwrap();
+        assert_eq!(spki.parsed().unwrap().key_size(), 1024);
+
+        // SEC1 key without public key
+        let der = write_tlv(
+            SEQUENCE,
+            &[
+                &write_tlv(INTEGER, &[1])[..],
+                &write_tlv(OCTET_STRING, &[1; 32]),
+            ]
+            .concat(),
+        );
+        assert!(public_key(&PrivateKeyDer::Sec1(der.into())).is_err());
+    }
+
+    #[test]
+    fn test_tlv() {
+        for len in [0, 1, 127, 128, 255, 256, 70000] {
+            let contents = vec![0x42; len];
+            let der = write_tlv(OCTET_STRING, &contents);
+            assert_eq!(read_tlv(&der), Some((OCTET_STRING, &contents[..], &[][..])));
+        }
+        assert_eq!(read_tlv(&[OCTET_STRING, 2, 0]), None);
+        assert_eq!(read_tlv(&[OCTET_STRING, 0x80]), None);
+    }
+}
@@ -0,0 +1,524 @@
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::{
+    DistributionPointName, ExtendedKeyUsage, GeneralName, ParsedExtension,
+};
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::oid_registry::{
+    Oid, OID_KEY_TYPE_EC_PUBLIC_KEY, OID_PKCS1_RSAENCRYPTION, OID_SIG_ED25519, OID_SIG_ED448,
+};
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::PublicKey;
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::der;
+
+/// decoded public key.
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicKeyInfo {
+    pub algorithm: String,
+    pub bits: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
+    pub spki_sha256: String,
+}
+
+/// decoded certificate extension.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionInfo {
+    pub name: String,
+    pub critical: bool,
+    pub value: String,
+}
+
+/// decoded x.509 certificate.
+#[derive(Debug, Clone, Serialize)]
+pub struct CertInfo {
+    pub version: u32,
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub expired: bool,
+    pub signature_algorithm: String,
+    pub public_key: PublicKeyInfo,
+    pub is_ca: bool,
+    pub subject_alt_names: Vec<String>,
+    pub key_usage: Vec<String>,
+    pub extended_key_usage: Vec<String>,
+    pub extensions: Vec<ExtensionInfo>,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+/// decoded private key.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKeyInfo>,
+    /// why the public key could not be determined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// whether the key matches one of the certificates it was checked against.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<bool>,
+    /// subject of the matching certificate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+}
+
+/// decoded pem item.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ItemInfo {
+    Certificate(Box<CertInfo>),
+    PrivateKey(KeyInfo),
+    PublicKey(PublicKeyInfo),
+    Other { kind: &'static str, size: usize },
+}
+
+/// format bytes as colon separated upper case hex like openssl.
+pub fn fingerprint(bytes: &[u8]) -> String {
+    let hex = hex::encode_upper(bytes);
+    let mut s = String::with_capacity(hex.len() * 3 / 2);
+    for (i, c) in hex.chars().enumerate() {
+        if i > 0 && i % 2 == 0 {
+            s.push(':');
+        }
+        s.push(c);
+    }
+    s
+}
+
+fn oid_name(oid: &Oid) -> String {
+    match oid2sn(oid, oid_registry()) {
+        Ok(name) => name.into(),
+        Err(_) => oid.to_id_string(),
+    }
+}
+
+pub fn format_time(time: ASN1Time) -> String {
+    time.to_datetime()
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| time.to_string())
+}
+
+/// format general name in the style of openssl.
+fn general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(name) => format!("DNS:{}", name),
+        GeneralName::RFC822Name(name) => format!("email:{}", name),
+        GeneralName::URI(uri) => format!("URI:{}", uri),
+        GeneralName::IPAddress(ip) => match <[u8; 4]>::try_from(*ip) {
+            Ok(ip) => format!("IP:{}", IpAddr::from(ip)),
+            Err(_) => match <[u8; 16]>::try_from(*ip) {
+                Ok(ip) => format!("IP:{}", IpAddr::from(ip)),
+                Err(_) => format!("IP:{}", fingerprint(ip)),
+            },
+        },
+        GeneralName::DirectoryName(name) => format!("DirName:{}", name),
+        GeneralName::RegisteredID(oid) => format!("RID:{}", oid_name(oid)),
+        name => name.to_string(),
+    }
+}
+
+fn general_names(names: &[GeneralName]) -> String {
+    names
+        .iter()
+        .map(general_name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn extended_key_usage(eku: &ExtendedKeyUsage) -> Vec<String> {
+    let mut usages = Vec::new();
+    for (set, name) in [
+        (eku.any, "Any Extended Key Usage"),
+        (eku.server_auth, "TLS Web Server Authentication"),
+        (eku.client_auth, "TLS Web Client Authentication"),
+        (eku.code_signing, "Code Signing"),
+        (eku.email_protection, "E-mail Protection"),
+        (eku.time_stamping, "Time Stamping"),
+        (eku.ocsp_signing, "OCSP Signing"),
+    ] {
+        if set {
+            usages.push(name.to_string());
+        }
+    }
+    usages.extend(eku.other.iter().map(oid_name));
+    usages
+}
+
+/// describe value of a parsed extension.
+fn extension_value(ext: &ParsedExtension) -> String {
+    match ext {
+        ParsedExtension::SubjectAlternativeName(san) => general_names(&san.general_names),
+        ParsedExtension::IssuerAlternativeName(ian) => general_names(&ian.general_names),
+        ParsedExtension::KeyUsage(usage) => usage.to_string(),
+        ParsedExtension::ExtendedKeyUsage(eku) => extended_key_usage(eku).join(", "),
+        ParsedExtension::BasicConstraints(bc) => match bc.path_len_constraint {
+            Some(len) => format!("CA:{}, pathlen:{}", bc.ca, len),
+            None => format!("CA:{}", bc.ca),
+        },
+        ParsedExtension::SubjectKeyIdentifier(id) => fingerprint(id.0),
+        ParsedExtension::AuthorityKeyIdentifier(aki) => match &aki.key_identifier {
+            Some(id) => fingerprint(id.0),
+            None => String::new(),
+        },
+        ParsedExtension::CRLDistributionPoints(points) => points
+            .iter()
+            .filter_map(|point| match &point.distribution_point {
+                Some(DistributionPointName::FullName(names)) => Some(general_names(names)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        ParsedExtension::AuthorityInfoAccess(aia) => aia
+            .iter()
+            .map(|desc| {
+                format!(
+                    "{} - {}",
+                    oid_name(&desc.access_method),
+                    general_name(&desc.access_location)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        ParsedExtension::CertificatePolicies(policies) => policies
+            .iter()
+            .map(|policy| oid_name(&policy.policy_id))
+            .collect::<Vec<_>>()
+            .join(", "),
+        ParsedExtension::SCT(scts) => format!("{} signed certificate timestamps", scts.len()),
+        ParsedExtension::ParseError { error } => format!("invalid: {}", error),
+        _ => String::new(),
+    }
+}
+
+/// decode a der encoded subject public key info.
+pub fn public_key_info(spki: &SubjectPublicKeyInfo) -> PublicKeyInfo {
+    let oid = &spki.algorithm.algorithm;
+    let (algorithm, bits, curve) = if *oid == OID_PKCS1_RSAENCRYPTION {
+        // count modulus bits without leading zeros
+        let bits = match spki.parsed() {
+            Ok(PublicKey::RSA(rsa)) => {
+                let modulus = rsa.modulus;
+                let skip = modulus.iter().take_while(|b| **b == 0).count();
+                match modulus.get(skip) {
+                    Some(b) => (modulus.len() - skip) * 8 - b.leading_zeros() as usize,
+                    None => 0,
+                }
+            }
+            _ => 0,
+        };
+        ("RSA".to_string(), bits, None)
+    } else if *oid == OID_KEY_TYPE_EC_PUBLIC_KEY {
+        let curve = spki
+            .algorithm
+            .parameters
+            .as_ref()
+            .and_then(|params| params.as_oid().ok())
+            .map(|oid| oid_name(&oid));
+        let bits = spki.parsed().map(|key| key.key_size()).unwrap_or(0);
+        ("EC".to_string(), bits, curve)
+    } else if *oid == OID_SIG_ED25519 {
+        ("Ed25519".to_string(), 256, None)
+    } else if *oid == OID_SIG_ED448 {
+        ("Ed448".to_string(), 456, None)
+    } else {
+        (oid_name(oid), 0, None)
+    };
+
+    PublicKeyInfo {
+        algorithm,
+        bits,
+        curve,
+        spki_sha256: fingerprint(&Sha256::digest(spki.raw)),
+    }
+}
+
+/// get the process default crypto provider or the aws-lc-rs provider.
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+}
+
+/// decode certificate.
+pub fn inspect_cert(der: &CertificateDer) -> Result<CertInfo, Box<dyn Error>> {
+    let (_, cert) = X509Certificate::from_der(der)?;
+
+    let mut subject_alt_names = Vec::new();
+    let mut key_usage = Vec::new();
+    let mut extended_key_usage_names = Vec::new();
+    let mut is_ca = false;
+    let mut extensions = Vec::new();
+    for ext in cert.extensions() {
+        match ext.parsed_extension() {
+            ParsedExtension::SubjectAlternativeName(san) => {
+                subject_alt_names = san.general_names.iter().map(general_name).collect();
+            }
+            ParsedExtension::KeyUsage(usage) => {
+                key_usage = usage.to_string().split(", ").map(Into::into).collect();
+            }
+            ParsedExtension::ExtendedKeyUsage(eku) => {
+                extended_key_usage_names = extended_key_usage(eku);
+            }
+            ParsedExtension::BasicConstraints(bc) => is_ca = bc.ca,
+            _ => {}
+        }
+        extensions.push(ExtensionInfo {
+            name: oid_name(&ext.oid),
+            critical: ext.critical,
+            value: extension_value(ext.parsed_extension()),
+        });
+    }
+
+    let validity = cert.validity();
+    Ok(CertInfo {
+        version: cert.version().0 + 1,
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.raw_serial_as_string().to_uppercase(),
+        not_before: format_time(validity.not_before),
+        not_after: format_time(validity.not_after),
+        expired: validity.not_after < ASN1Time::now(),
+        signature_algorithm: oid_name(&cert.signature_algorithm.algorithm),
+        public_key: public_key_info(cert.public_key()),
+        is_ca,
+        subject_alt_names,
+        key_usage,
+        extended_key_usage: extended_key_usage_names,
+        extensions,
+        sha1: fingerprint(&Sha1::digest(der)),
+        sha256: fingerprint(&Sha256::digest(der)),
+    })
+}
+
+/// get public key of a private key. it is read from the key, which works for
+/// keys the crypto provider does not support, or derived by the crypto
+/// provider for keys without one.
+fn key_public_key(key: &PrivateKeyDer) -> Result<PublicKeyInfo, Box<dyn Error>> {
+    let spki = match der::public_key(key) {
+        Ok(spki) => spki,
+        Err(err) => {
+            let Ok(signing_key) = crypto_provider()
+                .key_provider
+                .load_private_key(key.clone_key())
+            else {
+                return Err(err);
+            };
+            signing_key.public_key().ok_or(err)?.to_vec()
+        }
+    };
+    let (_, spki) = SubjectPublicKeyInfo::from_der(&spki)?;
+    Ok(public_key_info(&spki))
+}
+
+/// decode private key and check whether it matches one of the certificates.
+/// keys without a known public key are returned with the error.
+pub fn inspect_key(key: &PrivateKeyDer, certs: &[CertInfo]) -> KeyInfo {
+    let format = match key {
+        PrivateKeyDer::Pkcs1(_) => "PKCS#1",
+        PrivateKeyDer::Sec1(_) => "SEC1",
+        PrivateKeyDer::Pkcs8(_) => "PKCS#8",
+        _ => "unknown",
+    };
+
+    let (public_key, error) = match key_public_key(key) {
+        Ok(public_key) => (Some(public_key), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+
+    let (matches, certificate) = match &public_key {
+        Some(public_key) if !certs.is_empty() => {
+            let cert = certs
+                .iter()
+                .find(|cert| cert.public_key.spki_sha256 == public_key.spki_sha256);
+            (Some(cert.is_some()), cert.map(|cert| cert.subject.clone()))
+        }
+        _ => (None, None),
+    };
+
+    KeyInfo {
+        format,
+        public_key,
+        error,
+        matches,
+        certificate,
+    }
+}
+
+impl fmt::Display for PublicKeyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} bit", self.algorithm, self.bits)?;
+        if let Some(curve) = &self.curve {
+            write!(f, " ({})", curve)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "certificate")?;
+        writeln!(f, "  version:             {}", self.version)?;
+        writeln!(f, "  subject:             {}", self.subject)?;
+        writeln!(f, "  issuer:              {}", self.issuer)?;
+        writeln!(f, "  serial:              {}", self.serial)?;
+        writeln!(f, "  not before:          {}", self.not_before)?;
+        write!(f, "  not after:           {}", self.not_after)?;
+        writeln!(f, "{}", if self.expired { " (expired)" } else { "" })?;
+        writeln!(f, "  signature algorithm: {}", self.signature_algorithm)?;
+        writeln!(f, "  public key:          {}", self.public_key)?;
+        writeln!(f, "  spki sha256:         {}", self.public_key.spki_sha256)?;
+        writeln!(f, "  ca:                  {}", self.is_ca)?;
+        if !self.subject_alt_names.is_empty() {
+            writeln!(
+                f,
+                "  alternative names:   {}",
+                self.subject_alt_names.join(", ")
+            )?;
+        }
+        if !self.key_usage.is_empty() {
+            writeln!(f, "  key usage:           {}", self.key_usage.join(", "))?;
+        }
+        if !self.extended_key_usage.is_empty() {
+            writeln!(
+                f,
+                "  extended key usage:  {}",
+                self.extended_key_usage.join(", ")
+            )?;
+        }
+        if !self.extensions.is_empty() {
+            writeln!(f, "  extensions:")?;
+            for ext in &self.extensions {
+                let critical = if ext.critical { " (critical)" } else { "" };
+                writeln!(f, "    {}{}: {}", ext.name, critical, ext.value)?;
+            }
+        }
+        writeln!(f, "  sha1 fingerprint:    {}", self.sha1)?;
+        write!(f, "  sha256 fingerprint:  {}", self.sha256)
+    }
+}
+
+impl fmt::Display for KeyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} private key", self.format)?;
+        if let Some(public_key) = &self.public_key {
+            write!(f, "\n  public key:          {}", public_key)?;
+            write!(f, "\n  spki sha256:         {}", public_key.spki_sha256)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, "\n  error:               {}", error)?;
+        }
+        match (self.matches, &self.certificate) {
+            (Some(true), Some(subject)) => write!(f, "\n  matches certificate: {}", subject),
+            (Some(_), _) => write!(f, "\n  matches certificate: none"),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ItemInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemInfo::Certificate(cert) => cert.fmt(f),
+            ItemInfo::PrivateKey(key) => key.fmt(f),
+            ItemInfo::PublicKey(key) => {
+                writeln!(f, "public key")?;
+                writeln!(f, "  public key:          {}", key)?;
+                write!(f, "  spki sha256:         {}", key.spki_sha256)
+            }
+            ItemInfo::Other { kind, size } => write!(f, "{} ({} bytes)", kind, size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{KeyPair, PublicKeyData, RsaKeySize};
+
+    #[test]
+    fn test_fingerprint() {
+        for (bytes, want) in [
+            (&[][..], ""),
+            (&[0x0a][..], "0A"),
+            (&[0xde, 0xad, 0xbe, 0xef][..], "DE:AD:BE:EF"),
+        ] {
+            assert_eq!(fingerprint(bytes), want);
+        }
+    }
+
+    #[test]
+    fn test_inspect_key() {
+        for (key, algorithm, bits) in [
+            (
+                KeyPair::generate_rsa_for(&rcgen::PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap(),
+                "RSA",
+                2048,
+            ),
+            (
+                KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap(),
+                "EC",
+                256,
+            ),
+            (
+                KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384).unwrap(),
+                "EC",
+                384,
+            ),
+            (
+                KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap(),
+                "Ed25519",
+                256,
+            ),
+        ] {
+            let spki_sha256 = fingerprint(&Sha256::digest(key.subject_public_key_info()));
+            let pkcs8 = key.serialize_der();
+            let mut keys = vec![PrivateKeyDer::Pkcs8(pkcs8.clone().into())];
+            if algorithm == "RSA" {
+                let (_, _, pkcs1) = der::pkcs8_parts(&pkcs8).unwrap();
+                keys.push(PrivateKeyDer::Pkcs1(pkcs1.to_vec().into()));
+            }
+            for der in keys {
+                let info = inspect_key(&der, &[]);
+                let public_key = info
+                    .public_key
+                    .unwrap_or_else(|| panic!("{} {:?}", info.format, info.error));
+                assert_eq!(
+                    (public_key.algorithm.as_str(), public_key.bits),
+                    (algorithm, bits),
+                    "{}",
+                    info.format
+                );
+                assert_eq!(public_key.spki_sha256, spki_sha256, "{}", info.format);
+            }
+        }
+
+        // PKCS#8 Ed448 key without public key, which the crypto provider
+        // does not support
+        let mut der = vec![
+            0x30, 0x47, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x71, 0x04, 0x3b,
+            0x04, 0x39,
+        ];
+        der.resize(der.len() + 57, 0x01);
+        let info = inspect_key(&PrivateKeyDer::Pkcs8(der.into()), &[]);
+        assert!(info.public_key.is_none());
+        assert!(info.error.is_some());
+        assert_eq!(info.matches, None);
+    }
+}
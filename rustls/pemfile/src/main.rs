@@ -1,31 +1,315 @@
+use clap::{Args, Parser, Subcommand};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
 use rustls_pemfile::{read_one, Item};
-use std::env;
-use std::fs::File;
+use serde::Serialize;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io::BufReader;
 use std::iter;
+use std::path::{Path, PathBuf};
+use x509_parser::prelude::{FromDer, SubjectPublicKeyInfo};
 
-fn parse_pem_file(file: String) -> std::io::Result<()> {
+mod convert;
+mod der;
+mod inspect;
+mod verify;
+
+use convert::{DerItem, Format, KeyFormat};
+use inspect::{inspect_cert, inspect_key, public_key_info, CertInfo, ItemInfo};
+
+/// Inspect, verify and convert certificates and keys in PEM files
+#[derive(Parser)]
+#[clap(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    // files are inspected without a command
+    #[clap(flatten)]
+    inspect: InspectArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode certificates and keys
+    Inspect(InspectArgs),
+    /// Verify the certificate chain of a bundle and check it for common mistakes
+    Verify(VerifyArgs),
+    /// Convert between PEM, DER and PKCS#12 and split or merge PEM files
+    Convert(ConvertArgs),
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    /// PEM files to inspect
+    #[clap(name = "FILE", required = true)]
+    files: Vec<PathBuf>,
+    /// Check keys against the certificates in this file instead of the
+    /// certificates in the inspected files
+    #[clap(long)]
+    cert: Option<PathBuf>,
+    /// Print JSON instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// PEM bundle with the leaf certificate, intermediates and optionally the
+    /// private key
+    #[clap(name = "BUNDLE")]
+    bundle: PathBuf,
+    /// Trust anchors in PEM format instead of the native certificate store
+    #[clap(long)]
+    ca: Option<PathBuf>,
+    /// Private key in PEM format to check against the leaf certificate
+    #[clap(long)]
+    key: Option<PathBuf>,
+    /// Hostname the leaf certificate must be valid for
+    #[clap(long)]
+    host: Option<String>,
+    /// Verification time as RFC 3339 timestamp instead of now
+    #[clap(long, value_parser = verify::parse_time)]
+    time: Option<UnixTime>,
+    /// Print JSON instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// PEM, DER or PKCS#12 files to convert, the items of all files are merged
+    #[clap(name = "FILE", required = true)]
+    files: Vec<PathBuf>,
+    /// Output file, or prefix of the output files with --split
+    #[clap(short, long)]
+    out: PathBuf,
+    /// Output format
+    #[clap(short, long, value_enum, default_value_t = Format::Pem)]
+    to: Format,
+    /// Convert private keys to this format
+    #[clap(short, long, value_enum)]
+    key_format: Option<KeyFormat>,
+    /// Write every item to its own file named <OUT>-<N>.pem or <OUT>-<N>.der
+    #[clap(long)]
+    split: bool,
+    /// Password of PKCS#12 input and output files
+    #[clap(long, default_value = "")]
+    password: String,
+}
+
+/// decoded items of a pem file.
+#[derive(Serialize)]
+struct FileInfo {
+    file: PathBuf,
+    items: Vec<ItemInfo>,
+}
+
+fn parse_pem_file(file: &Path) -> Result<Vec<Item>, Box<dyn Error>> {
     // open file
-    let f = File::open(file)?;
+    let f = File::open(file).map_err(|err| format!("{}: {}", file.display(), err))?;
     let mut reader = BufReader::new(f);
 
     // parse file
+    let mut items = Vec::new();
     for item in iter::from_fn(|| read_one(&mut reader).transpose()) {
-        match item.unwrap() {
-            Item::X509Certificate(cert) => println!("certificate {cert:?}"),
-            Item::Pkcs1Key(key) => println!("rsa pkcs1 key {key:?}"),
-            Item::Pkcs8Key(key) => println!("pkcs8 key {key:?}"),
-            Item::Sec1Key(key) => println!("sec1 ec key {key:?}"),
-            _ => println!("unhandled item"),
+        items.push(item.map_err(|err| format!("{}: {}", file.display(), err))?);
+    }
+    Ok(items)
+}
+
+/// decode all certificates of the items.
+fn certificates(items: &[Item]) -> Result<Vec<CertInfo>, Box<dyn Error>> {
+    let mut certs = Vec::new();
+    for item in items {
+        if let Item::X509Certificate(cert) = item {
+            certs.push(inspect_cert(cert)?);
+        }
+    }
+    Ok(certs)
+}
+
+fn inspect_item(item: Item, certs: &[CertInfo]) -> Result<ItemInfo, Box<dyn Error>> {
+    Ok(match item {
+        Item::X509Certificate(cert) => ItemInfo::Certificate(Box::new(inspect_cert(&cert)?)),
+        Item::Pkcs1Key(key) => ItemInfo::PrivateKey(inspect_key(&PrivateKeyDer::Pkcs1(key), certs)),
+        Item::Pkcs8Key(key) => ItemInfo::PrivateKey(inspect_key(&PrivateKeyDer::Pkcs8(key), certs)),
+        Item::Sec1Key(key) => ItemInfo::PrivateKey(inspect_key(&PrivateKeyDer::Sec1(key), certs)),
+        Item::SubjectPublicKeyInfo(spki) => {
+            ItemInfo::PublicKey(public_key_info(&SubjectPublicKeyInfo::from_der(&spki)?.1))
+        }
+        Item::Crl(crl) => ItemInfo::Other {
+            kind: "certificate revocation list",
+            size: crl.len(),
+        },
+        Item::Csr(csr) => ItemInfo::Other {
+            kind: "certificate signing request",
+            size: csr.len(),
+        },
+        _ => ItemInfo::Other {
+            kind: "unhandled item",
+            size: 0,
+        },
+    })
+}
+
+/// split items into certificates and private keys.
+fn certs_and_keys(items: Vec<Item>) -> (Vec<CertificateDer<'static>>, Vec<PrivateKeyDer<'static>>) {
+    let mut certs = Vec::new();
+    let mut keys = Vec::new();
+    for item in items {
+        match item {
+            Item::X509Certificate(cert) => certs.push(cert),
+            Item::Pkcs1Key(key) => keys.push(key.into()),
+            Item::Pkcs8Key(key) => keys.push(key.into()),
+            Item::Sec1Key(key) => keys.push(key.into()),
+            _ => {}
+        }
+    }
+    (certs, keys)
+}
+
+fn inspect(args: InspectArgs) -> Result<(), Box<dyn Error>> {
+    // read all files first, keys are matched against certificates of all files
+    let mut files = Vec::new();
+    for file in args.files {
+        let items = parse_pem_file(&file)?;
+        files.push((file, items));
+    }
+    let certs = match &args.cert {
+        Some(file) => certificates(&parse_pem_file(file)?)?,
+        None => {
+            let mut certs = Vec::new();
+            for (_, items) in &files {
+                certs.extend(certificates(items)?);
+            }
+            certs
+        }
+    };
+
+    let mut infos = Vec::new();
+    for (file, items) in files {
+        let mut info = FileInfo {
+            file,
+            items: Vec::new(),
+        };
+        for item in items {
+            info.items.push(inspect_item(item, &certs)?);
+        }
+        infos.push(info);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&infos)?);
+        return Ok(());
+    }
+    for info in infos {
+        println!("Parsing file {}", info.file.display());
+        for item in info.items {
+            println!("{item}");
+        }
+    }
+    Ok(())
+}
+
+fn verify(args: VerifyArgs) -> Result<(), Box<dyn Error>> {
+    let (certs, mut keys) = certs_and_keys(parse_pem_file(&args.bundle)?);
+    if let Some(file) = &args.key {
+        keys.extend(certs_and_keys(parse_pem_file(file)?).1);
+    }
+
+    let anchors = match &args.ca {
+        Some(file) => certs_and_keys(parse_pem_file(file)?).0,
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for err in native.errors {
+                eprintln!("native certificate store: {err}");
+            }
+            native.certs
         }
+    };
+
+    let time = args.time.unwrap_or_else(UnixTime::now);
+    let report = verify::verify(&certs, &keys, &anchors, args.host.as_deref(), time)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{report}");
+    }
+    if !report.valid {
+        return Err("verification failed".into());
     }
     Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    for file in env::args().skip(1) {
-        println!("Parsing file {file}");
-        parse_pem_file(file)?;
+fn convert(args: ConvertArgs) -> Result<(), Box<dyn Error>> {
+    let mut items = Vec::new();
+    for file in &args.files {
+        let data = fs::read(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+        let file_items = convert::read(&data, &args.password)
+            .map_err(|err| format!("{}: {}", file.display(), err))?;
+        items.extend(file_items);
+    }
+    if let Some(format) = args.key_format {
+        items = items
+            .iter()
+            .map(|item| convert::convert_key(item, format))
+            .collect::<Result<_, _>>()?;
+    }
+
+    // one output per item when splitting, otherwise all items in one output
+    let outputs: Vec<(PathBuf, &[DerItem])> = if args.split {
+        let extension = match args.to {
+            Format::Pem => "pem",
+            Format::Der => "der",
+            Format::P12 => return Err("PKCS#12 archives cannot be split".into()),
+        };
+        items
+            .chunks(1)
+            .enumerate()
+            .map(|(n, item)| {
+                let mut path = OsString::from(&args.out);
+                path.push(format!("-{}.{}", n + 1, extension));
+                (path.into(), item)
+            })
+            .collect()
+    } else {
+        vec![(args.out.clone(), &items[..])]
+    };
+
+    for (path, items) in outputs {
+        let data = match args.to {
+            Format::Pem => convert::write_pem(items).into_bytes(),
+            Format::Der => match items {
+                [item] => item.der.clone(),
+                _ => return Err("DER files hold a single item, use --split".into()),
+            },
+            Format::P12 => {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                convert::write_pkcs12(items, &name, &args.password)?
+            }
+        };
+        let private = items.iter().any(|item| item.kind.is_private_key());
+        convert::write_file(&path, &data, private)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        println!("Wrote {}", path.display());
     }
     Ok(())
 }
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    let Some(command) = cli.command else {
+        return inspect(cli.inspect);
+    };
+    match command {
+        Command::Inspect(args) => inspect(args),
+        Command::Verify(args) => verify(args),
+        Command::Convert(args) => convert(args),
+    }
+}
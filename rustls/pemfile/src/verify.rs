@@ -0,0 +1,474 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, TrustAnchor, UnixTime};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use webpki::{anchor_from_trusted_cert, EndEntityCert, KeyUsage};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+use x509_parser::time::ASN1Time;
+
+use crate::inspect::{crypto_provider, format_time, inspect_key, public_key_info};
+
+/// severity of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// problem found in a bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// certificate of the bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainEntry {
+    pub position: usize,
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+}
+
+/// result of verifying a bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// certificates in bundle order.
+    pub certificates: Vec<ChainEntry>,
+    /// positions of the certificates from the leaf up to the trust anchor.
+    pub path: Vec<usize>,
+    /// subject of the trust anchor the chain ends at.
+    pub anchor: Option<String>,
+    pub findings: Vec<Finding>,
+    pub valid: bool,
+}
+
+impl Report {
+    fn error(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// parse verification time given as rfc 3339 timestamp.
+pub fn parse_time(s: &str) -> Result<UnixTime, String> {
+    let time = OffsetDateTime::parse(s, &Rfc3339).map_err(|err| err.to_string())?;
+    let secs = u64::try_from(time.unix_timestamp()).map_err(|_| "time before 1970")?;
+    Ok(UnixTime::since_unix_epoch(Duration::from_secs(secs)))
+}
+
+fn issued_by(cert: &X509Certificate, issuer: &X509Certificate) -> bool {
+    cert.issuer().as_raw() == issuer.subject().as_raw()
+}
+
+/// verify certificate chain of a bundle for host at time and lint the
+/// bundle. certificates and keys are numbered from 1 in messages.
+pub fn verify(
+    certs: &[CertificateDer],
+    keys: &[PrivateKeyDer],
+    anchors: &[CertificateDer],
+    host: Option<&str>,
+    time: UnixTime,
+) -> Result<Report, Box<dyn Error>> {
+    let mut parsed = Vec::new();
+    for (i, cert) in certs.iter().enumerate() {
+        let (_, cert) = X509Certificate::from_der(cert)
+            .map_err(|err| format!("certificate #{}: {}", i + 1, err))?;
+        parsed.push(cert);
+    }
+    // native stores may contain certificates we cannot parse, ignore them
+    let anchor_certs = anchors
+        .iter()
+        .filter_map(|cert| X509Certificate::from_der(cert).ok().map(|(_, cert)| cert))
+        .collect::<Vec<_>>();
+    let now = ASN1Time::from_timestamp(time.as_secs() as i64)?;
+
+    let mut report = Report {
+        certificates: parsed
+            .iter()
+            .enumerate()
+            .map(|(i, cert)| ChainEntry {
+                position: i + 1,
+                subject: cert.subject().to_string(),
+                issuer: cert.issuer().to_string(),
+                not_after: format_time(cert.validity().not_after),
+            })
+            .collect(),
+        path: Vec::new(),
+        anchor: None,
+        findings: Vec::new(),
+        valid: false,
+    };
+    if certs.is_empty() {
+        report.error("bundle contains no certificates".into());
+        return Ok(report);
+    }
+    let name = |i: usize| format!("#{} ({})", i + 1, parsed[i].subject());
+
+    // duplicates are reported once and ignored afterwards
+    let mut unique: Vec<usize> = Vec::new();
+    for i in 0..certs.len() {
+        match unique.iter().find(|&&j| certs[j] == certs[i]) {
+            Some(&j) => report.warning(format!(
+                "certificate {} is a duplicate of #{}",
+                name(i),
+                j + 1
+            )),
+            None => unique.push(i),
+        }
+    }
+
+    for &i in &unique {
+        let validity = parsed[i].validity();
+        if validity.not_after < now {
+            report.error(format!(
+                "certificate {} expired at {}",
+                name(i),
+                format_time(validity.not_after)
+            ));
+        } else if validity.not_before > now {
+            report.error(format!(
+                "certificate {} is not valid before {}",
+                name(i),
+                format_time(validity.not_before)
+            ));
+        }
+    }
+
+    // the leaf did not issue any other certificate of the bundle
+    let leaves = unique
+        .iter()
+        .copied()
+        .filter(|&i| {
+            !unique
+                .iter()
+                .any(|&j| j != i && issued_by(&parsed[j], &parsed[i]))
+        })
+        .collect::<Vec<_>>();
+    let leaf = match leaves.first() {
+        Some(_) if leaves.contains(&0) => 0,
+        Some(&leaf) => leaf,
+        None => 0,
+    };
+    if leaf != 0 {
+        report.error(format!(
+            "leaf certificate {} must be the first certificate",
+            name(leaf)
+        ));
+    }
+
+    // follow issuers from the leaf through the bundle to a trust anchor
+    let mut path = vec![leaf];
+    loop {
+        let current = path[path.len() - 1];
+        let cert = &parsed[current];
+        if issued_by(cert, cert) {
+            if current != leaf {
+                report.warning(format!(
+                    "bundle contains self-signed root certificate {}, it does not need to be sent",
+                    name(current)
+                ));
+            }
+            break;
+        }
+        let next = unique
+            .iter()
+            .copied()
+            .find(|&j| !path.contains(&j) && issued_by(cert, &parsed[j]));
+        match next {
+            Some(next) => {
+                if next < current {
+                    report.error(format!(
+                        "certificate {} issued {} and must come after it",
+                        name(next),
+                        name(current)
+                    ));
+                }
+                path.push(next);
+            }
+            None => {
+                match anchor_certs.iter().find(|anchor| issued_by(cert, anchor)) {
+                    Some(anchor) => report.anchor = Some(anchor.subject().to_string()),
+                    None => report.error(format!(
+                        "missing intermediate: issuer {} of certificate {} is neither in the bundle nor a trust anchor",
+                        cert.issuer(),
+                        name(current)
+                    )),
+                }
+                break;
+            }
+        }
+    }
+    for &i in &unique {
+        if !path.contains(&i) {
+            report.warning(format!(
+                "certificate {} is not part of the chain of the leaf certificate",
+                name(i)
+            ));
+        }
+    }
+    report.path = path.iter().map(|i| i + 1).collect();
+
+    // keys must belong to the leaf certificate
+    let leaf_spki = public_key_info(parsed[leaf].public_key()).spki_sha256;
+    for (k, key) in keys.iter().enumerate() {
+        let info = inspect_key(key, &[]);
+        match info.public_key {
+            Some(public_key) if public_key.spki_sha256 == leaf_spki => {}
+            Some(_) => report.error(format!(
+                "private key #{} does not match the leaf certificate {}",
+                k + 1,
+                name(leaf)
+            )),
+            None => report.error(format!(
+                "private key #{}: {}",
+                k + 1,
+                info.error.unwrap_or_default()
+            )),
+        }
+    }
+
+    // let webpki build and validate the chain
+    let trust_anchors = anchors
+        .iter()
+        .filter_map(|cert| anchor_from_trusted_cert(cert).ok())
+        .collect::<Vec<TrustAnchor>>();
+    let intermediates = unique
+        .iter()
+        .filter(|&&i| i != leaf)
+        .map(|&i| certs[i].clone())
+        .collect::<Vec<_>>();
+    match EndEntityCert::try_from(&certs[leaf]) {
+        Ok(ee) => {
+            let provider = crypto_provider();
+            if let Err(err) = ee.verify_for_usage(
+                provider.signature_verification_algorithms.all,
+                &trust_anchors,
+                &intermediates,
+                time,
+                KeyUsage::server_auth(),
+                None,
+                None,
+            ) {
+                report.error(format!("chain verification failed: {:?}", err));
+            }
+            if let Some(host) = host {
+                let server_name = ServerName::try_from(host)?;
+                if let Err(err) = ee.verify_is_valid_for_subject_name(&server_name) {
+                    report.error(format!(
+                        "leaf certificate is not valid for {}: {:?}",
+                        host, err
+                    ));
+                }
+            }
+        }
+        Err(err) => report.error(format!("invalid leaf certificate: {:?}", err)),
+    }
+
+    report.valid = !report
+        .findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error);
+    Ok(report)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "bundle:")?;
+        for cert in &self.certificates {
+            writeln!(
+                f,
+                "  #{} {}\n     issuer {}, expires {}",
+                cert.position, cert.subject, cert.issuer, cert.not_after
+            )?;
+        }
+        let path = self
+            .path
+            .iter()
+            .map(|i| format!("#{}", i))
+            .collect::<Vec<_>>();
+        writeln!(f, "chain: {}", path.join(" -> "))?;
+        if let Some(anchor) = &self.anchor {
+            writeln!(f, "trust anchor: {}", anchor)?;
+        }
+        for finding in &self.findings {
+            let severity = match finding.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            writeln!(f, "{}: {}", severity, finding.message)?;
+        }
+        write!(f, "{}", if self.valid { "OK" } else { "FAILED" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair, PKCS_ECDSA_P256_SHA256,
+    };
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+
+    struct Pki {
+        root: CertificateDer<'static>,
+        inter: CertificateDer<'static>,
+        leaf: CertificateDer<'static>,
+        expired: CertificateDer<'static>,
+        leaf_key: PrivateKeyDer<'static>,
+        inter_key: PrivateKeyDer<'static>,
+    }
+
+    fn ca_params(cn: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, cn);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+    }
+
+    fn pki() -> Pki {
+        let key = || KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let private_key = |key: &KeyPair| PrivatePkcs8KeyDer::from(key.serialize_der()).into();
+
+        let root_key = key();
+        let root_params = ca_params("root");
+        let root = root_params.self_signed(&root_key).unwrap();
+        let root_issuer = Issuer::new(root_params, root_key);
+
+        let inter_key = key();
+        let inter_params = ca_params("inter");
+        let inter = inter_params.signed_by(&inter_key, &root_issuer).unwrap();
+        let inter_issuer = Issuer::new(inter_params, &inter_key);
+
+        let leaf_key = key();
+        let mut leaf_params = CertificateParams::new(vec!["www.example.com".into()]).unwrap();
+        leaf_params
+            .distinguished_name
+            .push(DnType::CommonName, "www.example.com");
+        let leaf = leaf_params.signed_by(&leaf_key, &inter_issuer).unwrap();
+        leaf_params.not_after = rcgen::date_time_ymd(2000, 1, 1);
+        let expired = leaf_params.signed_by(&leaf_key, &inter_issuer).unwrap();
+
+        Pki {
+            root: root.der().clone(),
+            inter: inter.der().clone(),
+            leaf: leaf.der().clone(),
+            expired: expired.der().clone(),
+            leaf_key: private_key(&leaf_key),
+            inter_key: private_key(&inter_key),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let pki = pki();
+        let anchors = [pki.root.clone()];
+        let host = Some("www.example.com");
+        let leaf_key = [pki.leaf_key.clone_key()];
+        let inter_key = [pki.inter_key.clone_key()];
+
+        for (certs, keys, host, valid, want) in [
+            (vec![&pki.leaf, &pki.inter], &leaf_key[..], host, true, None),
+            (
+                vec![&pki.inter, &pki.leaf],
+                &[],
+                host,
+                false,
+                Some("must be the first"),
+            ),
+            (
+                vec![&pki.leaf],
+                &[],
+                host,
+                false,
+                Some("missing intermediate"),
+            ),
+            (
+                vec![&pki.leaf, &pki.inter, &pki.inter],
+                &[],
+                host,
+                true,
+                Some("duplicate of #2"),
+            ),
+            (
+                vec![&pki.leaf, &pki.inter, &pki.root],
+                &[],
+                host,
+                true,
+                Some("self-signed root"),
+            ),
+            (
+                vec![&pki.leaf, &pki.root, &pki.inter],
+                &[],
+                host,
+                false,
+                Some("must come after it"),
+            ),
+            (
+                vec![&pki.expired, &pki.inter],
+                &[],
+                host,
+                false,
+                Some("expired"),
+            ),
+            (
+                vec![&pki.leaf, &pki.inter],
+                &[],
+                Some("other.example.com"),
+                false,
+                Some("not valid for other.example.com"),
+            ),
+            (
+                vec![&pki.leaf, &pki.inter],
+                &inter_key[..],
+                host,
+                false,
+                Some("does not match"),
+            ),
+            (vec![], &[], host, false, Some("no certificates")),
+        ] {
+            let certs = certs.into_iter().cloned().collect::<Vec<_>>();
+            let report = verify(&certs, keys, &anchors, host, UnixTime::now()).unwrap();
+            assert_eq!(report.valid, valid, "{}", report);
+            match want {
+                Some(want) => assert!(
+                    report
+                        .findings
+                        .iter()
+                        .any(|finding| finding.message.contains(want)),
+                    "{}",
+                    report
+                ),
+                None => assert!(report.findings.is_empty(), "{}", report),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        for (s, want) in [
+            ("1970-01-01T00:00:00Z", Some(0)),
+            ("2025-01-01T00:00:00Z", Some(1735689600)),
+            ("2025-01-01T01:00:00+01:00", Some(1735689600)),
+            ("1969-12-31T23:59:59Z", None),
+            ("2025-01-01", None),
+        ] {
+            assert_eq!(parse_time(s).ok().map(|time| time.as_secs()), want, "{}", s);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt::{self, Write};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+
+/// certificate of the peer chain.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Cert {
+    pub subject: String,
+    pub issuer: String,
+    /// sha-256 digest of the certificate in hex
+    pub sha256: String,
+}
+
+/// format bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for byte in bytes {
+        write!(&mut s, "{:02x}", byte).unwrap();
+    }
+    s
+}
+
+impl Cert {
+    /// parse der encoded certificate. names are formatted like
+    /// "CN=example.com, O=Example" regardless of the tls stack.
+    pub fn from_der(der: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let (_, cert) = X509Certificate::from_der(der)?;
+        Ok(Cert {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sha256: hex(&Sha256::digest(der)),
+        })
+    }
+}
+
+impl fmt::Display for Cert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", self.subject, &self.sha256[..16])
+    }
+}
+
+/// outcome of a connection of a client. names are normalized, so the
+/// summaries of the openssl and rustls clients can be compared.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Summary {
+    /// negotiated protocol version like "TLSv1.3"
+    pub version: Option<String>,
+    /// IANA name of the negotiated cipher suite
+    pub cipher: Option<String>,
+    /// certificate chain sent by the server starting with the peer
+    /// certificate
+    pub chain: Vec<Cert>,
+    /// sha-256 digest of the peer certificate in hex
+    pub digest: Option<String>,
+    /// http status of the response
+    pub status: Option<u16>,
+    /// error which ended the connection
+    pub error: Option<String>,
+}
+
+/// get status of a http/1 response from its status line.
+pub fn status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|b| *b == b'\n').next()?;
+    String::from_utf8_lossy(line)
+        .trim_end()
+        .split(' ')
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |value: Option<&str>| value.unwrap_or("-").to_string();
+        writeln!(f, "Version: {}", show(self.version.as_deref()))?;
+        writeln!(f, "Cipher:  {}", show(self.cipher.as_deref()))?;
+        for (i, cert) in self.chain.iter().enumerate() {
+            writeln!(f, "Chain {}: {} (issuer {})", i, cert, cert.issuer)?;
+        }
+        writeln!(f, "Digest:  {}", show(self.digest.as_deref()))?;
+        let status = self.status.map(|status| status.to_string());
+        writeln!(f, "Status:  {}", show(status.as_deref()))?;
+        if let Some(error) = &self.error {
+            writeln!(f, "Error:   {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        for (response, want) in [
+            (
+                &b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"[..],
+                Some(200),
+            ),
+            (b"HTTP/1.0 204 No Content\r\n\r\n", Some(204)),
+            (b"HTTP/1.1 404\r\n", Some(404)),
+            (b"HTTP/1.1 OK\r\n", None),
+            (b"", None),
+        ] {
+            assert_eq!(status(response), want, "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = Cert::from_der(cert.cert.der()).unwrap();
+        assert_eq!(cert.subject, "CN=rcgen self signed cert");
+        assert_eq!(cert.issuer, cert.subject);
+        assert_eq!(cert.sha256.len(), 64);
+
+        let summary = Summary {
+            version: Some("TLSv1.3".into()),
+            cipher: Some("TLS_AES_128_GCM_SHA256".into()),
+            digest: Some(cert.sha256.clone()),
+            chain: vec![cert.clone()],
+            status: None,
+            error: Some("connection reset".into()),
+        };
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "Version: TLSv1.3\n\
+                 Cipher:  TLS_AES_128_GCM_SHA256\n\
+                 Chain 0: CN=rcgen self signed cert [{}] (issuer CN=rcgen self signed cert)\n\
+                 Digest:  {}\n\
+                 Status:  -\n\
+                 Error:   connection reset\n",
+                &cert.sha256[..16],
+                cert.sha256
+            )
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::Resumption;
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, SignatureScheme};
+
+const ADDR: &str = "127.0.0.1:3000";
+const RECONNECTS: u32 = 200;
+
+/// certificate verifier that accepts the server's self-signed certificate.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// start the https server and wait until it accepts connections.
+fn start_server() -> Result<Child, Box<dyn Error>> {
+    let server = Command::new(env!("CARGO_BIN_EXE_https"))
+        .stdout(Stdio::null())
+        .spawn()?;
+    for _ in 0..50 {
+        if TcpStream::connect(ADDR).is_ok() {
+            return Ok(server);
+        }
+        sleep(Duration::from_millis(100));
+    }
+    Err("server did not start".into())
+}
+
+/// connect to the server, run a GET request and read the whole response.
+fn get(config: &Arc<ClientConfig>) -> Result<bool, Box<dyn Error>> {
+    let mut conn = rustls::ClientConnection::new(config.clone(), "localhost".try_into()?)?;
+    let mut sock = TcpStream::connect(ADDR)?;
+    let mut tls = rustls::Stream::new(&mut conn, &mut sock);
+    tls.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut response = Vec::new();
+    tls.read_to_end(&mut response)?;
+    Ok(tls.conn.handshake_kind() == Some(rustls::HandshakeKind::Resumed))
+}
+
+/// measure average reconnect latency with the client config.
+fn bench(name: &str, config: ClientConfig) -> Result<(), Box<dyn Error>> {
+    let config = Arc::new(config);
+
+    // first connection is never resumed
+    get(&config)?;
+
+    let mut resumed = 0;
+    let start = Instant::now();
+    for _ in 0..RECONNECTS {
+        if get(&config)? {
+            resumed += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{}: {} reconnects, {} resumed, {:.3} ms per request",
+        name,
+        RECONNECTS,
+        resumed,
+        elapsed.as_secs_f64() * 1000.0 / RECONNECTS as f64
+    );
+    Ok(())
+}
+
+fn client_config(resumption: bool) -> ClientConfig {
+    let provider = Arc::new(crypto::aws_lc_rs::default_provider());
+    let mut config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+        .with_no_client_auth();
+    if !resumption {
+        config.resumption = Resumption::disabled();
+    }
+    config
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut server = start_server()?;
+    let result = bench("without resumption", client_config(false))
+        .and_then(|_| bench("with resumption", client_config(true)));
+    server.kill()?;
+    server.wait()?;
+    result
+}
@@ -0,0 +1,196 @@
+use socket2::{Domain, Socket, Type};
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// first file descriptor passed by systemd socket activation.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// backlog of bound listen sockets.
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// address to listen on, either a tcp socket address or a unix socket path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("empty unix socket path".into());
+            }
+            return Ok(ListenAddr::Unix(path.into()));
+        }
+        match s.parse() {
+            Ok(addr) => Ok(ListenAddr::Tcp(addr)),
+            Err(err) => Err(format!("invalid listen address {}: {}", s, err)),
+        }
+    }
+}
+
+/// non-blocking listener for incoming connections. listeners are std
+/// listeners, so they can be taken before the runtime is started.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{} port {} (https://{}/)", addr.ip(), addr.port(), addr),
+                Err(_) => write!(f, "unknown tcp socket"),
+            },
+            Listener::Unix(listener) => match listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => write!(f, "unix socket {}", path.display()),
+                    None => write!(f, "unnamed unix socket"),
+                },
+                Err(_) => write!(f, "unknown unix socket"),
+            },
+        }
+    }
+}
+
+/// bind tcp listener. ipv6 sockets only accept ipv6 connections, so the same
+/// port can also be used for ipv4.
+fn bind_tcp(addr: SocketAddr) -> io::Result<Listener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    Ok(Listener::Tcp(socket.into()))
+}
+
+/// bind unix listener, stale sockets from previous runs are removed.
+fn bind_unix(path: &PathBuf) -> io::Result<Listener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path)
+        && metadata.file_type().is_socket()
+    {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    Ok(Listener::Unix(listener))
+}
+
+/// bind listener to address.
+pub fn bind(addr: &ListenAddr) -> io::Result<Listener> {
+    match addr {
+        ListenAddr::Tcp(addr) => bind_tcp(*addr),
+        ListenAddr::Unix(path) => bind_unix(path),
+    }
+}
+
+/// remove the variables of socket activation, so child processes do not
+/// inherit them.
+fn unset_environment() {
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        // SAFETY: the listeners are taken in main before the runtime and its
+        // threads are started
+        unsafe { env::remove_var(name) };
+    }
+}
+
+/// get listeners passed by systemd socket activation and unset its
+/// variables, see sd_listen_fds(3).
+pub fn from_systemd() -> io::Result<Vec<Listener>> {
+    let pid = match env::var("LISTEN_PID") {
+        Ok(pid) => pid,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let fds = env::var("LISTEN_FDS");
+    unset_environment();
+
+    // check if listen fds are meant for this process
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+
+    let fds = match fds.map(|fds| fds.parse::<RawFd>()) {
+        Ok(Ok(fds)) => fds,
+        _ => return Err(io::Error::other("invalid LISTEN_FDS")),
+    };
+
+    let mut listeners = Vec::new();
+    for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + fds {
+        // systemd passes ownership of the file descriptors to this process
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+        if listener.local_addr().is_ok() {
+            listener.set_nonblocking(true)?;
+            listeners.push(Listener::Tcp(listener));
+            continue;
+        }
+
+        // not an ip socket, try unix socket
+        let listener = unsafe { UnixListener::from_raw_fd(listener.into_raw_fd()) };
+        listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        listeners.push(Listener::Unix(listener));
+    }
+    Ok(listeners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_systemd() {
+        for (pid, fds) in [("1", "1"), (&std::process::id().to_string(), "0")] {
+            // SAFETY: no other test uses these variables
+            unsafe {
+                env::set_var("LISTEN_PID", pid);
+                env::set_var("LISTEN_FDS", fds);
+                env::set_var("LISTEN_FDNAMES", "https");
+            }
+            assert!(from_systemd().unwrap().is_empty());
+            for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+                assert!(env::var(name).is_err(), "{} is set", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_listen_addr_from_str() {
+        for (addr, want) in [
+            (
+                "127.0.0.1:3000",
+                Ok(ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 3000)))),
+            ),
+            (
+                "[::1]:3000",
+                Ok(ListenAddr::Tcp(SocketAddr::from((
+                    [0, 0, 0, 0, 0, 0, 0, 1],
+                    3000,
+                )))),
+            ),
+            (
+                "unix:/run/https.sock",
+                Ok(ListenAddr::Unix("/run/https.sock".into())),
+            ),
+            ("unix:", Err(())),
+            ("localhost:3000", Err(())),
+            ("127.0.0.1", Err(())),
+        ] {
+            assert_eq!(addr.parse::<ListenAddr>().map_err(|_| ()), want);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use clap::Parser;
 use futures_util::TryStreamExt;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Empty, Full, StreamBody};
@@ -9,17 +10,25 @@ use hyper_util::server;
 use rcgen::{generate_simple_self_signed, CertifiedKey};
 use std::convert::Infallible;
 use std::env;
-use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tls_listener::TlsListener;
+use tls_listener::{AsyncAccept, TlsListener};
 use tokio::fs::File;
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
 use tokio_rustls::rustls::ServerConfig;
 use tokio_rustls::TlsAcceptor;
 use tokio_util::io::ReaderStream;
 
+mod listen;
+mod session;
+mod telemetry;
+
+use listen::{ListenAddr, Listener};
+use session::EarlyDataStream;
+use telemetry::{HandshakeInfo, Stats, TimedAcceptor};
+
 fn bad_request() -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible> {
     Ok(Response::builder()
         .status(StatusCode::BAD_REQUEST)
@@ -27,6 +36,14 @@ fn bad_request() -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible>
         .unwrap())
 }
 
+/// reject request received as early data, see RFC 8470.
+fn too_early() -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible> {
+    Ok(Response::builder()
+        .status(StatusCode::TOO_EARLY)
+        .body(Empty::new().map_err(|e| match e {}).boxed())
+        .unwrap())
+}
+
 /// remove extra slashes from request path.
 fn remove_extra_slashes(path: &str) -> String {
     let mut out = String::new();
@@ -130,13 +147,33 @@ async fn handle_get_file(
     }
 }
 
+/// show handshake parameters of the caller's own connection and counters.
+fn handle_get_tls(
+    tls: &HandshakeInfo,
+    stats: &Stats,
+) -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible> {
+    let html = format!(
+        "<!DOCTYPE html><html><head><title>TLS</title></head><body>\
+        <h1>Your connection</h1><table>{}</table>\
+        <h1>Server counters</h1><table>{}</table></body></html>",
+        tls.html_rows(),
+        stats.html_rows(),
+    );
+    let body = Full::from(html);
+    Ok(Response::new(body.map_err(|e| match e {}).boxed()))
+}
+
 async fn handle_get(
-    remote_addr: SocketAddr,
+    remote_addr: Arc<str>,
+    tls: Arc<HandshakeInfo>,
+    stats: Arc<Stats>,
     req: Request<Incoming>,
 ) -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible> {
     println!("{} {} {}", remote_addr, req.method(), req.uri().path());
 
-    if is_local_dir(&req).await {
+    if get_req_path(&req) == "/_tls" {
+        handle_get_tls(&tls, &stats)
+    } else if is_local_dir(&req).await {
         handle_get_dir(req).await
     } else {
         handle_get_file(req).await
@@ -144,58 +181,107 @@ async fn handle_get(
 }
 
 async fn handle(
-    remote_addr: SocketAddr,
+    remote_addr: Arc<str>,
+    tls: Arc<HandshakeInfo>,
+    stats: Arc<Stats>,
+    past_early_data: Arc<AtomicBool>,
     req: Request<Incoming>,
 ) -> Result<Response<BoxBody<Bytes, std::io::Error>>, Infallible> {
+    // early data can be replayed, only allow idempotent GET requests which
+    // were received completely as early data. later requests need the
+    // finished handshake, which a replay cannot complete
+    if !past_early_data.load(Ordering::Acquire) && req.method() != Method::GET {
+        return too_early();
+    }
+
     match req.method() {
-        &Method::GET => handle_get(remote_addr, req).await,
+        &Method::GET => handle_get(remote_addr, tls, stats, req).await,
         _ => bad_request(),
     }
 }
 
-fn tls_acceptor() -> TlsAcceptor {
+fn tls_acceptor(early_data: bool) -> TlsAcceptor {
     // generate certificate and private key
     let CertifiedKey { cert, signing_key } = generate_simple_self_signed(Vec::new()).unwrap();
     let key = PrivatePkcs8KeyDer::from(signing_key.serialize_der());
     let cert = cert.der().clone();
 
-    Arc::new(
-        ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(vec![cert], key.into())
-            .unwrap(),
-    )
-    .into()
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key.into())
+        .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    session::configure(&mut config, early_data).unwrap();
+
+    Arc::new(config).into()
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // create listener
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    let mut listener = TlsListener::new(tls_acceptor(), TcpListener::bind(addr).await?);
-
-    println!(
-        "Serving HTTP on {} port {} (https://{}/)...",
-        addr.ip(),
-        addr.port(),
-        addr
-    );
+/// Serve files in the current directory over HTTPS
+#[derive(Parser)]
+#[clap(version)]
+struct Args {
+    /// Address to listen on, e.g., 127.0.0.1:3000, [::1]:3000 or
+    /// unix:/run/https.sock. Can be used multiple times. Defaults to
+    /// 127.0.0.1:3000 if no sockets are passed by systemd
+    #[clap(short, long = "listen", name = "ADDR")]
+    listen: Vec<ListenAddr>,
+    /// Enable TLS 1.3 early data (0-RTT) for GET requests. Sessions are then
+    /// resumed from the session cache only, without rotating session
+    /// tickets, as early data requires single-use sessions
+    #[clap(long)]
+    early_data: bool,
+}
 
-    // main loop
+/// accept and serve connections from tls listener.
+async fn serve<A>(mut listener: TlsListener<A, TimedAcceptor>, stats: Arc<Stats>)
+where
+    A: AsyncAccept + Unpin,
+    A::Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     loop {
         // get connection from listener
-        let (stream, remote_addr) = match listener.accept().await {
+        let ((mut stream, duration), remote_addr) = match listener.accept().await {
             Ok((stream, remote_addr)) => (stream, remote_addr),
             Err(err) => {
-                eprintln!("Error: {:?}", err);
+                let reason = match &err {
+                    tls_listener::Error::TlsAcceptError { error, .. } => {
+                        telemetry::failure_reason(error)
+                    }
+                    tls_listener::Error::HandshakeTimeout { .. } => "HandshakeTimeout".into(),
+                    _ => "ListenerError".into(),
+                };
+                let peer = match err.peer_addr() {
+                    Some(addr) => format!("{:?}", addr),
+                    None => "-".into(),
+                };
+                eprintln!("tls_handshake_failed peer={} reason={}", peer, reason);
+                stats.record_failure(&reason);
                 continue;
             }
         };
+
+        // record handshake parameters
+        let early_data = session::read_early_data(&mut stream);
+        let tls = Arc::new(HandshakeInfo::new(
+            &stream,
+            duration,
+            !early_data.is_empty(),
+        ));
+        let remote_addr: Arc<str> = format!("{:?}", remote_addr).into();
+        println!("{}", tls.log_line(&remote_addr));
+        stats.record_handshake(&tls);
+        let stream = EarlyDataStream::new(early_data, stream);
+        let past_early_data = stream.past_early_data();
         let io = TokioIo::new(stream);
 
         // set service function
-        let service = move |req: hyper::Request<hyper::body::Incoming>| async move {
-            handle(remote_addr, req).await
+        let stats = stats.clone();
+        let service = move |req: hyper::Request<hyper::body::Incoming>| {
+            let remote_addr = remote_addr.clone();
+            let tls = tls.clone();
+            let stats = stats.clone();
+            let past_early_data = past_early_data.clone();
+            async move { handle(remote_addr, tls, stats, past_early_data, req).await }
         };
 
         // handle connection
@@ -210,9 +296,173 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    // get listeners from systemd socket activation and command line, before
+    // the runtime starts its threads
+    let mut listeners = listen::from_systemd()?;
+    for addr in &args.listen {
+        listeners.push(listen::bind(addr)?);
+    }
+    if listeners.is_empty() {
+        let addr = ListenAddr::Tcp(([127, 0, 0, 1], 3000).into());
+        listeners.push(listen::bind(&addr)?);
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(run(args, listeners))
+}
+
+/// serve connections on all listeners.
+async fn run(args: Args, listeners: Vec<Listener>) -> Result<(), Box<dyn std::error::Error>> {
+    let acceptor = TimedAcceptor::new(tls_acceptor(args.early_data));
+    let stats = Arc::new(Stats::default());
+    let mut tasks = Vec::new();
+    for listener in listeners {
+        println!("Serving HTTP on {}...", listener);
+        let acceptor = acceptor.clone();
+        let stats = stats.clone();
+        tasks.push(match listener {
+            Listener::Tcp(listener) => {
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                tokio::spawn(serve(TlsListener::new(acceptor, listener), stats))
+            }
+            Listener::Unix(listener) => {
+                let listener = tokio::net::UnixListener::from_std(listener)?;
+                tokio::spawn(serve(TlsListener::new(acceptor, listener), stats))
+            }
+        });
+    }
+    for task in tasks {
+        task.await?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, HandshakeKind, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    /// start server configured like tls_acceptor with a certificate for
+    /// localhost and return its address and a connector trusting it.
+    async fn start_server(early_data: bool) -> (std::net::SocketAddr, TlsConnector) {
+        let CertifiedKey { cert, signing_key } =
+            generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let key = PrivatePkcs8KeyDer::from(signing_key.serialize_der());
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.der().clone()], key.into())
+            .unwrap();
+        session::configure(&mut config, early_data).unwrap();
+
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let acceptor = TimedAcceptor::new(Arc::new(config).into());
+        let listener = TlsListener::new(acceptor, tcp);
+        tokio::spawn(serve(listener, Arc::new(Stats::default())));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.der().clone()).unwrap();
+        let mut config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.enable_early_data = early_data;
+        let connector = TlsConnector::from(Arc::new(config)).early_data(early_data);
+        (addr, connector)
+    }
+
+    /// send request on a new connection and return the handshake kind,
+    /// whether early data was accepted and the status line of the response.
+    async fn send(
+        addr: std::net::SocketAddr,
+        connector: &TlsConnector,
+        request: &str,
+    ) -> (HandshakeKind, bool, String) {
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, tcp).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+
+        // the session ticket is received with the response
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        let response = String::from_utf8_lossy(&response);
+        let status = response.lines().next().unwrap_or_default().to_string();
+        let (_, conn) = stream.get_ref();
+        (
+            conn.handshake_kind().unwrap(),
+            conn.is_early_data_accepted(),
+            status,
+        )
+    }
+
+    const GET: &str = "GET /_tls HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    const POST: &str = "POST /_tls HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\
+                        Connection: close\r\n\r\n";
+    const GET_KEEP_ALIVE: &str = "GET /_tls HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_resumption() {
+        let (addr, connector) = start_server(false).await;
+        for want in [HandshakeKind::Full, HandshakeKind::Resumed] {
+            let (kind, early_data, status) = send(addr, &connector, GET).await;
+            assert_eq!(kind, want);
+            assert!(!early_data);
+            assert_eq!(status, "HTTP/1.1 200 OK");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_early_data() {
+        let (addr, connector) = start_server(true).await;
+
+        // each session is used once, later requests are sent as early data
+        for (request, want_kind, want_early_data, want_status) in [
+            (GET, HandshakeKind::Full, false, "HTTP/1.1 200 OK"),
+            (GET, HandshakeKind::Resumed, true, "HTTP/1.1 200 OK"),
+            (POST, HandshakeKind::Resumed, true, "HTTP/1.1 425 Too Early"),
+        ] {
+            let (kind, early_data, status) = send(addr, &connector, request).await;
+            assert_eq!(
+                (kind, early_data, status.as_str()),
+                (want_kind, want_early_data, want_status),
+                "{}",
+                request
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_after_early_data() {
+        let (addr, connector) = start_server(true).await;
+        send(addr, &connector, GET).await;
+
+        // the get request is sent as early data, the post request after the
+        // handshake on the same connection
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, tcp).await.unwrap();
+        stream.write_all(GET_KEEP_ALIVE.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+        stream.write_all(POST.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("HTTP/1.1 400 Bad Request"));
+    }
 
     #[test]
     fn test_get_uri_path_parent() {
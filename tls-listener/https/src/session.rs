@@ -0,0 +1,170 @@
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::rustls::crypto::aws_lc_rs::Ticketer;
+use tokio_rustls::rustls::crypto::GetRandomFailed;
+use tokio_rustls::rustls::server::{ProducesTickets, ServerSessionMemoryCache};
+use tokio_rustls::rustls::{self, ServerConfig, TicketRotator};
+use tokio_rustls::server::TlsStream;
+
+/// lifetime of a session ticket key in seconds. tickets encrypted with a key
+/// are accepted for twice this duration.
+const TICKET_KEY_LIFETIME: u32 = 60 * 60;
+
+/// maximum number of sessions stored in the stateful session cache.
+const SESSION_CACHE_SIZE: usize = 1024;
+
+/// maximum amount of early data accepted from a client.
+const MAX_EARLY_DATA_SIZE: u32 = 16 * 1024;
+
+/// ticket producer that shares a ticketer created by the crypto provider.
+struct SharedTicketer(Arc<dyn ProducesTickets>);
+
+impl std::fmt::Debug for SharedTicketer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SharedTicketer").finish()
+    }
+}
+
+impl ProducesTickets for SharedTicketer {
+    fn enabled(&self) -> bool {
+        self.0.enabled()
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.0.lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.0.encrypt(plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.0.decrypt(cipher)
+    }
+}
+
+/// create a ticketer with new random keys.
+fn make_ticketer() -> Result<Box<dyn ProducesTickets>, GetRandomFailed> {
+    let ticketer = Ticketer::new().map_err(|_| GetRandomFailed)?;
+    Ok(Box::new(SharedTicketer(ticketer)))
+}
+
+/// configure session resumption and optionally early data in server config.
+///
+/// rustls only accepts early data with single-use stateful sessions, so the
+/// ticketer is only enabled if early data is disabled.
+pub fn configure(config: &mut ServerConfig, early_data: bool) -> Result<(), rustls::Error> {
+    config.session_storage = ServerSessionMemoryCache::new(SESSION_CACHE_SIZE);
+    if early_data {
+        config.max_early_data_size = MAX_EARLY_DATA_SIZE;
+    } else {
+        config.ticketer = Arc::new(TicketRotator::new(TICKET_KEY_LIFETIME, make_ticketer)?);
+    }
+    Ok(())
+}
+
+/// read early data received during the handshake from tls stream.
+pub fn read_early_data<C>(stream: &mut TlsStream<C>) -> Vec<u8> {
+    let mut data = Vec::new();
+    if let Some(mut early_data) = stream.get_mut().1.early_data()
+        && let Err(err) = early_data.read_to_end(&mut data)
+    {
+        eprintln!("Error reading early data: {}", err);
+    }
+    data
+}
+
+/// stream that returns early data before reading from the inner stream.
+pub struct EarlyDataStream<S> {
+    early_data: Vec<u8>,
+    pos: usize,
+    past_early_data: Arc<AtomicBool>,
+    inner: S,
+}
+
+impl<S> EarlyDataStream<S> {
+    pub fn new(early_data: Vec<u8>, inner: S) -> Self {
+        EarlyDataStream {
+            past_early_data: Arc::new(AtomicBool::new(early_data.is_empty())),
+            early_data,
+            pos: 0,
+            inner,
+        }
+    }
+
+    /// flag which is set once data after the early data was read. requests
+    /// parsed before are completely made of early data.
+    pub fn past_early_data(&self) -> Arc<AtomicBool> {
+        self.past_early_data.clone()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for EarlyDataStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos < this.early_data.len() {
+            let n = buf.remaining().min(this.early_data.len() - this.pos);
+            buf.put_slice(&this.early_data[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            this.past_early_data.store(true, Ordering::Release);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for EarlyDataStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_early_data_stream() {
+        for (early_data, inner, want) in [
+            ("", "", ""),
+            ("GET / HTTP/1.1\r\n", "", "GET / HTTP/1.1\r\n"),
+            ("", "\r\n", "\r\n"),
+            ("GET / HTTP/1.1\r\n", "\r\n", "GET / HTTP/1.1\r\n\r\n"),
+        ] {
+            let mut stream = EarlyDataStream::new(early_data.into(), inner.as_bytes());
+            let past_early_data = stream.past_early_data();
+            let mut got = String::new();
+            stream.read_to_string(&mut got).await.unwrap();
+            assert_eq!(got, want);
+            assert_eq!(
+                past_early_data.load(Ordering::Acquire),
+                early_data.is_empty() || !inner.is_empty()
+            );
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tls_listener::AsyncTls;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::{self, HandshakeKind};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// tls acceptor that measures the duration of each handshake.
+#[derive(Clone)]
+pub struct TimedAcceptor {
+    acceptor: TlsAcceptor,
+}
+
+impl TimedAcceptor {
+    pub fn new(acceptor: TlsAcceptor) -> Self {
+        TimedAcceptor { acceptor }
+    }
+}
+
+impl<C> AsyncTls<C> for TimedAcceptor
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = (TlsStream<C>, Duration);
+    type Error = io::Error;
+    type AcceptFuture = Pin<Box<dyn Future<Output = Result<Self::Stream, Self::Error>> + Send>>;
+
+    fn accept(&self, conn: C) -> Self::AcceptFuture {
+        let acceptor = self.acceptor.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let stream = acceptor.accept(conn).await?;
+            Ok((stream, start.elapsed()))
+        })
+    }
+}
+
+/// negotiated parameters of a tls handshake.
+#[derive(Debug, Clone)]
+pub struct HandshakeInfo {
+    pub version: String,
+    pub cipher_suite: String,
+    pub alpn: Option<String>,
+    pub sni: Option<String>,
+    pub resumed: bool,
+    pub early_data: bool,
+    pub duration: Duration,
+}
+
+impl HandshakeInfo {
+    pub fn new<C>(stream: &TlsStream<C>, duration: Duration, early_data: bool) -> Self {
+        let (_, conn) = stream.get_ref();
+        HandshakeInfo {
+            version: match conn.protocol_version() {
+                Some(version) => format!("{:?}", version),
+                None => "unknown".into(),
+            },
+            cipher_suite: match conn.negotiated_cipher_suite() {
+                Some(suite) => format!("{:?}", suite.suite()),
+                None => "unknown".into(),
+            },
+            alpn: conn
+                .alpn_protocol()
+                .map(|alpn| String::from_utf8_lossy(alpn).into_owned()),
+            sni: conn.server_name().map(String::from),
+            resumed: conn.handshake_kind() == Some(HandshakeKind::Resumed),
+            early_data,
+            duration,
+        }
+    }
+
+    /// get handshake info as a single key=value log line.
+    pub fn log_line(&self, remote_addr: &str) -> String {
+        format!(
+            "tls_handshake peer={} version={} cipher={} alpn={} sni={} resumed={} early_data={} duration_ms={:.3}",
+            remote_addr,
+            self.version,
+            self.cipher_suite,
+            self.alpn.as_deref().unwrap_or("-"),
+            self.sni.as_deref().unwrap_or("-"),
+            self.resumed,
+            self.early_data,
+            self.duration.as_secs_f64() * 1000.0,
+        )
+    }
+
+    /// get handshake info as html table rows.
+    pub fn html_rows(&self) -> String {
+        let mut html = String::new();
+        for (key, value) in [
+            ("Protocol version", self.version.clone()),
+            ("Cipher suite", self.cipher_suite.clone()),
+            ("ALPN", self.alpn.clone().unwrap_or("-".into())),
+            ("SNI", self.sni.clone().unwrap_or("-".into())),
+            ("Resumed", self.resumed.to_string()),
+            ("Early data", self.early_data.to_string()),
+            (
+                "Handshake duration",
+                format!("{:.3} ms", self.duration.as_secs_f64() * 1000.0),
+            ),
+        ] {
+            let _ = write!(html, "<tr><td>{}</td><td>{}</td></tr>", key, value);
+        }
+        html
+    }
+}
+
+/// get name of the enum variant of value from its debug representation,
+/// without the fields of the variant.
+fn variant(value: &impl fmt::Debug) -> String {
+    let debug = format!("{:?}", value);
+    debug
+        .split(['(', '{', ' '])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// get a short failure reason label from a tls accept error. labels only
+/// consist of variant names, so there is a fixed set of them.
+pub fn failure_reason(err: &io::Error) -> String {
+    let Some(err) = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<rustls::Error>())
+    else {
+        return format!("io:{}", variant(&err.kind()));
+    };
+    match err {
+        rustls::Error::AlertReceived(rustls::AlertDescription::Unknown(_)) => {
+            "AlertReceived(Unknown)".into()
+        }
+        rustls::Error::AlertReceived(alert) => format!("AlertReceived({})", variant(alert)),
+        rustls::Error::InvalidCertificate(err) => format!("InvalidCertificate({})", variant(err)),
+        rustls::Error::InvalidMessage(err) => format!("InvalidMessage({})", variant(err)),
+        rustls::Error::PeerIncompatible(err) => format!("PeerIncompatible({})", variant(err)),
+        rustls::Error::PeerMisbehaved(err) => format!("PeerMisbehaved({})", variant(err)),
+        err => variant(err),
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    handshakes: u64,
+    resumed: u64,
+    early_data: u64,
+    versions: BTreeMap<String, u64>,
+    cipher_suites: BTreeMap<String, u64>,
+    failures: BTreeMap<String, u64>,
+}
+
+/// handshake counters shared between connections.
+#[derive(Default)]
+pub struct Stats {
+    counters: Mutex<Counters>,
+}
+
+impl Stats {
+    pub fn record_handshake(&self, info: &HandshakeInfo) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.handshakes += 1;
+        if info.resumed {
+            counters.resumed += 1;
+        }
+        if info.early_data {
+            counters.early_data += 1;
+        }
+        *counters.versions.entry(info.version.clone()).or_default() += 1;
+        *counters
+            .cipher_suites
+            .entry(info.cipher_suite.clone())
+            .or_default() += 1;
+    }
+
+    pub fn record_failure(&self, reason: &str) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.failures.entry(reason.into()).or_default() += 1;
+    }
+
+    /// get counters as html table rows.
+    pub fn html_rows(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut html = format!(
+            "<tr><td>handshakes</td><td>{}</td></tr><tr><td>resumed</td><td>{}</td></tr>\
+            <tr><td>early data</td><td>{}</td></tr>",
+            counters.handshakes, counters.resumed, counters.early_data
+        );
+        for (prefix, map) in [
+            ("version", &counters.versions),
+            ("cipher", &counters.cipher_suites),
+            ("failure", &counters.failures),
+        ] {
+            for (key, count) in map {
+                let _ = write!(
+                    html,
+                    "<tr><td>{} {}</td><td>{}</td></tr>",
+                    prefix, key, count
+                );
+            }
+        }
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{AlertDescription, CertificateError, OtherError, PeerIncompatible};
+
+    #[test]
+    fn test_failure_reason() {
+        for (err, want) in [
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::AlertReceived(AlertDescription::UnknownCA),
+                ),
+                "AlertReceived(UnknownCA)",
+            ),
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::PeerIncompatible(PeerIncompatible::NoCipherSuitesInCommon),
+                ),
+                "PeerIncompatible(NoCipherSuitesInCommon)",
+            ),
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::General("some text".into()),
+                ),
+                "General",
+            ),
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::Other(OtherError(Arc::new(io::Error::other("some text")))),
+                ),
+                "Other",
+            ),
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(
+                        Arc::new(io::Error::other("some text")),
+                    ))),
+                ),
+                "InvalidCertificate(Other)",
+            ),
+            (
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    rustls::Error::AlertReceived(AlertDescription::Unknown(200)),
+                ),
+                "AlertReceived(Unknown)",
+            ),
+            (
+                io::Error::from(io::ErrorKind::UnexpectedEof),
+                "io:UnexpectedEof",
+            ),
+        ] {
+            assert_eq!(failure_reason(&err), want);
+        }
+    }
+}